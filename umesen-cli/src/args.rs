@@ -0,0 +1,180 @@
+use std::path::PathBuf;
+
+use umesen_core::{Region, power_on::RamState};
+
+pub const USAGE: &str = "\
+Usage: umesen-cli <ROM> [OPTIONS]
+
+Runs a NES ROM or NSF without a display and prints a hash of the final frame and all generated audio
+
+Options:
+  --frames <N>             Number of frames to run, defaults to the movie length or 60
+  --movie <FILE>           FM2 movie to play back
+  --fds-bios <FILE>        FDS BIOS ROM, required when the ROM is a .fds disk image
+  --nsf-track <N>          Track to play when the ROM is a .nsf or .nsfe file, starting from 1
+  --region <REGION>        Force ntsc, pal or dendy timing instead of the one in the header
+  --power-on-ram <STATE>   Fill RAM at power on with zeros, ones, pattern or random
+  --seed <N>               Seed for --power-on-ram random, defaults to 0
+  --png <FILE>             Write the final frame to a PNG
+  --expect-frame <CRC32>   Exit with an error if the frame hash is different
+  --expect-audio <CRC32>   Exit with an error if the audio hash is different
+  --test-rom               Run until the ROM reports a result with the $6000 test ROM protocol,
+                           --frames is the maximum number of frames to wait
+  -h, --help               Print this message";
+
+#[derive(Default, Debug)]
+pub struct Args {
+    pub rom: PathBuf,
+    pub frames: Option<u32>,
+    pub movie: Option<PathBuf>,
+    pub fds_bios: Option<PathBuf>,
+    pub nsf_track: Option<u8>,
+    pub region: Option<Region>,
+    pub power_on_ram: Option<RamState>,
+    pub seed: Option<u64>,
+    pub png: Option<PathBuf>,
+    pub expect_frame: Option<u32>,
+    pub expect_audio: Option<u32>,
+    pub test_rom: bool,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ArgsError {
+    #[error("Missing ROM path")]
+    MissingRom,
+    #[error("Missing value for '{0}'")]
+    MissingValue(String),
+    #[error("Invalid value '{1}' for '{0}'")]
+    InvalidValue(String, String),
+    #[error("Unknown argument '{0}'")]
+    Unknown(String),
+    #[error("Help requested")]
+    Help,
+}
+
+impl Args {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, ArgsError> {
+        let mut parsed = Args::default();
+        let mut rom = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(ArgsError::MissingValue(arg.clone()));
+            match arg.as_str() {
+                "-h" | "--help" => return Err(ArgsError::Help),
+                "--frames" => {
+                    let frames = value()?;
+                    parsed.frames = Some(
+                        frames
+                            .parse()
+                            .map_err(|_| ArgsError::InvalidValue(arg, frames))?,
+                    );
+                }
+                "--movie" => parsed.movie = Some(value()?.into()),
+                "--fds-bios" => parsed.fds_bios = Some(value()?.into()),
+                "--nsf-track" => {
+                    let track = value()?;
+                    parsed.nsf_track = Some(
+                        track
+                            .parse()
+                            .ok()
+                            .filter(|track| *track != 0)
+                            .ok_or(ArgsError::InvalidValue(arg, track))?,
+                    );
+                }
+                "--region" => {
+                    let region = value()?;
+                    parsed.region = Some(
+                        Region::ALL
+                            .into_iter()
+                            .find(|r| r.name().eq_ignore_ascii_case(&region))
+                            .ok_or(ArgsError::InvalidValue(arg, region))?,
+                    );
+                }
+                "--power-on-ram" => {
+                    let state = value()?;
+                    parsed.power_on_ram = Some(match state.as_str() {
+                        "zeros" => RamState::Zeros,
+                        "ones" => RamState::Ones,
+                        "pattern" => RamState::Pattern,
+                        "random" => RamState::Random,
+                        _ => return Err(ArgsError::InvalidValue(arg, state)),
+                    });
+                }
+                "--seed" => {
+                    let seed = value()?;
+                    parsed.seed = Some(
+                        seed.parse()
+                            .map_err(|_| ArgsError::InvalidValue(arg, seed))?,
+                    );
+                }
+                "--png" => parsed.png = Some(value()?.into()),
+                "--expect-frame" => parsed.expect_frame = Some(parse_crc(&arg, value()?)?),
+                "--test-rom" => parsed.test_rom = true,
+                "--expect-audio" => parsed.expect_audio = Some(parse_crc(&arg, value()?)?),
+                _ if arg.starts_with('-') || rom.is_some() => return Err(ArgsError::Unknown(arg)),
+                _ => rom = Some(arg.into()),
+            }
+        }
+
+        parsed.rom = rom.ok_or(ArgsError::MissingRom)?;
+        Ok(parsed)
+    }
+}
+
+fn parse_crc(arg: &str, value: String) -> Result<u32, ArgsError> {
+    u32::from_str_radix(value.trim_start_matches("0x"), 16)
+        .map_err(|_| ArgsError::InvalidValue(arg.to_owned(), value))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Args, ArgsError> {
+        Args::parse(args.split_whitespace().map(str::to_owned))
+    }
+
+    #[test]
+    fn parse_args() {
+        let args = parse("game.nes --frames 120 --png out.png --expect-frame 0xdeadbeef").unwrap();
+        assert!(!args.test_rom);
+        assert_eq!(args.rom, PathBuf::from("game.nes"));
+        assert_eq!(args.frames, Some(120));
+        assert_eq!(args.png, Some("out.png".into()));
+        assert_eq!(args.expect_frame, Some(0xdeadbeef));
+        assert_eq!(args.movie, None);
+        assert_eq!(args.fds_bios, None);
+
+        let args = parse("game.fds --fds-bios disksys.rom").unwrap();
+        assert_eq!(args.fds_bios, Some("disksys.rom".into()));
+
+        let args = parse("music.nsf --nsf-track 3").unwrap();
+        assert_eq!(args.nsf_track, Some(3));
+        assert!(matches!(
+            parse("music.nsf --nsf-track 0"),
+            Err(ArgsError::InvalidValue(..))
+        ));
+
+        let args = parse("game.nes --region pal").unwrap();
+        assert_eq!(args.region, Some(Region::Pal));
+        assert!(matches!(
+            parse("game.nes --region secam"),
+            Err(ArgsError::InvalidValue(..))
+        ));
+
+        let args = parse("game.nes --power-on-ram random --seed 7").unwrap();
+        assert_eq!(args.power_on_ram, Some(RamState::Random));
+        assert_eq!(args.seed, Some(7));
+
+        assert!(matches!(parse("--frames 1"), Err(ArgsError::MissingRom)));
+        assert!(matches!(
+            parse("a.nes --frames"),
+            Err(ArgsError::MissingValue(_))
+        ));
+        assert!(matches!(
+            parse("a.nes --frames x"),
+            Err(ArgsError::InvalidValue(..))
+        ));
+        assert!(matches!(parse("a.nes b.nes"), Err(ArgsError::Unknown(_))));
+    }
+}
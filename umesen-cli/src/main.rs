@@ -0,0 +1,153 @@
+mod args;
+
+use std::process::ExitCode;
+
+use ringbuf::traits::Consumer;
+use umesen_core::{
+    Emulator, SaveStateError,
+    cartridge::NesParseError,
+    cpu::CpuError,
+    movie::{Movie, MovieError},
+    power_on::PowerOnConfig,
+    ppu::{HEIGHT, WIDTH},
+    test_rom::{TestRomStatus, run_test_rom},
+};
+
+use crate::args::{Args, ArgsError, USAGE};
+
+const DEFAULT_FRAMES: u32 = 60;
+const DEFAULT_TEST_ROM_FRAMES: u32 = 60 * 60;
+const AUDIO_SAMPLE_RATE: u32 = 44100;
+
+#[derive(thiserror::Error, Debug)]
+enum CliError {
+    #[error(transparent)]
+    Args(#[from] ArgsError),
+    #[error("Failed to load ROM: {0}")]
+    Rom(#[from] NesParseError),
+    #[error("Failed to load movie: {0}")]
+    Movie(#[from] MovieError),
+    #[error("Failed to load movie save state: {0}")]
+    MovieState(#[from] SaveStateError),
+    #[error("CPU halted: {0}")]
+    Cpu(#[from] CpuError),
+    #[error("Failed to write PNG: {0}")]
+    Png(#[from] png::EncodingError),
+    #[error("Test ROM did not pass: {0:?}")]
+    TestRom(TestRomStatus),
+    #[error("{0} hash {1:08x} does not match the expected {2:08x}")]
+    HashMismatch(&'static str, u32, u32),
+}
+
+fn main() -> ExitCode {
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Warn)
+        .parse_default_env()
+        .init();
+
+    match Args::parse(std::env::args().skip(1))
+        .map_err(CliError::from)
+        .and_then(run)
+    {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(CliError::Args(ArgsError::Help)) => {
+            println!("{USAGE}");
+            ExitCode::SUCCESS
+        }
+        Err(err @ CliError::Args(_)) => {
+            eprintln!("{err}\n\n{USAGE}");
+            ExitCode::FAILURE
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), CliError> {
+    let mut emu = Emulator::default();
+    emu.rewind.config.enabled = false;
+    emu.set_region_override(args.region);
+    emu.power_on = PowerOnConfig {
+        ram_state: args.power_on_ram.unwrap_or_default(),
+        seed: args.seed.unwrap_or_default(),
+    };
+    let is_nsf = args
+        .rom
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("nsf") || ext.eq_ignore_ascii_case("nsfe"));
+    match &args.fds_bios {
+        Some(bios_path) => emu.load_fds_file(&args.rom, bios_path)?,
+        None if is_nsf => emu.load_nsf_file(&args.rom)?,
+        None => emu.load_nes_file(&args.rom)?,
+    }
+    if let Some(track) = args.nsf_track {
+        emu.play_nsf_track(track - 1);
+    }
+    if args.test_rom {
+        let result = run_test_rom(&mut emu, args.frames.unwrap_or(DEFAULT_TEST_ROM_FRAMES))?;
+        println!("{}", result.text);
+        println!("status: {:?} after {} frames", result.status, result.frames);
+        return match result.status {
+            TestRomStatus::Passed => Ok(()),
+            status => Err(CliError::TestRom(status)),
+        };
+    }
+
+    let mut audio = emu.setup_audio_buffer(AUDIO_SAMPLE_RATE, std::time::Duration::from_secs(1));
+
+    let mut frames = args.frames.unwrap_or(DEFAULT_FRAMES);
+    if let Some(path) = &args.movie {
+        let movie = Movie::load_fm2_file(path)?;
+        frames = args.frames.unwrap_or(movie.frames.len() as u32);
+        emu.play_movie(movie)?;
+    }
+
+    let mut audio_hasher = crc32fast::Hasher::new();
+    for _ in 0..frames {
+        emu.next_frame()?;
+        for sample in audio.pop_iter() {
+            audio_hasher.update(&sample.to_le_bytes());
+        }
+    }
+
+    let rgb: Vec<u8> = emu
+        .ppu()
+        .screen_pixels
+        .iter()
+        .flat_map(|pixel| pixel.iter().copied())
+        .collect();
+    let frame_crc = crc32fast::hash(&rgb);
+    let audio_crc = audio_hasher.finalize();
+    println!("frames: {frames}");
+    println!("frame_crc32: {frame_crc:08x}");
+    println!("audio_crc32: {audio_crc:08x}");
+
+    if let Some(path) = &args.png {
+        write_png(path, &rgb)?;
+    }
+
+    if let Some(expected) = args.expect_frame
+        && expected != frame_crc
+    {
+        return Err(CliError::HashMismatch("Frame", frame_crc, expected));
+    }
+    if let Some(expected) = args.expect_audio
+        && expected != audio_crc
+    {
+        return Err(CliError::HashMismatch("Audio", audio_crc, expected));
+    }
+    Ok(())
+}
+
+fn write_png(path: &std::path::Path, rgb: &[u8]) -> Result<(), CliError> {
+    let file = std::fs::File::create(path).map_err(png::EncodingError::from)?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), WIDTH as u32, HEIGHT as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgb)?;
+    writer.finish()?;
+    Ok(())
+}
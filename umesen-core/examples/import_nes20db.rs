@@ -0,0 +1,26 @@
+//! Prints the embedded game database with its entries replaced by the games in the
+//! NES 2.0 XML database
+//! `cargo run -p umesen-core --example import_nes20db -- nes20db.xml > game_database.txt`
+
+fn main() {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("Usage: import_nes20db <nes20db.xml>");
+        std::process::exit(1);
+    };
+    let xml = match std::fs::read_to_string(&path) {
+        Ok(xml) => xml,
+        Err(err) => {
+            eprintln!("Failed to read {path}: {err}");
+            std::process::exit(1);
+        }
+    };
+
+    // Keep the comments describing the format
+    let header = include_str!("../src/cartridge/game_database.txt")
+        .lines()
+        .take_while(|line| line.is_empty() || line.starts_with('#'));
+    for line in header {
+        println!("{line}");
+    }
+    print!("{}", umesen_core::cartridge::convert_nes20db(&xml));
+}
@@ -1,12 +1,15 @@
-use crate::{apu::counters::TimerCounter, cpu::IrqStatus};
+use crate::{apu::counters::TimerCounter, cpu::IrqStatus, region::Region};
 
 /// Rates from https://www.nesdev.org/wiki/APU_DMC
 /// Note this is halfed since it is in APU cycles
 pub const DMC_RATES: [u16; 16] = [
     214, 190, 170, 160, 143, 127, 113, 107, 95, 80, 71, 64, 53, 42, 36, 27,
 ];
+pub const PAL_DMC_RATES: [u16; 16] = [
+    199, 177, 158, 149, 138, 118, 105, 99, 88, 74, 66, 59, 49, 39, 33, 25,
+];
 
-#[derive(Default)]
+#[derive(Default, serde::Serialize, serde::Deserialize)]
 pub struct DmcChannel {
     timer: TimerCounter<u16>,
     output_level: u8,
@@ -23,10 +26,14 @@ pub struct DmcChannel {
 }
 
 impl DmcChannel {
-    pub fn write(&mut self, address: u16, value: u8) {
+    pub fn write(&mut self, address: u16, value: u8, region: Region) {
         match address {
             0x4010 => {
-                self.timer.start = DMC_RATES[(value & 0x0f) as usize];
+                let rates = match region {
+                    Region::Pal => &PAL_DMC_RATES,
+                    Region::Ntsc | Region::Dendy => &DMC_RATES,
+                };
+                self.timer.start = rates[(value & 0x0f) as usize];
                 self.looping = value & 0b0100_0000 != 0;
                 self.irq.set_enabled(value & 0b1000_0000 != 0);
             }
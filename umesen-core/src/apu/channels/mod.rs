@@ -1,13 +1,16 @@
-use crate::apu::ApuConfig;
+use crate::{
+    apu::{ApuConfig, ExpansionSamples},
+    region::Region,
+};
 
 use super::{Status, counters::FrameCounterState};
 
 mod dmc;
 mod noise;
-mod pulse;
+pub(crate) mod pulse;
 mod triangle;
 
-#[derive(Default)]
+#[derive(Default, serde::Serialize, serde::Deserialize)]
 pub struct Channels {
     pulse_0: pulse::PulseChannel<0>,
     pulse_1: pulse::PulseChannel<1>,
@@ -17,13 +20,13 @@ pub struct Channels {
 }
 
 impl Channels {
-    pub fn write(&mut self, address: u16, value: u8) {
+    pub fn write(&mut self, address: u16, value: u8, region: Region) {
         std::debug_assert_matches!(address, 0x4000..=0x4013);
         self.pulse_0.write(address, value, 0);
         self.pulse_1.write(address, value, 1);
         self.triangle.write(address, value);
-        self.noise.write(address, value);
-        self.dmc.write(address, value);
+        self.noise.write(address, value, region);
+        self.dmc.write(address, value, region);
     }
 
     pub fn clock(&mut self, cpu_cycles: u64) {
@@ -83,7 +86,7 @@ impl Channels {
         status
     }
 
-    pub fn sample(&self, config: &ApuConfig) -> f32 {
+    pub fn sample(&self, config: &ApuConfig, expansion: ExpansionSamples) -> f32 {
         let pulse_0 = self.pulse_0.sample() as f32 * config.pulse_0_volume;
         let pulse_1 = self.pulse_1.sample() as f32 * config.pulse_1_volume;
         let noise = self.noise.sample() as f32 * config.noise_volume;
@@ -95,6 +98,11 @@ impl Channels {
         let tnd = triangle / 8227. + noise / 12241. + dmc / 22638.;
         let pulse_out = (95.88 * pulse) / (8128. + 100. * pulse);
         let tnd_out = (159.79 * tnd) / (1. + 100. * tnd);
-        (tnd_out + pulse_out) * config.volume
+        let expansion_out: f32 = expansion
+            .iter()
+            .flatten()
+            .map(|(chip, sample)| sample * config.expansion_volume(*chip))
+            .sum();
+        (tnd_out + pulse_out + expansion_out) * config.volume
     }
 }
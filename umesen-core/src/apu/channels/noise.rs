@@ -1,6 +1,9 @@
-use crate::apu::{
-    counters::{LengthCounter, TimerCounter},
-    envelope::Envelope,
+use crate::{
+    apu::{
+        counters::{LengthCounter, TimerCounter},
+        envelope::Envelope,
+    },
+    region::Region,
 };
 
 /// Period values from https://www.nesdev.org/wiki/APU_Noise
@@ -8,8 +11,11 @@ use crate::apu::{
 const NOISE_PERIODS: [u16; 16] = [
     2, 4, 8, 16, 32, 48, 64, 80, 101, 127, 190, 254, 381, 508, 1017, 2034,
 ];
+const PAL_NOISE_PERIODS: [u16; 16] = [
+    2, 4, 7, 15, 30, 44, 59, 74, 94, 118, 177, 236, 354, 472, 945, 1889,
+];
 
-#[derive(Default)]
+#[derive(Default, serde::Serialize, serde::Deserialize)]
 pub struct NoiseChannel {
     pub timer: TimerCounter<u16>,
     pub length_counter: LengthCounter,
@@ -19,7 +25,7 @@ pub struct NoiseChannel {
 }
 
 impl NoiseChannel {
-    pub fn write(&mut self, address: u16, value: u8) {
+    pub fn write(&mut self, address: u16, value: u8, region: Region) {
         match address {
             0x400c => {
                 self.envelope.write(value);
@@ -28,7 +34,11 @@ impl NoiseChannel {
             0x400d => (),
             0x400e => {
                 self.mode_flag = value & 0b1000_0000 != 0;
-                self.timer.start = NOISE_PERIODS[(value & 0x0f) as usize];
+                let periods = match region {
+                    Region::Pal => &PAL_NOISE_PERIODS,
+                    Region::Ntsc | Region::Dendy => &NOISE_PERIODS,
+                };
+                self.timer.start = periods[(value & 0x0f) as usize];
             }
             0x400f => {
                 self.envelope.start();
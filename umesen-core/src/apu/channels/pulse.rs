@@ -15,8 +15,11 @@ const PULSE_WAVEFORM: [[u8; 8]; 4] = [
 ];
 
 /// Generator for pulse/square wave
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct PulseChannel<const NUMBER: u16> {
     pub sequencer: Sequencer,
+    /// Index into PULSE_WAVEFORM
+    duty: usize,
     pub envelope: Envelope,
     pub length_counter: LengthCounter,
     pub sweep: Sweep<NUMBER>,
@@ -25,7 +28,8 @@ pub struct PulseChannel<const NUMBER: u16> {
 impl<const NUMBER: u16> Default for PulseChannel<NUMBER> {
     fn default() -> Self {
         Self {
-            sequencer: Sequencer::new(&PULSE_WAVEFORM[0]),
+            sequencer: Sequencer::new(PULSE_WAVEFORM[0].len()),
+            duty: 0,
             envelope: Envelope::default(),
             length_counter: LengthCounter::default(),
             sweep: Sweep::default(),
@@ -38,7 +42,7 @@ impl<const NUMBER: u16> PulseChannel<NUMBER> {
         match address - number * 4 {
             // 0x4000 or 0x4004 if number == 1 etc
             0x4000 => {
-                self.sequencer.sequence = &PULSE_WAVEFORM[(value >> 6) as usize];
+                self.duty = (value >> 6) as usize;
                 self.envelope.write(value);
                 self.length_counter.halt = value & 0b0010_0000 != 0;
             }
@@ -56,14 +60,14 @@ impl<const NUMBER: u16> PulseChannel<NUMBER> {
 
     pub fn sample(&self) -> u8 {
         if self.length_counter.playing() && !self.sweep.muted(&self.sequencer) {
-            self.sequencer.sample() * self.envelope.volume()
+            self.sequencer.sample(&PULSE_WAVEFORM[self.duty]) * self.envelope.volume()
         } else {
             0
         }
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Sweep<const NUMBER: u16> {
     enabled: bool,
     timer: TimerCounter<u8>,
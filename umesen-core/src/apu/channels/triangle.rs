@@ -5,6 +5,7 @@ const TRIANGLE_WAVEFORM: [u8; 32] = [
     0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
 ];
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct TriangleChannel {
     pub length_counter: LengthCounter,
     pub linear_counter: u8,
@@ -20,7 +21,7 @@ impl Default for TriangleChannel {
             linear_counter_reload: true,
             linear_counter_reload_value: 0,
             length_counter: LengthCounter::default(),
-            sequencer: Sequencer::new(&TRIANGLE_WAVEFORM),
+            sequencer: Sequencer::new(TRIANGLE_WAVEFORM.len()),
         }
     }
 }
@@ -66,6 +67,6 @@ impl TriangleChannel {
     }
 
     pub fn sample(&self) -> u8 {
-        self.sequencer.sample()
+        self.sequencer.sample(&TRIANGLE_WAVEFORM)
     }
 }
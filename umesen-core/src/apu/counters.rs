@@ -1,4 +1,4 @@
-use crate::cpu::IrqStatus;
+use crate::{cpu::IrqStatus, region::Region};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FrameCounterState {
@@ -7,7 +7,7 @@ pub enum FrameCounterState {
     None,
 }
 
-#[derive(Default)]
+#[derive(Default, serde::Serialize, serde::Deserialize)]
 pub struct FrameCounter {
     cycles_counter: i32,
     five_step_mode: bool,
@@ -29,27 +29,31 @@ impl FrameCounter {
     }
 
     /// Ran on every cpu cycle
-    pub fn clock(&mut self) -> FrameCounterState {
+    pub fn clock(&mut self, region: Region) -> FrameCounterState {
         let mut state = FrameCounterState::None;
         // Sequence from https://www.nesdev.org/wiki/APU_Frame_Counter
         // Note that this is in CPU cycles and includes one cycle delay
+        let [quarter, half, three_quarters, four_step_end, five_step_end] = match region {
+            Region::Pal => [8313, 16627, 24939, 33252, 41565],
+            Region::Ntsc | Region::Dendy => [7457, 14913, 22371, 29828, 37281],
+        };
         match self.cycles_counter {
-            7457 => state = FrameCounterState::Quarter,
-            14913 => state = FrameCounterState::Half,
-            22371 => state = FrameCounterState::Quarter,
+            c if c == quarter => state = FrameCounterState::Quarter,
+            c if c == half => state = FrameCounterState::Half,
+            c if c == three_quarters => state = FrameCounterState::Quarter,
 
-            29828 if !self.five_step_mode => self.irq.on(),
-            29829 if !self.five_step_mode => {
+            c if c == four_step_end && !self.five_step_mode => self.irq.on(),
+            c if c == four_step_end + 1 && !self.five_step_mode => {
                 self.irq.on();
                 state = FrameCounterState::Half
             }
-            29830 if !self.five_step_mode => {
+            c if c == four_step_end + 2 && !self.five_step_mode => {
                 // This is meant to be cycle 0 so we skip the first cycle for the next loop
                 self.cycles_counter = 1;
                 self.irq.on();
             }
 
-            37281 if self.five_step_mode => {
+            c if c == five_step_end && self.five_step_mode => {
                 self.cycles_counter = 0;
                 state = FrameCounterState::Half
             }
@@ -66,7 +70,7 @@ const LENGTH_COUNTER_VALUES: [u8; 32] = [
     12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
 ];
 
-#[derive(Default)]
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
 pub struct LengthCounter {
     pub halt: bool,
     counter: u8,
@@ -99,7 +103,7 @@ impl LengthCounter {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
 pub struct TimerCounter<T> {
     pub start: T,
     pub counter: T,
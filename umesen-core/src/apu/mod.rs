@@ -1,9 +1,11 @@
 use ringbuf::traits::Producer;
 
+use crate::region::Region;
+
 use channels::Channels;
 use counters::FrameCounter;
 
-mod channels;
+pub(crate) mod channels;
 mod counters;
 mod envelope;
 mod sequencer;
@@ -21,6 +23,22 @@ bitflags::bitflags! {
     }
 }
 
+/// Sound chips inside cartridges that add extra audio channels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpansionChip {
+    Vrc6,
+    Vrc7,
+    Namco163,
+    Sunsoft5b,
+    Fds,
+    Mmc5,
+}
+
+/// Output of a cartridge's sound chip, already mixed to the same scale as the APU channels
+pub type ExpansionSample = (ExpansionChip, f32);
+/// Outputs of every sound chip in a cartridge, only NSF files can use more than one
+pub type ExpansionSamples = [Option<ExpansionSample>; 6];
+
 #[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq, Debug)]
 #[serde(default)]
 pub struct ApuConfig {
@@ -30,6 +48,25 @@ pub struct ApuConfig {
     pub pulse_1_volume: f32,
     pub noise_volume: f32,
     pub dmc_volume: f32,
+    pub vrc6_volume: f32,
+    pub vrc7_volume: f32,
+    pub namco163_volume: f32,
+    pub sunsoft5b_volume: f32,
+    pub fds_volume: f32,
+    pub mmc5_volume: f32,
+}
+
+impl ApuConfig {
+    pub fn expansion_volume(&self, chip: ExpansionChip) -> f32 {
+        match chip {
+            ExpansionChip::Vrc6 => self.vrc6_volume,
+            ExpansionChip::Vrc7 => self.vrc7_volume,
+            ExpansionChip::Namco163 => self.namco163_volume,
+            ExpansionChip::Sunsoft5b => self.sunsoft5b_volume,
+            ExpansionChip::Fds => self.fds_volume,
+            ExpansionChip::Mmc5 => self.mmc5_volume,
+        }
+    }
 }
 
 impl Default for ApuConfig {
@@ -41,18 +78,28 @@ impl Default for ApuConfig {
             pulse_1_volume: 1.,
             noise_volume: 1.,
             dmc_volume: 1.,
+            vrc6_volume: 1.,
+            vrc7_volume: 1.,
+            namco163_volume: 1.,
+            sunsoft5b_volume: 1.,
+            fds_volume: 1.,
+            mmc5_volume: 1.,
         }
     }
 }
 
-/// Emulated RP2A03 NTSC APU
-#[derive(Default)]
+/// Emulated RP2A03 APU, or the RP2A07 with the PAL timings
+#[derive(Default, serde::Serialize, serde::Deserialize)]
 pub struct Apu {
+    #[serde(skip)]
     pub config: ApuConfig,
     pub(crate) channels: Channels,
     frame_counter: FrameCounter,
+    pub(crate) region: Region,
 
+    #[serde(skip)]
     pub(crate) sample_rate: f32,
+    #[serde(skip)]
     pub(crate) buffer_prod: Option<ringbuf::HeapProd<f32>>,
     high_pass: OnePoleFilter<true>,
     cycles_since_sample: f32,
@@ -62,7 +109,7 @@ impl Apu {
     pub fn write(&mut self, address: u16, value: u8) {
         std::debug_assert_matches!(address, 0x4000..=0x4017);
         match address {
-            0x4000..=0x4013 => self.channels.write(address, value),
+            0x4000..=0x4013 => self.channels.write(address, value, self.region),
             0x4015 => self.channels.set_enabled(Status::from_bits_truncate(value)),
             0x4017 => {
                 let state = self.frame_counter.write(value);
@@ -79,20 +126,21 @@ impl Apu {
     }
 
     /// Ran on every CPU cycle
-    pub fn clock(&mut self, cpu_cycles: u64) {
+    /// expansion_audio gets the output of the audio channels inside the cartridge
+    pub fn clock(&mut self, cpu_cycles: u64, expansion_audio: impl Fn() -> ExpansionSamples) {
         self.channels.clock(cpu_cycles);
 
-        let state = self.frame_counter.clock();
+        let state = self.frame_counter.clock(self.region);
         self.channels.handle_frame_state(state);
 
         if let Some(buffer) = self.buffer_prod.as_mut() {
             while self.cycles_since_sample > 0. {
-                let mut sample = self.channels.sample(&self.config);
+                let mut sample = self.channels.sample(&self.config, expansion_audio());
                 // Include low freq high pass filter to get rid of DC bias
                 sample = self.high_pass.process(sample, self.sample_rate, 20.);
 
                 buffer.try_push(sample).ok();
-                self.cycles_since_sample -= crate::cpu::CLOCK_SPEED_HZ / self.sample_rate;
+                self.cycles_since_sample -= self.region.cpu_clock_hz() / self.sample_rate;
             }
             self.cycles_since_sample += 1.;
         }
@@ -107,7 +155,7 @@ impl Apu {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, serde::Serialize, serde::Deserialize)]
 struct OnePoleFilter<const HIGH_PASS: bool> {
     prev_out: f32,
     prev_in: f32,
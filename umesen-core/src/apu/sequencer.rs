@@ -1,30 +1,33 @@
 use super::counters::TimerCounter;
 
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct Sequencer {
     /// 11 bit number for the sequencer to go to the next step
     pub timer: TimerCounter<u16>,
     /// 11 bit number for the timer start
     pub step: usize,
-    pub sequence: &'static [u8],
+    /// Number of steps in the sequence before looping back
+    length: usize,
 }
 
 impl Sequencer {
-    pub fn new(sequence: &'static [u8]) -> Self {
+    pub fn new(length: usize) -> Self {
         Self {
-            sequence,
+            length,
             step: 0,
             timer: TimerCounter::default(),
         }
     }
 
-    pub fn sample(&self) -> u8 {
-        self.sequence[self.step]
+    /// Get the value in the sequence at the current step
+    pub fn sample(&self, sequence: &[u8]) -> u8 {
+        sequence[self.step]
     }
 
     pub fn clock(&mut self) {
         if self.timer.clock() {
             self.step += 1;
-            if self.step >= self.sequence.len() {
+            if self.step >= self.length {
                 self.step = 0;
             }
         }
@@ -0,0 +1,340 @@
+//! Expressions that decide if a breakpoint should break, like `A == $10 && X > 3`
+
+/// Values an expression can read when the breakpoint is checked
+#[derive(Default, Debug, Clone, Copy)]
+pub struct ConditionContext {
+    pub registers: super::CpuRegisters,
+    /// Address that was accessed
+    pub address: u16,
+    /// Value that was read or written, or the opcode for execute breakpoints
+    pub value: u8,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ConditionError {
+    #[error("Unexpected '{0}'")]
+    Unexpected(String),
+    #[error("Unknown variable '{0}'")]
+    UnknownVariable(String),
+    #[error("Invalid number '{0}'")]
+    InvalidNumber(String),
+    #[error("Condition ended unexpectedly")]
+    UnexpectedEnd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    A,
+    X,
+    Y,
+    Sp,
+    Pc,
+    Flags,
+    Address,
+    Value,
+}
+
+impl Variable {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "a" => Variable::A,
+            "x" => Variable::X,
+            "y" => Variable::Y,
+            "sp" => Variable::Sp,
+            "pc" => Variable::Pc,
+            "p" => Variable::Flags,
+            "address" => Variable::Address,
+            "value" => Variable::Value,
+            _ => return None,
+        })
+    }
+
+    fn get(self, context: &ConditionContext) -> i32 {
+        let registers = &context.registers;
+        match self {
+            Variable::A => registers.a as i32,
+            Variable::X => registers.x as i32,
+            Variable::Y => registers.y as i32,
+            Variable::Sp => registers.sp as i32,
+            Variable::Pc => registers.pc as i32,
+            Variable::Flags => registers.flags as i32,
+            Variable::Address => context.address as i32,
+            Variable::Value => context.value as i32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Add,
+    Sub,
+}
+
+impl BinaryOp {
+    fn from_token(token: &str) -> Option<Self> {
+        Some(match token {
+            "||" => BinaryOp::Or,
+            "&&" => BinaryOp::And,
+            "|" => BinaryOp::BitOr,
+            "^" => BinaryOp::BitXor,
+            "&" => BinaryOp::BitAnd,
+            "==" => BinaryOp::Equal,
+            "!=" => BinaryOp::NotEqual,
+            "<" => BinaryOp::Less,
+            "<=" => BinaryOp::LessEqual,
+            ">" => BinaryOp::Greater,
+            ">=" => BinaryOp::GreaterEqual,
+            "+" => BinaryOp::Add,
+            "-" => BinaryOp::Sub,
+            _ => return None,
+        })
+    }
+
+    /// Higher binds tighter, same as C
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::BitOr => 3,
+            BinaryOp::BitXor => 4,
+            BinaryOp::BitAnd => 5,
+            BinaryOp::Equal | BinaryOp::NotEqual => 6,
+            BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual => 7,
+            BinaryOp::Add | BinaryOp::Sub => 8,
+        }
+    }
+
+    fn apply(self, lhs: i32, rhs: i32) -> i32 {
+        match self {
+            BinaryOp::Or => (lhs != 0 || rhs != 0) as i32,
+            BinaryOp::And => (lhs != 0 && rhs != 0) as i32,
+            BinaryOp::BitOr => lhs | rhs,
+            BinaryOp::BitXor => lhs ^ rhs,
+            BinaryOp::BitAnd => lhs & rhs,
+            BinaryOp::Equal => (lhs == rhs) as i32,
+            BinaryOp::NotEqual => (lhs != rhs) as i32,
+            BinaryOp::Less => (lhs < rhs) as i32,
+            BinaryOp::LessEqual => (lhs <= rhs) as i32,
+            BinaryOp::Greater => (lhs > rhs) as i32,
+            BinaryOp::GreaterEqual => (lhs >= rhs) as i32,
+            BinaryOp::Add => lhs.wrapping_add(rhs),
+            BinaryOp::Sub => lhs.wrapping_sub(rhs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Number(i32),
+    Variable(Variable),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, context: &ConditionContext) -> i32 {
+        match self {
+            Expr::Number(number) => *number,
+            Expr::Variable(variable) => variable.get(context),
+            Expr::Not(expr) => (expr.eval(context) == 0) as i32,
+            Expr::Binary(op, lhs, rhs) => op.apply(lhs.eval(context), rhs.eval(context)),
+        }
+    }
+}
+
+/// Parsed breakpoint condition
+/// Numbers can be decimal or hex with `$` or `0x`, and the variables are
+/// A, X, Y, SP, PC, P, Address and Value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self, ConditionError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, next: 0 };
+        let expr = parser.expr(0)?;
+        if let Some(token) = parser.tokens.get(parser.next) {
+            return Err(ConditionError::Unexpected(token.clone()));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    /// Text the condition was parsed from
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Any non zero result is true
+    pub fn eval(&self, context: &ConditionContext) -> bool {
+        self.expr.eval(context) != 0
+    }
+}
+
+/// Split into numbers, names, operators and parentheses
+fn tokenize(source: &str) -> Result<Vec<String>, ConditionError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_alphanumeric() || c == '$' || c == '_' {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '$' || c == '_') {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        } else {
+            chars.next();
+            let mut token = c.to_string();
+            // Two character operators
+            if let Some(&next) = chars.peek() {
+                let pair = format!("{c}{next}");
+                if matches!(pair.as_str(), "||" | "&&" | "==" | "!=" | "<=" | ">=") {
+                    token = pair;
+                    chars.next();
+                }
+            }
+            if BinaryOp::from_token(&token).is_none() && !matches!(c, '!' | '(' | ')') {
+                return Err(ConditionError::Unexpected(token));
+            }
+            tokens.push(token);
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_number(token: &str) -> Result<i32, ConditionError> {
+    let parsed = if let Some(hex) = token.strip_prefix('$') {
+        i32::from_str_radix(hex, 16)
+    } else if let Some(hex) = token.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16)
+    } else {
+        token.parse()
+    };
+    parsed.map_err(|_| ConditionError::InvalidNumber(token.to_string()))
+}
+
+/// Precedence climbing parser
+struct Parser {
+    tokens: Vec<String>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.next).map(String::as_str)
+    }
+
+    fn advance(&mut self) -> Result<String, ConditionError> {
+        let token = self
+            .tokens
+            .get(self.next)
+            .ok_or(ConditionError::UnexpectedEnd)?;
+        self.next += 1;
+        Ok(token.clone())
+    }
+
+    /// Parse binary operators that bind tighter than min_precedence
+    fn expr(&mut self, min_precedence: u8) -> Result<Expr, ConditionError> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.peek().and_then(BinaryOp::from_token) {
+            if op.precedence() <= min_precedence {
+                break;
+            }
+            self.next += 1;
+            let rhs = self.expr(op.precedence())?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ConditionError> {
+        let token = self.advance()?;
+        match token.as_str() {
+            "!" => Ok(Expr::Not(Box::new(self.unary()?))),
+            "(" => {
+                let expr = self.expr(0)?;
+                match self.advance()?.as_str() {
+                    ")" => Ok(expr),
+                    other => Err(ConditionError::Unexpected(other.to_string())),
+                }
+            }
+            _ if token.starts_with('$') || token.starts_with(|c: char| c.is_ascii_digit()) => {
+                parse_number(&token).map(Expr::Number)
+            }
+            _ if token.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                Variable::from_name(&token)
+                    .map(Expr::Variable)
+                    .ok_or(ConditionError::UnknownVariable(token))
+            }
+            _ => Err(ConditionError::Unexpected(token)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::breakpoint::CpuRegisters;
+
+    fn eval(source: &str, context: &ConditionContext) -> bool {
+        Condition::parse(source).unwrap().eval(context)
+    }
+
+    #[test]
+    fn evaluate() {
+        let context = ConditionContext {
+            registers: CpuRegisters {
+                a: 0x10,
+                x: 4,
+                pc: 0xc000,
+                ..Default::default()
+            },
+            address: 0x2007,
+            value: 0xff,
+        };
+        assert!(eval("A == $10 && X > 3", &context));
+        assert!(!eval("a == $10 && x > 4", &context));
+        assert!(eval("x == 1 || pc >= 0xc000", &context));
+        assert!(eval("value & $80", &context));
+        assert!(eval("!(address != $2007)", &context));
+        // && binds tighter than ||
+        assert!(eval("1 || 0 && 0", &context));
+        assert!(eval("X + 2 - 1 == 5", &context));
+    }
+
+    #[test]
+    fn errors() {
+        use ConditionError::*;
+        assert_eq!(Condition::parse("A =="), Err(UnexpectedEnd));
+        assert_eq!(Condition::parse("Z == 1"), Err(UnknownVariable("Z".into())));
+        assert_eq!(
+            Condition::parse("A == $1g"),
+            Err(InvalidNumber("$1g".into()))
+        );
+        assert_eq!(Condition::parse("A = 1"), Err(Unexpected("=".into())));
+        assert_eq!(Condition::parse("(A == 1"), Err(UnexpectedEnd));
+        assert_eq!(Condition::parse("A 1"), Err(Unexpected("1".into())));
+    }
+}
@@ -0,0 +1,205 @@
+//! Breakpoints that pause emulation when the CPU executes or accesses an address, or the PPU
+//! accesses its own address space
+
+mod condition;
+
+pub use condition::{Condition, ConditionContext, ConditionError};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakpointKind {
+    #[default]
+    Execute,
+    Read,
+    Write,
+    /// PPU address space reads, including rendering fetches
+    PpuRead,
+    PpuWrite,
+}
+
+impl BreakpointKind {
+    pub const ALL: [BreakpointKind; 5] = [
+        BreakpointKind::Execute,
+        BreakpointKind::Read,
+        BreakpointKind::Write,
+        BreakpointKind::PpuRead,
+        BreakpointKind::PpuWrite,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BreakpointKind::Execute => "Execute",
+            BreakpointKind::Read => "Read",
+            BreakpointKind::Write => "Write",
+            BreakpointKind::PpuRead => "PPU read",
+            BreakpointKind::PpuWrite => "PPU write",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub kind: BreakpointKind,
+    /// Inclusive address range
+    pub start: u16,
+    pub end: u16,
+    pub enabled: bool,
+    /// Only breaks when this is true
+    pub condition: Option<Condition>,
+    /// Times the breakpoint has broken
+    pub hit_count: u32,
+}
+
+impl Breakpoint {
+    pub fn new(kind: BreakpointKind, start: u16, end: u16) -> Self {
+        Self {
+            kind,
+            start,
+            end,
+            enabled: true,
+            condition: None,
+            hit_count: 0,
+        }
+    }
+
+    fn matches(&self, kind: BreakpointKind, context: &ConditionContext) -> bool {
+        self.enabled
+            && self.kind == kind
+            && (self.start..=self.end).contains(&context.address)
+            && self.condition.as_ref().is_none_or(|c| c.eval(context))
+    }
+}
+
+/// CPU registers at the start of the instruction that hit a breakpoint
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuRegisters {
+    pub pc: u16,
+    pub sp: u8,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub flags: u8,
+}
+
+/// Why emulation was paused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakReason {
+    /// Index of the breakpoint in the list
+    pub index: usize,
+    pub kind: BreakpointKind,
+    pub address: u16,
+    pub value: u8,
+}
+
+/// Every breakpoint and the state needed to check them while the CPU and PPU are running
+/// Lives next to the cartridge on the PPU bus so both buses can check it
+#[derive(Default)]
+pub struct Breakpoints {
+    pub list: Vec<Breakpoint>,
+    registers: CpuRegisters,
+    hit: Option<BreakReason>,
+    /// Execute breakpoints are skipped once at this address so resuming doesn't break again
+    resume_at: Option<u16>,
+}
+
+impl Breakpoints {
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Check execute breakpoints before the instruction at pc is fetched
+    /// Returns true if the instruction should not run
+    pub(crate) fn check_execute(&mut self, registers: CpuRegisters, opcode: u8) -> bool {
+        self.registers = registers;
+        if self.resume_at.take() == Some(registers.pc) {
+            return false;
+        }
+        let hit = self.check(BreakpointKind::Execute, registers.pc, opcode);
+        if hit {
+            self.resume_at = Some(registers.pc);
+        }
+        hit
+    }
+
+    /// Returns true if any breakpoint was hit
+    pub(crate) fn check(&mut self, kind: BreakpointKind, address: u16, value: u8) -> bool {
+        let context = ConditionContext {
+            registers: self.registers,
+            address,
+            value,
+        };
+        let mut hit = false;
+        for (index, breakpoint) in self.list.iter_mut().enumerate() {
+            if breakpoint.matches(kind, &context) {
+                hit = true;
+                breakpoint.hit_count += 1;
+                // Keep the first one when an instruction hits several
+                self.hit.get_or_insert(BreakReason {
+                    index,
+                    kind,
+                    address,
+                    value,
+                });
+            }
+        }
+        hit
+    }
+
+    /// Don't break on an execute breakpoint for the next instruction if it's at pc
+    pub fn skip_execute_at(&mut self, pc: u16) {
+        self.resume_at = Some(pc);
+    }
+
+    /// Breakpoint hit since the last call
+    pub fn take_hit(&mut self) -> Option<BreakReason> {
+        self.hit.take()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn check() {
+        let mut breakpoints = Breakpoints::default();
+        breakpoints
+            .list
+            .push(Breakpoint::new(BreakpointKind::Write, 0x2000, 0x2007));
+        breakpoints.list.push(Breakpoint {
+            condition: Some(Condition::parse("value == 3").unwrap()),
+            ..Breakpoint::new(BreakpointKind::Read, 0x0300, 0x0300)
+        });
+
+        breakpoints.check(BreakpointKind::Read, 0x2000, 3);
+        breakpoints.check(BreakpointKind::Write, 0x2008, 3);
+        breakpoints.check(BreakpointKind::Read, 0x0300, 2);
+        assert_eq!(breakpoints.take_hit(), None);
+
+        breakpoints.check(BreakpointKind::Read, 0x0300, 3);
+        breakpoints.check(BreakpointKind::Write, 0x2006, 3);
+        let hit = breakpoints.take_hit().unwrap();
+        assert_eq!((hit.index, hit.address), (1, 0x0300));
+        assert_eq!(breakpoints.list[0].hit_count, 1);
+
+        breakpoints.list[0].enabled = false;
+        breakpoints.check(BreakpointKind::Write, 0x2006, 3);
+        assert_eq!(breakpoints.take_hit(), None);
+    }
+
+    #[test]
+    fn resume_execute() {
+        let mut breakpoints = Breakpoints::default();
+        breakpoints
+            .list
+            .push(Breakpoint::new(BreakpointKind::Execute, 0x8000, 0x8000));
+        let registers = CpuRegisters {
+            pc: 0x8000,
+            ..Default::default()
+        };
+        assert!(breakpoints.check_execute(registers, 0xea));
+        assert!(breakpoints.take_hit().is_some());
+        // Resuming runs the instruction it broke on
+        assert!(!breakpoints.check_execute(registers, 0xea));
+        assert!(breakpoints.check_execute(registers, 0xea));
+    }
+}
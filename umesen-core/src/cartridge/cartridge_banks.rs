@@ -22,19 +22,43 @@ impl<T, const C: usize> std::ops::DerefMut for FixedArray<T, C> {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+// Serde only implements arrays up to 32 elements so serialize as a sequence instead
+impl<T: serde::Serialize, const C: usize> serde::Serialize for FixedArray<T, C> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter())
+    }
+}
+
+impl<'de, T: serde::Deserialize<'de>, const C: usize> serde::Deserialize<'de> for FixedArray<T, C> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = Vec::<T>::deserialize(deserializer)?;
+        let len = values.len();
+        let array = values.try_into().map_err(|_| {
+            serde::de::Error::invalid_length(len, &format!("an array of length {C}").as_str())
+        })?;
+        Ok(Self(array))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Bank {
     Number(u8),
     FromLast(u8),
+    /// Bank number that doesn't fit in a byte, for mappers with more than 256 banks
+    Wide(u16),
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, serde::Serialize, serde::Deserialize)]
 pub struct MemoryBanks(Vec<u8>);
 
 // (size of a single bank in units of kb, and the bank number)
 pub type BankMapping = (usize, Bank);
 
 impl MemoryBanks {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self(data)
+    }
+
     pub fn write(&mut self, bank_mapping: BankMapping, offset: u16, value: u8) {
         if !self.0.is_empty() {
             let index = self.index(bank_mapping, offset);
@@ -50,6 +74,22 @@ impl MemoryBanks {
         }
     }
 
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+
     /// Get the index into the inner vec based on the banks and offset
     /// Offset will be wrapped around bank_size
     fn index(&self, (bank_size_kb, bank): BankMapping, offset: u16) -> usize {
@@ -58,6 +98,7 @@ impl MemoryBanks {
 
         let bank_number = match bank {
             Bank::Number(number) => number as usize,
+            Bank::Wide(number) => number as usize,
             Bank::FromLast(from_last) => num_banks - from_last as usize - 1,
         };
 
@@ -76,9 +117,9 @@ pub struct CartridgeBanks {
 impl CartridgeBanks {
     pub fn new(prg_ram: Vec<u8>, prg_rom: Vec<u8>, chr_mem: Vec<u8>) -> Self {
         Self {
-            prg_ram: MemoryBanks(prg_ram),
-            prg_rom: MemoryBanks(prg_rom),
-            chr_mem: MemoryBanks(chr_mem),
+            prg_ram: MemoryBanks::new(prg_ram),
+            prg_rom: MemoryBanks::new(prg_rom),
+            chr_mem: MemoryBanks::new(chr_mem),
         }
     }
 }
@@ -1,14 +1,20 @@
+use crate::region::Region;
+
 #[derive(thiserror::Error, Debug)]
 pub enum NesParseError {
     #[error("Magic number '{0}' in header is not a valid NES header")]
     InvalidMagicNumber(String),
     #[error("Mapper id '{0}' is not supported")]
     UnsupportedMapper(u16),
+    #[error("FDS image is not made of 65500 byte disk sides")]
+    InvalidFdsImage,
+    #[error("NSF file is invalid: {0}")]
+    InvalidNsf(&'static str),
     #[error(transparent)]
     IoError(#[from] std::io::Error),
 }
 
-#[derive(Default, Debug, PartialEq, Clone, Copy)]
+#[derive(Default, Debug, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
 pub enum Mirroring {
     /// A A
     /// B B
@@ -40,6 +46,7 @@ pub struct CartridgeHeader {
     pub prg_ram_size: usize,
     pub chr_mem_size: usize,
     pub chr_mem_is_rom: bool,
+    pub region: Region,
 }
 
 impl CartridgeHeader {
@@ -77,14 +84,17 @@ impl CartridgeHeader {
             mapper_id |= (data[8] as u16 & 0x0f) << 8;
         }
 
-        if data[12] & 0b01 != 0 {
-            log::warn!("Detected PAL ROM, this emulator only supports NTSC");
-        }
+        // Multi-region ROMs run as NTSC
+        let region = match data[12] & 0b11 {
+            1 if is_v2 => Region::Pal,
+            3 if is_v2 => Region::Dendy,
+            _ => Region::Ntsc,
+        };
 
         Ok(Self {
             prg_rom_size,
             mapper_id,
-            submapper_id: data[8] & 0xf0 >> 4,
+            submapper_id: if is_v2 { data[8] >> 4 } else { 0 },
             mirroring: if data[6] & 0b000_1000 != 0 {
                 // Note: this bit could mean a different mirrorings in some mappers?
                 Mirroring::FourScreen
@@ -103,6 +113,7 @@ impl CartridgeHeader {
             chr_mem_is_rom: chr_rom_size != 0,
             prg_ram_size,
             is_v2,
+            region,
         })
     }
 }
@@ -142,8 +153,33 @@ mod test {
                 prg_ram_size: 8 * 1024,
                 has_trainer: false,
                 chr_mem_is_rom: true,
-                is_v2: false
+                is_v2: false,
+                region: Region::Ntsc,
             }
         )
     }
+
+    #[test]
+    fn parse_region() {
+        let mut data = [
+            0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x31, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+        for (timing, region) in [
+            (0, Region::Ntsc),
+            (1, Region::Pal),
+            (2, Region::Ntsc),
+            (3, Region::Dendy),
+        ] {
+            data[12] = timing;
+            assert_eq!(CartridgeHeader::from_nes(data).unwrap().region, region);
+        }
+        // iNES 1.0 has no timing byte
+        data[7] = 0;
+        data[12] = 1;
+        assert_eq!(
+            CartridgeHeader::from_nes(data).unwrap().region,
+            Region::Ntsc
+        );
+    }
 }
@@ -0,0 +1,206 @@
+use crate::cartridge::NesParseError;
+
+/// Size of a disk side in a .fds image
+pub const SIDE_SIZE: usize = 65500;
+const HEADER_SIZE: usize = 16;
+/// Gap of zeros at the start of a disk side before the first block, 28300 bits
+const LEADING_GAP_SIZE: usize = 28300 / 8;
+/// Gap of zeros after each block, 976 bits
+const BLOCK_GAP_SIZE: usize = 976 / 8;
+/// Every block starts with a 1 bit which is read as 0x80
+const BLOCK_START_MARK: u8 = 0x80;
+/// Size of a side with the gaps and CRCs, leaves space for games to write new files
+const RAW_SIDE_SIZE: usize = 0x14000;
+
+/// Disk inserted into the Famicom Disk System
+/// The sides are stored how the drive would read them, with the gaps and CRCs that .fds images leave out
+/// https://www.nesdev.org/wiki/FDS_disk_format
+#[derive(Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FdsDisk {
+    /// fwNES header at the start of the image, empty if the image didn't have one
+    header: Vec<u8>,
+    sides: Vec<Vec<u8>>,
+}
+
+impl std::fmt::Debug for FdsDisk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FdsDisk")
+            .field("side_count", &self.sides.len())
+            .finish()
+    }
+}
+
+impl FdsDisk {
+    pub fn from_fds(image: &[u8]) -> Result<Self, NesParseError> {
+        let (header, data) = if image.starts_with(b"FDS\x1a") {
+            image.split_at(HEADER_SIZE.min(image.len()))
+        } else {
+            (&[][..], image)
+        };
+        if data.is_empty() || !data.len().is_multiple_of(SIDE_SIZE) {
+            return Err(NesParseError::InvalidFdsImage);
+        }
+
+        Ok(Self {
+            header: header.to_vec(),
+            sides: data.chunks(SIDE_SIZE).map(add_gaps).collect(),
+        })
+    }
+
+    /// Converts the disk back into a .fds image
+    pub fn to_fds(&self) -> Vec<u8> {
+        let mut image = self.header.clone();
+        for side in &self.sides {
+            image.extend(remove_gaps(side));
+        }
+        image
+    }
+
+    pub fn side_count(&self) -> usize {
+        self.sides.len()
+    }
+
+    pub fn side(&self, side: usize) -> &[u8] {
+        &self.sides[side]
+    }
+
+    pub fn side_mut(&mut self, side: usize) -> &mut [u8] {
+        &mut self.sides[side]
+    }
+}
+
+/// CRC used by the FDS for each block, the CRC of a block followed by its CRC is 0
+pub fn update_crc(crc: u16, value: u8) -> u16 {
+    let mut crc = crc;
+    for bit in 0..8 {
+        let carry = crc & 1 != 0;
+        crc >>= 1;
+        if carry {
+            crc ^= 0x8408;
+        }
+        if value & (1 << bit) != 0 {
+            crc ^= 0x8000;
+        }
+    }
+    crc
+}
+
+/// Size of the block starting at data[i] including the block type byte
+/// File data blocks get their size from the file header block before it
+fn block_size(data: &[u8], i: usize, file_size: usize) -> Option<usize> {
+    match data.get(i)? {
+        1 => Some(56),
+        2 => Some(2),
+        3 => Some(16),
+        4 => Some(1 + file_size),
+        _ => None,
+    }
+}
+
+fn file_size(file_header: &[u8]) -> usize {
+    file_header[13] as usize | (file_header[14] as usize) << 8
+}
+
+fn add_gaps(side: &[u8]) -> Vec<u8> {
+    let mut raw = vec![0; LEADING_GAP_SIZE];
+    let mut i = 0;
+    let mut size = 0;
+    while let Some(block_size) = block_size(side, i, size) {
+        let Some(block) = side.get(i..i + block_size) else {
+            break;
+        };
+        if block[0] == 3 {
+            size = file_size(block);
+        }
+
+        let mut crc = update_crc(0, BLOCK_START_MARK);
+        for byte in block {
+            crc = update_crc(crc, *byte);
+        }
+        crc = update_crc(update_crc(crc, 0), 0);
+
+        raw.push(BLOCK_START_MARK);
+        raw.extend_from_slice(block);
+        raw.extend_from_slice(&crc.to_le_bytes());
+        raw.extend(std::iter::repeat_n(0, BLOCK_GAP_SIZE));
+        i += block_size;
+    }
+    raw.resize(raw.len().max(RAW_SIDE_SIZE), 0);
+    raw
+}
+
+fn remove_gaps(raw: &[u8]) -> Vec<u8> {
+    let mut side = Vec::with_capacity(SIDE_SIZE);
+    let mut i = 0;
+    let mut size = 0;
+    loop {
+        // Skip the gap up to the start of the next block
+        while raw.get(i) == Some(&0) {
+            i += 1;
+        }
+        if raw.get(i) != Some(&BLOCK_START_MARK) {
+            break;
+        }
+        i += 1;
+
+        let Some(block) = block_size(raw, i, size).and_then(|len| raw.get(i..i + len)) else {
+            break;
+        };
+        if block[0] == 3 {
+            size = file_size(block);
+        }
+        side.extend_from_slice(block);
+        // Skip the CRC
+        i += block.len() + 2;
+    }
+    side.resize(SIDE_SIZE, 0);
+    side
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A side with the disk info block, file amount block and one file with 3 bytes
+    fn create_test_side() -> Vec<u8> {
+        let mut side = vec![1];
+        side.extend_from_slice(b"*NINTENDO-HVC*");
+        side.resize(56, 0);
+        side.extend_from_slice(&[2, 1]);
+        let mut file_header = vec![3, 0, 0, b'F', b'I', b'L', b'E', b' ', b' ', b' ', b' '];
+        file_header.extend_from_slice(&[0x00, 0x60, 3, 0, 0]);
+        side.extend_from_slice(&file_header);
+        side.extend_from_slice(&[4, 7, 8, 9]);
+        side.resize(SIDE_SIZE, 0);
+        side
+    }
+
+    #[test]
+    fn gaps() {
+        let side = create_test_side();
+        let raw = add_gaps(&side);
+        assert_eq!(raw[LEADING_GAP_SIZE], BLOCK_START_MARK);
+        assert_eq!(raw[LEADING_GAP_SIZE + 1], 1);
+
+        // The CRC of the block followed by the CRC is 0
+        let crc = raw[LEADING_GAP_SIZE..LEADING_GAP_SIZE + 1 + 56 + 2]
+            .iter()
+            .fold(0, |crc, byte| update_crc(crc, *byte));
+        assert_eq!(crc, 0);
+
+        assert_eq!(remove_gaps(&raw), side);
+    }
+
+    #[test]
+    fn fds_image() {
+        let mut image = b"FDS\x1a\x02".to_vec();
+        image.resize(HEADER_SIZE, 0);
+        image.extend(create_test_side());
+        image.extend(create_test_side());
+        let disk = FdsDisk::from_fds(&image).unwrap();
+        assert_eq!(disk.side_count(), 2);
+        assert_eq!(disk.to_fds(), image);
+
+        assert!(FdsDisk::from_fds(&image[..100]).is_err());
+    }
+}
@@ -0,0 +1,310 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use super::{CartridgeHeader, Mirroring};
+
+static EMBEDDED: LazyLock<GameDatabase> =
+    LazyLock::new(|| GameDatabase::parse(include_str!("game_database.txt")));
+
+/// Correct header values for a game
+#[derive(Debug, PartialEq, Clone)]
+pub struct GameDatabaseEntry {
+    pub name: String,
+    pub mapper_id: u16,
+    pub submapper_id: u8,
+    pub mirroring: Mirroring,
+    pub has_battery: bool,
+    pub prg_ram_size: usize,
+    /// Only used if the cartridge has no CHR ROM
+    pub chr_ram_size: usize,
+}
+
+impl GameDatabaseEntry {
+    /// Overwrite the values in the header with the ones from the database
+    pub fn apply(&self, header: &mut CartridgeHeader) {
+        header.mapper_id = self.mapper_id;
+        header.submapper_id = self.submapper_id;
+        header.mirroring = self.mirroring;
+        header.has_battery = self.has_battery;
+        header.prg_ram_size = self.prg_ram_size;
+        if !header.chr_mem_is_rom {
+            header.chr_mem_size = self.chr_ram_size;
+        }
+    }
+}
+
+/// Hash of the PRG ROM followed by the CHR ROM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RomHash {
+    Crc32(u32),
+    Sha1([u8; 20]),
+}
+
+impl RomHash {
+    fn parse(text: &str) -> Option<Self> {
+        let byte = |i: usize| u8::from_str_radix(text.get(i * 2..i * 2 + 2)?, 16).ok();
+        match text.len() {
+            8 => u32::from_str_radix(text, 16).ok().map(RomHash::Crc32),
+            40 => {
+                let mut sha1 = [0; 20];
+                for (i, value) in sha1.iter_mut().enumerate() {
+                    *value = byte(i)?;
+                }
+                Some(RomHash::Sha1(sha1))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Database of games with known correct headers keyed by the CRC32 or SHA-1 of the PRG and CHR ROM
+#[derive(Default, Debug)]
+pub struct GameDatabase {
+    entries: HashMap<RomHash, GameDatabaseEntry>,
+}
+
+impl GameDatabase {
+    /// Database included in the binary
+    pub fn embedded() -> &'static Self {
+        &EMBEDDED
+    }
+
+    /// Parse the database text format described in game_database.txt, invalid lines are skipped
+    pub fn parse(text: &str) -> Self {
+        let mut entries = HashMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match parse_line(line) {
+                Some((hash, entry)) => drop(entries.insert(hash, entry)),
+                None => log::warn!("Invalid game database entry on line {}", i + 1),
+            }
+        }
+        Self { entries }
+    }
+
+    pub fn get(&self, hash: RomHash) -> Option<&GameDatabaseEntry> {
+        self.entries.get(&hash)
+    }
+
+    /// Look up a ROM by its SHA-1 first since it's less likely to collide, then by its CRC32
+    pub fn find(&self, prg_rom: &[u8], chr_rom: &[u8]) -> Option<&GameDatabaseEntry> {
+        let mut sha1 = sha1_smol::Sha1::new();
+        sha1.update(prg_rom);
+        sha1.update(chr_rom);
+        self.get(RomHash::Sha1(sha1.digest().bytes()))
+            .or_else(|| self.get(RomHash::Crc32(super::rom_crc32(prg_rom, chr_rom))))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+fn parse_line(line: &str) -> Option<(RomHash, GameDatabaseEntry)> {
+    let mut fields = line.split_whitespace();
+    let hash = RomHash::parse(fields.next()?)?;
+    let entry = GameDatabaseEntry {
+        mapper_id: fields.next()?.parse().ok()?,
+        submapper_id: fields.next()?.parse().ok()?,
+        mirroring: match fields.next()? {
+            "H" => Mirroring::Horizontal,
+            "V" => Mirroring::Vertical,
+            "4" => Mirroring::FourScreen,
+            "A" => Mirroring::SingleScreenLow,
+            "B" => Mirroring::SingleScreenHigh,
+            _ => return None,
+        },
+        has_battery: match fields.next()? {
+            "0" => false,
+            "1" => true,
+            _ => return None,
+        },
+        prg_ram_size: fields.next()?.parse().ok()?,
+        chr_ram_size: fields.next()?.parse().ok()?,
+        name: fields.collect::<Vec<_>>().join(" "),
+    };
+    Some((hash, entry))
+}
+
+/// Convert the games in the NES 2.0 XML database (nes20db.xml) to lines of the text format,
+/// games that aren't for the NES or Famicom or have a mirroring that can't be stored are skipped
+pub fn convert_nes20db(xml: &str) -> String {
+    let mut lines = String::new();
+    for game in xml.split("<game>").skip(1) {
+        let game = game.split("</game>").next().unwrap_or(game);
+        match convert_nes20db_game(game) {
+            Some(line) => {
+                lines.push_str(&line);
+                lines.push('\n');
+            }
+            None => log::warn!("Skipped nes20db game: {}", game.trim()),
+        }
+    }
+    lines
+}
+
+fn convert_nes20db_game(game: &str) -> Option<String> {
+    // Games are preceded by a comment with the path of the dump
+    let name = game.split("<!--").nth(1)?.split("-->").next()?.trim();
+    let name = name.rsplit(['\\', '/']).next()?;
+    let name = name.strip_suffix(".nes").unwrap_or(name);
+
+    let console = xml_attribute(game, "console", "type").unwrap_or("0");
+    if console != "0" {
+        return None;
+    }
+    let sha1 = xml_attribute(game, "rom", "sha1")?.to_ascii_lowercase();
+    let mapper = xml_attribute(game, "pcb", "mapper")?;
+    let submapper = xml_attribute(game, "pcb", "submapper").unwrap_or("0");
+    let mirroring = match xml_attribute(game, "pcb", "mirroring")? {
+        mirroring @ ("H" | "V" | "4") => mirroring,
+        _ => return None,
+    };
+    let battery = xml_attribute(game, "pcb", "battery").unwrap_or("0");
+    let size = |tag: &str| -> usize {
+        xml_attribute(game, tag, "size")
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(0)
+    };
+    let prg_ram_size = size("prgram") + size("prgnvram");
+    let chr_ram_size = size("chrram") + size("chrnvram");
+    Some(format!(
+        "{sha1} {mapper} {submapper} {mirroring} {battery} {prg_ram_size} {chr_ram_size} {name}"
+    ))
+}
+
+/// Value of an attribute of the first element with a tag, like `<pcb mapper="4"/>`
+fn xml_attribute<'a>(xml: &'a str, tag: &str, attribute: &str) -> Option<&'a str> {
+    let element = xml.split(&format!("<{tag} ")).nth(1)?.split('>').next()?;
+    let value = element
+        .split(&format!(" {attribute}=\""))
+        .nth(1)
+        .or_else(|| element.strip_prefix(&format!("{attribute}=\"")))?;
+    value.split('"').next()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_database() {
+        let database = GameDatabase::parse(
+            "# comment\n\
+            0123abcd 4 1 V 1 8192 0 Some Game (USA)\n\
+            \n\
+            deadbeef 2 0 H 0 0 8192 Other\n\
+            00112233445566778899aabbccddeeff00112233 1 0 V 0 0 0 Hashed\n\
+            badline 1 2\n",
+        );
+        assert_eq!(database.len(), 3);
+        assert_eq!(
+            database.get(RomHash::Crc32(0x0123abcd)),
+            Some(&GameDatabaseEntry {
+                name: "Some Game (USA)".to_owned(),
+                mapper_id: 4,
+                submapper_id: 1,
+                mirroring: Mirroring::Vertical,
+                has_battery: true,
+                prg_ram_size: 8192,
+                chr_ram_size: 0,
+            })
+        );
+
+        let mut header = CartridgeHeader {
+            chr_mem_size: 0,
+            ..Default::default()
+        };
+        database
+            .get(RomHash::Crc32(0xdeadbeef))
+            .unwrap()
+            .apply(&mut header);
+        assert_eq!(header.mapper_id, 2);
+        assert_eq!(header.chr_mem_size, 8192);
+        assert!(database.get(RomHash::Crc32(0x12345678)).is_none());
+
+        let sha1 = RomHash::parse("00112233445566778899aabbccddeeff00112233").unwrap();
+        assert_eq!(database.get(sha1).unwrap().name, "Hashed");
+    }
+
+    #[test]
+    fn find_by_sha1_before_crc32() {
+        let prg_rom = [1, 2, 3];
+        let chr_rom = [4];
+        let crc32 = super::super::rom_crc32(&prg_rom, &chr_rom);
+        let mut sha1 = sha1_smol::Sha1::new();
+        sha1.update(&[1, 2, 3, 4]);
+        let database = GameDatabase::parse(&format!(
+            "{crc32:08x} 1 0 V 0 0 0 By CRC32\n{} 2 0 V 0 0 0 By SHA-1\n",
+            sha1.digest()
+        ));
+        assert_eq!(database.find(&prg_rom, &chr_rom).unwrap().name, "By SHA-1");
+        assert!(database.find(&prg_rom, &[]).is_none());
+    }
+
+    #[test]
+    fn nes20db() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<nes20db>
+	<game>
+		<!-- Licensed\Some Game (USA).nes -->
+		<prgrom size="131072" crc32="01234567" sha1="0123456789ABCDEF0123456789ABCDEF01234567"/>
+		<rom size="131072" crc32="89ABCDEF" sha1="00112233445566778899AABBCCDDEEFF00112233"/>
+		<prgnvram size="8192"/>
+		<chrram size="8192"/>
+		<pcb mapper="4" submapper="4" mirroring="V" battery="1"/>
+		<console type="0" region="0"/>
+	</game>
+	<game>
+		<!-- Vs. System\Other.nes -->
+		<rom size="40960" crc32="00000000" sha1="FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF"/>
+		<pcb mapper="99" submapper="0" mirroring="4" battery="0"/>
+		<console type="1" region="0"/>
+	</game>
+</nes20db>"#;
+        let text = convert_nes20db(xml);
+        assert_eq!(
+            text,
+            "00112233445566778899aabbccddeeff00112233 4 4 V 1 8192 8192 Some Game (USA)\n"
+        );
+        let database = GameDatabase::parse(&text);
+        assert_eq!(database.len(), 1);
+    }
+
+    #[test]
+    fn correct_header() {
+        let mut rom = include_bytes!("../../tests/nestest.nes").to_vec();
+        let database =
+            GameDatabase::parse("4131307f0f69f2a5c54b7d438328c5b2a5ed0820 0 0 H 0 8192 0 nestest");
+        let cartridge = crate::Cartridge::from_nes_with_database(&rom[..], &database).unwrap();
+        assert!(cartridge.original_header().is_none());
+
+        // Mapper 4 with vertical mirroring and a battery
+        rom[6] = 0x43;
+        let cartridge = crate::Cartridge::from_nes_with_database(&rom[..], &database).unwrap();
+        let header = cartridge.header();
+        assert_eq!(header.mapper_id, 0);
+        assert_eq!(header.mirroring, Mirroring::Horizontal);
+        assert!(!header.has_battery);
+        let original = cartridge.original_header().unwrap();
+        assert_eq!(original.mapper_id, 4);
+        assert_eq!(original.mirroring, Mirroring::Vertical);
+        assert!(original.has_battery);
+    }
+
+    #[test]
+    fn embedded_database_parses() {
+        // Every non comment line of the embedded database should be valid
+        let text = include_str!("game_database.txt");
+        let lines = text
+            .lines()
+            .filter(|l| !l.trim().is_empty() && !l.trim().starts_with('#'));
+        assert_eq!(GameDatabase::embedded().len(), lines.count());
+    }
+}
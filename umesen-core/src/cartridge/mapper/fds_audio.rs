@@ -0,0 +1,312 @@
+use crate::cartridge::FixedArray;
+
+/// Master volume multipliers from $4089, the output is divided by 1152 afterwards
+const MASTER_VOLUMES: [u32; 4] = [36, 24, 17, 14];
+/// Modulation counter changes for each modulation table entry, None resets the counter
+const MOD_STEPS: [Option<i8>; 8] = [
+    Some(0),
+    Some(1),
+    Some(2),
+    Some(4),
+    None,
+    Some(-4),
+    Some(-2),
+    Some(-1),
+];
+/// Brings the maximum output to about twice an APU pulse channel
+const OUTPUT_SCALE: f32 = 0.0036;
+
+/// Volume and modulation envelopes, also contain the frequency of the unit they control
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+struct Envelope {
+    speed: u8,
+    gain: u8,
+    increase: bool,
+    disabled: bool,
+    timer: u32,
+    frequency: u16,
+}
+
+impl Envelope {
+    fn write_control(&mut self, value: u8, master_speed: u8) {
+        self.speed = value & 0x3f;
+        self.increase = value & 0x40 != 0;
+        self.disabled = value & 0x80 != 0;
+        if self.disabled {
+            self.gain = self.speed;
+        }
+        self.reset_timer(master_speed);
+    }
+
+    fn reset_timer(&mut self, master_speed: u8) {
+        self.timer = 8 * (self.speed as u32 + 1) * master_speed as u32;
+    }
+
+    fn clock(&mut self, master_speed: u8) {
+        if self.disabled || master_speed == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.reset_timer(master_speed);
+            if self.increase && self.gain < 32 {
+                self.gain += 1;
+            } else if !self.increase && self.gain > 0 {
+                self.gain -= 1;
+            }
+        }
+    }
+}
+
+/// Wavetable channel with frequency modulation inside the FDS RAM adapter
+/// https://www.nesdev.org/wiki/FDS_audio
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct FdsAudio {
+    wave_table: FixedArray<u8, 64>,
+    wave_write_enabled: bool,
+    wave_halted: bool,
+    wave_position: u8,
+    wave_accumulator: u16,
+    volume: Envelope,
+    envelopes_disabled: bool,
+    master_volume: u8,
+    master_envelope_speed: u8,
+
+    mod_table: FixedArray<u8, 64>,
+    mod_position: u8,
+    mod_accumulator: u16,
+    mod_halted: bool,
+    /// 7 bit signed counter
+    mod_counter: i8,
+    modulation: Envelope,
+
+    output: u8,
+}
+
+impl Default for FdsAudio {
+    fn default() -> Self {
+        Self {
+            wave_table: FixedArray::default(),
+            wave_write_enabled: false,
+            wave_halted: true,
+            wave_position: 0,
+            wave_accumulator: 0,
+            volume: Envelope::default(),
+            envelopes_disabled: false,
+            master_volume: 0,
+            master_envelope_speed: 0xe8,
+            mod_table: FixedArray::default(),
+            mod_position: 0,
+            mod_accumulator: 0,
+            mod_halted: true,
+            mod_counter: 0,
+            modulation: Envelope::default(),
+            output: 0,
+        }
+    }
+}
+
+impl FdsAudio {
+    pub fn read(&self, address: u16) -> Option<u8> {
+        match address {
+            0x4040..=0x407f => Some(if self.wave_write_enabled {
+                self.wave_table[address as usize & 0x3f]
+            } else {
+                self.wave_table[self.wave_position as usize]
+            }),
+            0x4090 => Some(0x40 | self.volume.gain),
+            0x4092 => Some(0x40 | self.modulation.gain),
+            _ => None,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x4040..=0x407f if self.wave_write_enabled => {
+                self.wave_table[address as usize & 0x3f] = value & 0x3f;
+            }
+            0x4080 => self.volume.write_control(value, self.master_envelope_speed),
+            0x4082 => self.volume.frequency = (self.volume.frequency & 0xf00) | value as u16,
+            0x4083 => {
+                self.volume.frequency =
+                    (self.volume.frequency & 0x0ff) | (value as u16 & 0x0f) << 8;
+                self.wave_halted = value & 0x80 != 0;
+                self.envelopes_disabled = value & 0x40 != 0;
+                if self.wave_halted {
+                    self.wave_position = 0;
+                    self.wave_accumulator = 0;
+                }
+                if self.envelopes_disabled {
+                    self.volume.reset_timer(self.master_envelope_speed);
+                    self.modulation.reset_timer(self.master_envelope_speed);
+                }
+            }
+            0x4084 => self
+                .modulation
+                .write_control(value, self.master_envelope_speed),
+            0x4085 => self.mod_counter = ((value << 1) as i8) >> 1,
+            0x4086 => {
+                self.modulation.frequency = (self.modulation.frequency & 0xf00) | value as u16;
+            }
+            0x4087 => {
+                self.modulation.frequency =
+                    (self.modulation.frequency & 0x0ff) | (value as u16 & 0x0f) << 8;
+                self.mod_halted = value & 0x80 != 0;
+                if self.mod_halted {
+                    self.mod_accumulator = 0;
+                }
+            }
+            // Each write fills two entries of the table
+            0x4088 if self.mod_halted => {
+                for _ in 0..2 {
+                    self.mod_table[self.mod_position as usize] = value & 0b111;
+                    self.mod_position = (self.mod_position + 1) & 0x3f;
+                }
+            }
+            0x4089 => {
+                self.wave_write_enabled = value & 0x80 != 0;
+                self.master_volume = value & 0b11;
+            }
+            0x408a => self.master_envelope_speed = value,
+            _ => (),
+        }
+    }
+
+    /// Ran every CPU cycle
+    pub fn clock(&mut self) {
+        if !self.wave_halted && !self.envelopes_disabled {
+            self.volume.clock(self.master_envelope_speed);
+            self.modulation.clock(self.master_envelope_speed);
+        }
+
+        if !self.mod_halted && self.modulation.frequency != 0 {
+            let (accumulator, overflow) = self
+                .mod_accumulator
+                .overflowing_add(self.modulation.frequency);
+            self.mod_accumulator = accumulator;
+            if overflow {
+                self.clock_modulator();
+            }
+        }
+
+        if self.wave_halted || self.wave_write_enabled {
+            return;
+        }
+        let frequency = self.wave_frequency();
+        let (accumulator, overflow) = self.wave_accumulator.overflowing_add(frequency);
+        self.wave_accumulator = accumulator;
+        if overflow {
+            self.wave_position = (self.wave_position + 1) & 0x3f;
+        }
+
+        let level = self.volume.gain.min(32) as u32 * MASTER_VOLUMES[self.master_volume as usize];
+        self.output = (self.wave_table[self.wave_position as usize] as u32 * level / 1152) as u8;
+    }
+
+    fn clock_modulator(&mut self) {
+        let entry = self.mod_table[self.mod_position as usize];
+        self.mod_counter = match MOD_STEPS[entry as usize] {
+            // Counter is 7 bits so wrap it around
+            Some(step) => ((self.mod_counter.wrapping_add(step)) << 1) >> 1,
+            None => 0,
+        };
+        self.mod_position = (self.mod_position + 1) & 0x3f;
+    }
+
+    /// Wave frequency after applying the modulation
+    fn wave_frequency(&self) -> u16 {
+        let pitch = self.volume.frequency as i32;
+        if self.mod_halted {
+            return pitch as u16;
+        }
+
+        let counter = self.mod_counter as i32;
+        let mut temp = counter * self.modulation.gain as i32;
+        let remainder = temp & 0x0f;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+
+        temp *= pitch;
+        let remainder = temp & 0x3f;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        (pitch + temp).clamp(0, 0xffff) as u16
+    }
+
+    pub fn sample(&self) -> f32 {
+        self.output as f32 * OUTPUT_SCALE
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wave() {
+        let mut audio = FdsAudio::default();
+        audio.write(0x4089, 0x80);
+        for i in 0..64 {
+            audio.write(0x4040 + i, if i < 32 { 63 } else { 0 });
+        }
+        audio.write(0x4089, 0);
+        // Full volume with the envelope disabled
+        audio.write(0x4080, 0x80 | 32);
+        // Moves one step of the wave about every 16 cycles
+        audio.write(0x4082, 0xff);
+        audio.write(0x4083, 0x0f);
+        audio.clock();
+        assert_eq!(audio.read(0x4090), Some(0x40 | 32));
+        assert_eq!(audio.output, 63);
+        for _ in 0..520 {
+            audio.clock();
+        }
+        assert_eq!(audio.output, 0);
+
+        audio.write(0x4083, 0x80);
+        assert_eq!(audio.wave_position, 0);
+    }
+
+    #[test]
+    fn modulation() {
+        let mut audio = FdsAudio::default();
+        audio.write(0x4082, 0x00);
+        audio.write(0x4083, 0x01);
+        audio.write(0x4087, 0x80);
+        // Every entry adds 1 to the counter
+        for _ in 0..32 {
+            audio.write(0x4088, 1);
+        }
+        audio.write(0x4084, 0x80 | 32);
+        audio.write(0x4086, 0xff);
+        audio.write(0x4087, 0x0f);
+        assert_eq!(audio.wave_frequency(), 0x100);
+        let clock_modulator = |audio: &mut FdsAudio| {
+            for _ in 0..17 {
+                audio.clock();
+            }
+        };
+        clock_modulator(&mut audio);
+        assert_eq!(audio.mod_counter, 1);
+        assert_eq!(audio.wave_frequency(), 0x108);
+
+        // Counter wraps around at 7 bits
+        audio.write(0x4085, 0x3f);
+        clock_modulator(&mut audio);
+        assert_eq!(audio.mod_counter, -64);
+
+        audio.write(0x4085, 0x7e);
+        clock_modulator(&mut audio);
+        assert_eq!(audio.mod_counter, -1);
+        assert_eq!(audio.wave_frequency(), 0xf8);
+    }
+}
@@ -2,7 +2,7 @@ use crate::cartridge::{Bank, BankMapping, Mapper};
 
 /// INES designation for NROM boards
 /// https://www.nesdev.org/wiki/NROM
-#[derive(Default, Debug)]
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Mapper000 {}
 
 impl Mapper for Mapper000 {
@@ -2,7 +2,7 @@ use crate::cartridge::{Bank, Mapper};
 
 /// INES designation for UxROM boards
 /// https://www.nesdev.org/wiki/UxROM
-#[derive(Default, Debug)]
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Mapper002 {
     bank_number_low: u8,
 }
@@ -2,7 +2,7 @@ use crate::cartridge::{Bank, BankMapping, Mapper};
 
 /// INES designation for CNROM boards
 /// https://www.nesdev.org/wiki/CNROM
-#[derive(Default, Debug)]
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Mapper003 {
     bank_number: u8,
 }
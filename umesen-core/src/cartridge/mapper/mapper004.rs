@@ -1,12 +1,31 @@
 use crate::{
-    cartridge::{Bank, BankMapping, Mapper, Mirroring},
+    cartridge::{
+        Bank, BankMapping, CartridgeHeader, FixedArray, Mapper, Mirroring, NametableMapping,
+    },
     cpu::IrqStatus,
 };
 
-/// INES designation for MMC3 boards
+/// Boards using MMC3 with different PRG RAM, CHR and mirroring wiring
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+enum Mmc3Board {
+    #[default]
+    Mmc3,
+    /// MMC6 has 1kb of internal PRG RAM at $7000 with protection for each 512 byte half
+    Mmc6,
+    /// TxSROM (mapper 118) selects the nametables with bit 7 of the CHR banks
+    TxSrom,
+    /// TQROM (mapper 119) uses 8kb of CHR RAM when bit 6 of a CHR bank is set
+    Tqrom,
+}
+
+/// INES designation for MMC3 boards, also used for MMC6, TxSROM and TQROM
 /// https://www.nesdev.org/wiki/MMC3
-#[derive(Default, Debug)]
+/// https://www.nesdev.org/wiki/MMC6
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Mapper004 {
+    board: Mmc3Board,
+    /// Revision A only triggers an IRQ when the counter is decremented or reloaded to 0
+    old_irq_behaviour: bool,
     mirroring: Mirroring,
     registers: [u8; 8],
     selected_register_index: usize,
@@ -18,20 +37,93 @@ pub struct Mapper004 {
     irq_latch_value: u8,
     irq: IrqStatus,
     low_cycles: u8,
+
+    /// MMC6 $8000 bit 5
+    prg_ram_enabled: bool,
+    /// MMC6 $a001, read and write enable bits for the low and high half of the PRG RAM
+    prg_ram_protect: u8,
+    chr_ram: Option<FixedArray<u8, 0x2000>>,
 }
 
 impl Mapper004 {
+    pub fn new(header: &CartridgeHeader) -> Self {
+        let board = match (header.mapper_id, header.submapper_id) {
+            (4, 1) => Mmc3Board::Mmc6,
+            (118, _) => Mmc3Board::TxSrom,
+            (119, _) => Mmc3Board::Tqrom,
+            _ => Mmc3Board::Mmc3,
+        };
+        Self {
+            board,
+            old_irq_behaviour: header.mapper_id == 4 && header.submapper_id == 4,
+            chr_ram: (board == Mmc3Board::Tqrom).then(FixedArray::default),
+            ..Default::default()
+        }
+    }
+
     fn signal_scanline(&mut self) {
+        let reloaded = self.irq_reload;
+        let previous_counter = self.irq_counter;
         if self.irq_reload || self.irq_counter == 0 {
             self.irq_counter = self.irq_latch_value;
             self.irq_reload = false;
         } else {
             self.irq_counter -= 1;
-            if self.irq_counter == 0 {
-                self.irq.on();
-            }
+        }
+
+        let trigger = if self.old_irq_behaviour {
+            self.irq_counter == 0 && (previous_counter != 0 || reloaded)
+        } else {
+            self.irq_counter == 0
+        };
+        if trigger {
+            self.irq.on();
         }
     }
+
+    /// Bank number of the 1kb CHR bank the address is in
+    fn chr_bank(&self, address: u16) -> u8 {
+        let mut section_0 = [
+            self.registers[0] & !1,
+            self.registers[0] | 1,
+            self.registers[1] & !1,
+            self.registers[1] | 1,
+        ];
+        let mut section_1 = [
+            self.registers[2],
+            self.registers[3],
+            self.registers[4],
+            self.registers[5],
+        ];
+        if self.chr_mode_flip {
+            std::mem::swap(&mut section_0, &mut section_1);
+        }
+        let i = address as usize / 0x400;
+        match address {
+            0x0000..=0x0fff => section_0[i],
+            0x1000..=0x1fff => section_1[i - 4],
+            _ => unreachable!(),
+        }
+    }
+
+    /// Index into the TQROM CHR RAM if the address is mapped to it
+    fn chr_ram_index(&self, address: u16) -> Option<usize> {
+        let bank = self.chr_bank(address);
+        (self.chr_ram.is_some() && bank & 0x40 != 0)
+            .then(|| (bank as usize & 0b111) * 0x400 + address as usize % 0x400)
+    }
+
+    /// If the MMC6 PRG RAM half of the address can be read or written
+    fn mmc6_ram_access(&self, address: u16, write: bool) -> bool {
+        let high_half = address & 0x200 != 0;
+        let bit = match (high_half, write) {
+            (false, false) => 4,
+            (false, true) => 5,
+            (true, false) => 6,
+            (true, true) => 7,
+        };
+        self.prg_ram_enabled && self.prg_ram_protect & (1 << bit) != 0
+    }
 }
 
 impl Mapper for Mapper004 {
@@ -54,6 +146,30 @@ impl Mapper for Mapper004 {
         }
     }
 
+    fn map_prg_ram(&self, address: u16, write: bool) -> Option<BankMapping> {
+        match (self.board, address) {
+            (Mmc3Board::Mmc6, 0x7000..=0x7fff) => self
+                .mmc6_ram_access(address, write)
+                .then_some((1, Bank::Number(0))),
+            (Mmc3Board::Mmc6, _) => None,
+            (_, 0x6000..=0x7fff) => Some((8, Bank::Number(0))),
+            _ => None,
+        }
+    }
+
+    fn peek_cpu_read(&self, address: u16) -> Option<u8> {
+        // MMC6 reads 0 from a disabled half if the other half is enabled
+        match (self.board, address) {
+            (Mmc3Board::Mmc6, 0x7000..=0x7fff)
+                if !self.mmc6_ram_access(address, false)
+                    && self.mmc6_ram_access(address ^ 0x200, false) =>
+            {
+                Some(0)
+            }
+            _ => None,
+        }
+    }
+
     fn cpu_write(&mut self, address: u16, value: u8) {
         let even = address.is_multiple_of(2);
         match address {
@@ -62,6 +178,7 @@ impl Mapper for Mapper004 {
                 self.selected_register_index = (value & 0b0000_0111) as usize;
                 self.prg_mode_flip = value & 0b0100_0000 != 0;
                 self.chr_mode_flip = value & 0b1000_0000 != 0;
+                self.prg_ram_enabled = value & 0b0010_0000 != 0;
             }
             // Bank register data
             0x8000..=0x9fff if !even => {
@@ -75,6 +192,9 @@ impl Mapper for Mapper004 {
                     Mirroring::Horizontal
                 }
             }
+            0xa000..=0xbfff if !even && self.board == Mmc3Board::Mmc6 && self.prg_ram_enabled => {
+                self.prg_ram_protect = value;
+            }
             0xc000..=0xdfff if even => self.irq_latch_value = value,
             0xc000..=0xdfff if !even => self.irq_reload = true,
             // Disable IRQs and acknowledge them
@@ -97,35 +217,47 @@ impl Mapper for Mapper004 {
     }
 
     fn map_ppu(&self, address: u16) -> BankMapping {
-        let mut section_0 = [
-            Bank::Number(self.registers[0] & !1),
-            Bank::Number(self.registers[0] | 1),
-            Bank::Number(self.registers[1] & !1),
-            Bank::Number(self.registers[1] | 1),
-        ];
-        let mut section_1 = [
-            Bank::Number(self.registers[2]),
-            Bank::Number(self.registers[3]),
-            Bank::Number(self.registers[4]),
-            Bank::Number(self.registers[5]),
-        ];
-        if self.chr_mode_flip {
-            std::mem::swap(&mut section_0, &mut section_1);
+        let bank = self.chr_bank(address);
+        match self.board {
+            Mmc3Board::TxSrom => (1, Bank::Number(bank & 0x7f)),
+            Mmc3Board::Tqrom => (1, Bank::Number(bank & 0x3f)),
+            _ => (1, Bank::Number(bank)),
         }
-        let i = address as usize / 0x400;
-        match address {
-            0x0000..=0x0fff => (1, section_0[i]),
-            0x1000..=0x1fff => (1, section_1[i - 4]),
-            _ => unreachable!(),
+    }
+
+    fn peek_ppu_read(&self, address: u16) -> Option<u8> {
+        let index = self.chr_ram_index(address)?;
+        self.chr_ram.as_ref().map(|ram| ram[index])
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) -> bool {
+        let Some(index) = self.chr_ram_index(address) else {
+            return false;
+        };
+        if let Some(ram) = self.chr_ram.as_mut() {
+            ram[index] = value;
         }
+        true
+    }
+
+    fn map_nametable(&self, address: u16) -> Option<NametableMapping> {
+        // Bit 7 of the CHR bank at the same position in the first pattern table
+        (self.board == Mmc3Board::TxSrom).then(|| {
+            let bank = self.chr_bank(address & 0x0c00);
+            NametableMapping::Ciram(bank >> 7)
+        })
     }
 
     fn irq_status(&self) -> bool {
         self.irq.status
     }
 
+    fn internal_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.chr_ram.as_mut().map(|ram| ram.as_mut_slice())
+    }
+
     fn mirroring(&self) -> Option<Mirroring> {
-        Some(self.mirroring)
+        (self.board != Mmc3Board::TxSrom).then_some(self.mirroring)
     }
 }
 
@@ -133,13 +265,38 @@ impl Mapper for Mapper004 {
 mod test {
     use crate::{
         Cartridge,
-        cartridge::{Mirroring, mapper::test::create_test_catridge},
+        cartridge::{
+            CartridgeBanks, CartridgeHeader, Mirroring, NametableMapping,
+            mapper::test::{create_banks_rom, create_test_catridge},
+        },
     };
 
     fn setup_catridge() -> Cartridge {
         create_test_catridge(4, 8, &[&[1, 69], &[2], &[3], &[4]], 4, &[&[6], &[7], &[8]])
     }
 
+    fn setup_board(mapper_id: u16, submapper_id: u8) -> Cartridge {
+        let prg_rom = create_banks_rom(8, &[&[0], &[1], &[2], &[3]]);
+        let chr_banks: Vec<[u8; 1]> = (0..128).map(|i| [i]).collect();
+        let chr_banks: Vec<&[u8]> = chr_banks.iter().map(|bank| &bank[..]).collect();
+        let header = CartridgeHeader {
+            mapper_id,
+            submapper_id,
+            chr_mem_is_rom: true,
+            ..Default::default()
+        };
+        let banks = CartridgeBanks::new(vec![0; 1024], prg_rom, create_banks_rom(1, &chr_banks));
+        Cartridge::new(header, banks).unwrap()
+    }
+
+    /// Clocks the IRQ counter by toggling A12
+    fn clock_scanline(cartridge: &mut Cartridge) {
+        for _ in 0..3 {
+            cartridge.ppu_read(0x0000);
+        }
+        cartridge.ppu_read(0x1000);
+    }
+
     #[test]
     fn mirroring() {
         let mut cartridge = setup_catridge();
@@ -170,4 +327,89 @@ mod test {
         assert_eq!(cartridge.cpu_read(0xc000), Some(2));
         assert_eq!(cartridge.cpu_read(0xe000), Some(4));
     }
+
+    #[test]
+    fn mmc6_prg_ram_protect() {
+        let mut cartridge = setup_board(4, 1);
+        cartridge.cpu_write(0x7000, 1);
+        assert_eq!(cartridge.cpu_read(0x7000), None);
+
+        // Enable the RAM then allow reading and writing the low half only
+        cartridge.cpu_write(0x8000, 0b0010_0000);
+        cartridge.cpu_write(0xa001, 0b0011_0000);
+        cartridge.cpu_write(0x7000, 1);
+        cartridge.cpu_write(0x7200, 2);
+        assert_eq!(cartridge.cpu_read(0x7000), Some(1));
+        assert_eq!(cartridge.cpu_read(0x7200), Some(0));
+        // Mirrored every 1kb
+        assert_eq!(cartridge.cpu_read(0x7c00), Some(1));
+
+        cartridge.cpu_write(0xa001, 0b1111_0000);
+        cartridge.cpu_write(0x7200, 2);
+        assert_eq!(cartridge.cpu_read(0x7200), Some(2));
+
+        // Read only high half
+        cartridge.cpu_write(0xa001, 0b0111_0000);
+        cartridge.cpu_write(0x7200, 3);
+        assert_eq!(cartridge.cpu_read(0x7200), Some(2));
+    }
+
+    #[test]
+    fn txsrom_nametables() {
+        let mut cartridge = setup_board(118, 0);
+        cartridge.cpu_write(0x8000, 0);
+        cartridge.cpu_write(0x8001, 0x80);
+        cartridge.cpu_write(0x8000, 1);
+        cartridge.cpu_write(0x8001, 0x02);
+        assert_eq!(
+            cartridge.map_nametable(0x2000),
+            Some(NametableMapping::Ciram(1))
+        );
+        assert_eq!(
+            cartridge.map_nametable(0x2400),
+            Some(NametableMapping::Ciram(1))
+        );
+        assert_eq!(
+            cartridge.map_nametable(0x2800),
+            Some(NametableMapping::Ciram(0))
+        );
+        // Bit 7 isn't used for the CHR bank
+        assert_eq!(cartridge.ppu_read(0x0000), Some(0));
+        assert_eq!(cartridge.ppu_read(0x0800), Some(2));
+    }
+
+    #[test]
+    fn tqrom_chr_ram() {
+        let mut cartridge = setup_board(119, 0);
+        cartridge.cpu_write(0x8000, 2);
+        cartridge.cpu_write(0x8001, 5);
+        cartridge.cpu_write(0x8000, 3);
+        cartridge.cpu_write(0x8001, 0x41);
+        assert_eq!(cartridge.ppu_read(0x1000), Some(5));
+        cartridge.ppu_write(0x1000, 99);
+        assert_eq!(cartridge.ppu_read(0x1000), Some(5));
+
+        cartridge.ppu_write(0x1400, 42);
+        assert_eq!(cartridge.ppu_read(0x1400), Some(42));
+        cartridge.cpu_write(0x8001, 0x01);
+        assert_eq!(cartridge.ppu_read(0x1400), Some(1));
+    }
+
+    #[test]
+    fn irq_revisions() {
+        for (submapper_id, irq_on_zero_latch) in [(0, true), (4, false)] {
+            let mut cartridge = setup_board(4, submapper_id);
+            cartridge.cpu_write(0xc000, 0);
+            cartridge.cpu_write(0xc001, 0);
+            cartridge.cpu_write(0xe001, 0);
+            clock_scanline(&mut cartridge);
+            assert!(cartridge.irq_status(), "reload should trigger IRQ");
+
+            // Counter is already 0 so it gets reloaded to 0 without the reload flag
+            cartridge.cpu_write(0xe000, 0);
+            cartridge.cpu_write(0xe001, 0);
+            clock_scanline(&mut cartridge);
+            assert_eq!(cartridge.irq_status(), irq_on_zero_latch);
+        }
+    }
 }
@@ -0,0 +1,665 @@
+use crate::{
+    apu::{ExpansionChip, ExpansionSample, channels::pulse::PulseChannel},
+    cartridge::{Bank, BankMapping, FixedArray, Mapper, Mirroring, NametableMapping},
+};
+
+/// Dot where the emulated PPU fetches all the sprite patterns at once
+const SPRITE_FETCH_DOT: u16 = 261;
+/// Dot of the first background fetch for the next scanline
+const NEXT_LINE_FETCH_DOT: u16 = 328;
+/// CPU cycles without PPU reads before the mapper considers rendering to be stopped
+/// Hardware only needs 3 cycles but the emulated PPU doesn't do the garbage reads while
+/// fetching sprites, so the timeout has to cover the gap until the next background fetch
+/// (3 dots per CPU cycle at most)
+const IN_FRAME_TIMEOUT: u8 = ((NEXT_LINE_FETCH_DOT - SPRITE_FETCH_DOT).div_ceil(3) + 1) as u8;
+/// Pattern reads in a scanline before the sprite patterns get fetched
+const BACKGROUND_PATTERN_READS: u16 = 64;
+/// CPU cycles between the envelope and length counter clocks (240hz)
+const FRAME_CYCLES: u16 = 7457;
+
+/// INES designation for MMC5 boards
+/// https://www.nesdev.org/wiki/MMC5
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Mapper005 {
+    prg_mode: u8,
+    chr_mode: u8,
+    prg_ram_protect: [u8; 2],
+    exram_mode: u8,
+    /// Nametable slot for each of the 4 nametables, 2 bits each
+    nametable_slots: u8,
+    fill_tile: u8,
+    fill_attribute: u8,
+    /// $5113-$5117
+    prg_registers: [u8; 5],
+    /// $5120-$512b, $5120-$5127 is used for sprites and $5128-$512b for the background
+    /// Each one gets the upper bits from $5130 when it's written
+    chr_registers: [u16; 12],
+    chr_upper_bits: u8,
+    last_written_background_chr: bool,
+    tall_sprites: bool,
+
+    split_control: u8,
+    split_scroll: u8,
+    split_bank: u8,
+
+    irq_compare: u8,
+    irq_enabled: bool,
+    irq_pending: bool,
+    in_frame: bool,
+    scanline: u8,
+
+    multiplicand: u8,
+    multiplier: u8,
+
+    exram: FixedArray<u8, 0x400>,
+
+    // Tracking of the PPU reads
+    last_ppu_address: u16,
+    same_address_reads: u8,
+    idle_cycles: u8,
+    pattern_reads: u16,
+    fetching_sprites: bool,
+    /// Index of the background tile being fetched in the current scanline
+    tile: u8,
+    /// Byte in exram for the tile being fetched, used by extended attribute mode
+    tile_exram: u8,
+    in_split: bool,
+
+    pulse_0: PulseChannel<0>,
+    pulse_1: PulseChannel<1>,
+    pcm: u8,
+    /// The PCM channel plays what the CPU reads from $8000-$bfff instead of $5011 writes
+    pcm_read_mode: bool,
+    pcm_irq_enabled: bool,
+    pcm_irq_pending: bool,
+    odd_cycle: bool,
+    frame_cycles: u16,
+}
+
+impl Default for Mapper005 {
+    fn default() -> Self {
+        Self {
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            exram_mode: 0,
+            nametable_slots: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_registers: [0, 0, 0, 0, 0xff],
+            chr_registers: [0; 12],
+            chr_upper_bits: 0,
+            last_written_background_chr: false,
+            tall_sprites: false,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            irq_compare: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            in_frame: false,
+            scanline: 0,
+            multiplicand: 0xff,
+            multiplier: 0xff,
+            exram: FixedArray::default(),
+            last_ppu_address: 0,
+            same_address_reads: 0,
+            idle_cycles: 0,
+            pattern_reads: 0,
+            fetching_sprites: false,
+            tile: 0,
+            tile_exram: 0,
+            in_split: false,
+            pulse_0: PulseChannel::default(),
+            pulse_1: PulseChannel::default(),
+            pcm: 0,
+            pcm_read_mode: false,
+            pcm_irq_enabled: false,
+            pcm_irq_pending: false,
+            odd_cycle: false,
+            frame_cycles: 0,
+        }
+    }
+}
+
+impl Mapper005 {
+    /// Mapping for $6000-$ffff and if it's mapped to PRG ROM
+    fn map_prg(&self, address: u16) -> Option<(BankMapping, bool)> {
+        let bank_8kb = |register: u8| {
+            if register & 0x80 != 0 {
+                ((8, Bank::Number(register & 0x7f)), true)
+            } else {
+                ((8, Bank::Number(register & 0x07)), false)
+            }
+        };
+        let bank_16kb = |register: u8| {
+            if register & 0x80 != 0 {
+                ((16, Bank::Number((register & 0x7f) >> 1)), true)
+            } else {
+                ((16, Bank::Number((register & 0x07) >> 1)), false)
+            }
+        };
+        let rom_8kb = |register: u8| bank_8kb(register | 0x80);
+        let registers = &self.prg_registers;
+
+        Some(match (self.prg_mode, address) {
+            (_, 0x6000..=0x7fff) => bank_8kb(registers[0] & 0x7f),
+            (0, 0x8000..=0xffff) => ((32, Bank::Number((registers[4] & 0x7f) >> 2)), true),
+            (1, 0x8000..=0xbfff) | (2, 0x8000..=0xbfff) => bank_16kb(registers[2]),
+            (1, 0xc000..=0xffff) => bank_16kb(registers[4] | 0x80),
+            (2, 0xc000..=0xdfff) | (3, 0xc000..=0xdfff) => bank_8kb(registers[3]),
+            (3, 0x8000..=0x9fff) => bank_8kb(registers[1]),
+            (3, 0xa000..=0xbfff) => bank_8kb(registers[2]),
+            (_, 0xe000..=0xffff) => rom_8kb(registers[4]),
+            _ => return None,
+        })
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect == [0b10, 0b01]
+    }
+
+    fn nametable_slot(&self, address: u16) -> u8 {
+        (self.nametable_slots >> (((address >> 10) & 0b11) * 2)) & 0b11
+    }
+
+    /// If the PPU is fetching the background while rendering
+    fn fetching_background(&self) -> bool {
+        self.in_frame && !self.fetching_sprites
+    }
+
+    fn use_background_chr(&self) -> bool {
+        match (self.tall_sprites, self.in_frame) {
+            (false, _) => false,
+            (true, true) => !self.fetching_sprites,
+            (true, false) => self.last_written_background_chr,
+        }
+    }
+
+    fn split_y(&self) -> u16 {
+        (self.split_scroll as u16 + self.scanline as u16) % 240
+    }
+
+    fn in_split_region(&self, tile: u8) -> bool {
+        let threshold = self.split_control & 0x1f;
+        if self.split_control & 0x40 != 0 {
+            tile >= threshold
+        } else {
+            tile < threshold
+        }
+    }
+
+    fn read_split(&self, address: u16) -> u8 {
+        let coarse_y = self.split_y() / 8;
+        let coarse_x = (self.tile % 32) as u16;
+        if address & 0x3ff < 0x3c0 {
+            self.exram[(coarse_y * 32 + coarse_x) as usize]
+        } else {
+            let attribute = self.exram[(0x3c0 + (coarse_y / 4) * 8 + coarse_x / 4) as usize];
+            let shift = (coarse_y & 0b10) << 1 | (coarse_x & 0b10);
+            ((attribute >> shift) & 0b11) * 0x55
+        }
+    }
+
+    fn detect_scanline(&mut self) {
+        if !self.in_frame {
+            self.in_frame = true;
+            self.scanline = 0;
+            self.irq_pending = false;
+        } else {
+            self.scanline = self.scanline.wrapping_add(1);
+            if self.scanline == self.irq_compare && self.irq_compare != 0 {
+                self.irq_pending = true;
+            }
+        }
+        self.pattern_reads = 0;
+        self.fetching_sprites = false;
+    }
+
+    fn status(&self) -> u8 {
+        (self.irq_pending as u8) << 7 | (self.in_frame as u8) << 6
+    }
+}
+
+impl Mapper for Mapper005 {
+    fn map_cpu_read(&self, address: u16) -> Option<BankMapping> {
+        match self.map_prg(address) {
+            Some((mapping, true)) if address >= 0x8000 => Some(mapping),
+            _ => None,
+        }
+    }
+
+    fn map_prg_ram(&self, address: u16, write: bool) -> Option<BankMapping> {
+        if write && !self.prg_ram_writable() {
+            return None;
+        }
+        match self.map_prg(address) {
+            Some((mapping, false)) => Some(mapping),
+            _ => None,
+        }
+    }
+
+    fn peek_cpu_read(&self, address: u16) -> Option<u8> {
+        let product = self.multiplicand as u16 * self.multiplier as u16;
+        match address {
+            0x5015 => Some(
+                self.pulse_0.length_counter.playing() as u8
+                    | (self.pulse_1.length_counter.playing() as u8) << 1,
+            ),
+            0x5010 => Some((self.pcm_irq_pending as u8) << 7),
+            0x5204 => Some(self.status()),
+            0x5205 => Some(product as u8),
+            0x5206 => Some((product >> 8) as u8),
+            0x5c00..=0x5fff if self.exram_mode >= 2 => Some(self.exram[address as usize - 0x5c00]),
+            _ => None,
+        }
+    }
+
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        let value = self.peek_cpu_read(address);
+        match address {
+            0x5010 => self.pcm_irq_pending = false,
+            0x5204 => self.irq_pending = false,
+            // Reading the NMI vector means the frame has ended
+            0xfffa | 0xfffb => self.in_frame = false,
+            _ => (),
+        }
+        value
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            // Snoop the PPU registers for the sprite size and rendering being disabled
+            0x2000..=0x3fff if address & 0b111 == 0 => self.tall_sprites = value & 0x20 != 0,
+            0x2000..=0x3fff if address & 0b111 == 1 && value & 0x18 == 0 => self.in_frame = false,
+            // Pulses have no sweep units
+            0x5001 | 0x5005 => (),
+            0x5000..=0x5003 => self.pulse_0.write(address - 0x1000, value, 0),
+            0x5004..=0x5007 => self.pulse_1.write(address - 0x1000, value, 1),
+            0x5010 => {
+                self.pcm_read_mode = value & 0x01 != 0;
+                self.pcm_irq_enabled = value & 0x80 != 0;
+            }
+            0x5011 if !self.pcm_read_mode && value != 0 => self.pcm = value,
+            0x5015 => {
+                self.pulse_0.length_counter.set_enabled(value & 0b01 != 0);
+                self.pulse_1.length_counter.set_enabled(value & 0b10 != 0);
+            }
+            0x5100 => self.prg_mode = value & 0b11,
+            0x5101 => self.chr_mode = value & 0b11,
+            0x5102 => self.prg_ram_protect[0] = value & 0b11,
+            0x5103 => self.prg_ram_protect[1] = value & 0b11,
+            0x5104 => self.exram_mode = value & 0b11,
+            0x5105 => self.nametable_slots = value,
+            0x5106 => self.fill_tile = value,
+            0x5107 => self.fill_attribute = value & 0b11,
+            0x5113..=0x5117 => self.prg_registers[address as usize - 0x5113] = value,
+            0x5120..=0x512b => {
+                self.chr_registers[address as usize - 0x5120] =
+                    value as u16 | (self.chr_upper_bits as u16) << 8;
+                self.last_written_background_chr = address >= 0x5128;
+            }
+            0x5130 => self.chr_upper_bits = value & 0b11,
+            0x5200 => self.split_control = value,
+            0x5201 => self.split_scroll = value,
+            0x5202 => self.split_bank = value,
+            0x5203 => self.irq_compare = value,
+            0x5204 => self.irq_enabled = value & 0x80 != 0,
+            0x5205 => self.multiplicand = value,
+            0x5206 => self.multiplier = value,
+            // Exram can only be written to while rendering in the nametable modes
+            0x5c00..=0x5fff if self.exram_mode <= 1 => {
+                self.exram[address as usize - 0x5c00] = if self.in_frame { value } else { 0 };
+            }
+            0x5c00..=0x5fff if self.exram_mode == 2 => {
+                self.exram[address as usize - 0x5c00] = value
+            }
+            _ => (),
+        }
+    }
+
+    fn monitor_ppu(&mut self, address: u16) {
+        self.idle_cycles = 0;
+        if address == self.last_ppu_address {
+            self.same_address_reads = self.same_address_reads.saturating_add(1);
+        } else {
+            self.same_address_reads = 0;
+            self.last_ppu_address = address;
+        }
+
+        match address {
+            0x0000..=0x1fff if self.in_frame => {
+                self.pattern_reads += 1;
+                if self.pattern_reads == BACKGROUND_PATTERN_READS + 1 {
+                    self.fetching_sprites = true;
+                }
+            }
+            0x2000..=0x3eff if address & 0x3ff < 0x3c0 => {
+                // Scanlines end with the same nametable byte being read 3 times
+                if self.same_address_reads == 2 {
+                    self.detect_scanline();
+                    self.tile = 1;
+                } else if self.fetching_sprites {
+                    self.fetching_sprites = false;
+                    self.tile = 0;
+                } else {
+                    self.tile = self.tile.wrapping_add(1);
+                }
+                self.tile_exram = self.exram[address as usize & 0x3ff];
+                self.in_split = self.split_control & 0x80 != 0
+                    && self.exram_mode <= 1
+                    && self.in_split_region(self.tile);
+            }
+            _ => (),
+        }
+    }
+
+    fn map_ppu(&self, address: u16) -> BankMapping {
+        if self.fetching_background() {
+            if self.in_split {
+                return (4, Bank::Number(self.split_bank));
+            }
+            if self.exram_mode == 1 {
+                let bank = (self.tile_exram & 0x3f) | self.chr_upper_bits << 6;
+                return (4, Bank::Number(bank));
+            }
+        }
+
+        let i = address as usize / 0x400;
+        let registers = &self.chr_registers;
+        let register = if self.use_background_chr() {
+            match self.chr_mode {
+                0 | 1 => registers[11],
+                2 => registers[9 + ((i / 2) & 1) * 2],
+                _ => registers[8 + (i & 0b11)],
+            }
+        } else {
+            match self.chr_mode {
+                0 => registers[7],
+                1 => registers[3 + (i / 4) * 4],
+                2 => registers[1 + (i / 2) * 2],
+                _ => registers[i],
+            }
+        };
+        let kb = 8 >> self.chr_mode;
+        (kb, Bank::Wide(register))
+    }
+
+    fn chr_read_address(&self, address: u16) -> u16 {
+        // The split region has its own vertical scroll
+        if self.fetching_background() && self.in_split {
+            (address & !0b111) | (self.split_y() & 0b111)
+        } else {
+            address
+        }
+    }
+
+    fn monitor_cpu_read(&mut self, address: u16, value: u8) {
+        if self.pcm_read_mode && (0x8000..=0xbfff).contains(&address) {
+            // Reading a 0 doesn't change the output and raises the PCM IRQ instead
+            if value == 0 {
+                self.pcm_irq_pending = true;
+            } else {
+                self.pcm = value;
+            }
+        }
+    }
+
+    fn map_nametable(&self, address: u16) -> Option<NametableMapping> {
+        let attribute = address & 0x3ff >= 0x3c0;
+        if self.fetching_background() && (self.in_split || (self.exram_mode == 1 && attribute)) {
+            return Some(NametableMapping::Mapper);
+        }
+        Some(match self.nametable_slot(address) {
+            page @ (0 | 1) => NametableMapping::Ciram(page),
+            _ => NametableMapping::Mapper,
+        })
+    }
+
+    fn peek_nametable(&self, address: u16) -> u8 {
+        let attribute = address & 0x3ff >= 0x3c0;
+        if self.fetching_background() {
+            if self.in_split {
+                return self.read_split(address);
+            }
+            if self.exram_mode == 1 && attribute {
+                return (self.tile_exram >> 6) * 0x55;
+            }
+        }
+        match self.nametable_slot(address) {
+            2 if self.exram_mode <= 1 => self.exram[address as usize & 0x3ff],
+            3 if attribute => self.fill_attribute * 0x55,
+            3 => self.fill_tile,
+            _ => 0,
+        }
+    }
+
+    fn write_nametable(&mut self, address: u16, value: u8) {
+        if self.nametable_slot(address) == 2 && self.exram_mode <= 1 {
+            self.exram[address as usize & 0x3ff] = value;
+        }
+    }
+
+    fn clock(&mut self) {
+        self.idle_cycles = self.idle_cycles.saturating_add(1);
+        if self.idle_cycles >= IN_FRAME_TIMEOUT {
+            self.in_frame = false;
+        }
+
+        self.odd_cycle = !self.odd_cycle;
+        if self.odd_cycle {
+            self.pulse_0.sequencer.clock();
+            self.pulse_1.sequencer.clock();
+        }
+        self.frame_cycles += 1;
+        if self.frame_cycles >= FRAME_CYCLES {
+            self.frame_cycles = 0;
+            self.pulse_0.envelope.clock();
+            self.pulse_1.envelope.clock();
+            self.pulse_0.length_counter.clock();
+            self.pulse_1.length_counter.clock();
+        }
+    }
+
+    fn audio_sample(&self) -> Option<ExpansionSample> {
+        // Mixed like the APU channels https://www.nesdev.org/wiki/APU_Mixer
+        let pulse = (self.pulse_0.sample() + self.pulse_1.sample()) as f32;
+        let pulse_out = (95.88 * pulse) / (8128. + 100. * pulse);
+        let pcm = self.pcm as f32 / 2. / 22638.;
+        let pcm_out = (159.79 * pcm) / (1. + 100. * pcm);
+        Some((ExpansionChip::Mmc5, pulse_out + pcm_out))
+    }
+
+    fn irq_status(&self) -> bool {
+        (self.irq_enabled && self.irq_pending) || (self.pcm_irq_enabled && self.pcm_irq_pending)
+    }
+
+    fn internal_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(self.exram.as_mut_slice())
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        match self.nametable_slots {
+            0x44 => Some(Mirroring::Vertical),
+            0x50 => Some(Mirroring::Horizontal),
+            0x00 => Some(Mirroring::SingleScreenLow),
+            0x55 => Some(Mirroring::SingleScreenHigh),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Cartridge,
+        cartridge::{NametableMapping, mapper::test::create_test_catridge},
+        power_on::{PowerOnConfig, RamState},
+    };
+
+    fn setup_catridge() -> Cartridge {
+        create_test_catridge(
+            5,
+            8,
+            &[&[0], &[1], &[2], &[3], &[4], &[5], &[6], &[7]],
+            1,
+            &[&[0], &[1], &[2], &[3], &[4], &[5], &[6], &[7]],
+        )
+    }
+
+    #[test]
+    fn power_on_exram() {
+        let mut cartridge = setup_catridge();
+        cartridge.power_on(&PowerOnConfig {
+            ram_state: RamState::Ones,
+            seed: 0,
+        });
+        cartridge.cpu_write(0x5104, 2);
+        assert_eq!(cartridge.cpu_read(0x5c00), Some(0xff));
+    }
+
+    #[test]
+    fn prg_modes() {
+        let mut cartridge = setup_catridge();
+        // Starts in mode 3 with the last bank at $e000
+        assert_eq!(cartridge.cpu_read(0xe000), Some(7));
+
+        cartridge.cpu_write(0x5114, 0x81);
+        cartridge.cpu_write(0x5115, 0x82);
+        cartridge.cpu_write(0x5116, 0x83);
+        cartridge.cpu_write(0x5117, 0x84);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(1));
+        assert_eq!(cartridge.cpu_read(0xa000), Some(2));
+        assert_eq!(cartridge.cpu_read(0xc000), Some(3));
+        assert_eq!(cartridge.cpu_read(0xe000), Some(4));
+
+        cartridge.cpu_write(0x5100, 1);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(2));
+        assert_eq!(cartridge.cpu_read(0xc000), Some(4));
+
+        cartridge.cpu_write(0x5100, 0);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(4));
+    }
+
+    #[test]
+    fn prg_ram_protect() {
+        let mut cartridge = setup_catridge();
+        cartridge.cpu_write(0x6000, 5);
+        assert_eq!(cartridge.cpu_read(0x6000), Some(0));
+        cartridge.cpu_write(0x5102, 0b10);
+        cartridge.cpu_write(0x5103, 0b01);
+        cartridge.cpu_write(0x6000, 5);
+        assert_eq!(cartridge.cpu_read(0x6000), Some(5));
+    }
+
+    #[test]
+    fn multiplier() {
+        let mut cartridge = setup_catridge();
+        cartridge.cpu_write(0x5205, 200);
+        cartridge.cpu_write(0x5206, 100);
+        assert_eq!(cartridge.cpu_read(0x5205), Some((20000 & 0xff) as u8));
+        assert_eq!(cartridge.cpu_read(0x5206), Some((20000 >> 8) as u8));
+    }
+
+    #[test]
+    fn nametables() {
+        let mut cartridge = setup_catridge();
+        // Ciram 0, ciram 1, exram and fill mode
+        cartridge.cpu_write(0x5105, 0b11_10_01_00);
+        cartridge.cpu_write(0x5104, 2);
+        cartridge.cpu_write(0x5106, 0x42);
+        cartridge.cpu_write(0x5107, 2);
+        assert_eq!(
+            cartridge.map_nametable(0x2000),
+            Some(NametableMapping::Ciram(0))
+        );
+        assert_eq!(
+            cartridge.map_nametable(0x2400),
+            Some(NametableMapping::Ciram(1))
+        );
+        assert_eq!(
+            cartridge.map_nametable(0x2c00),
+            Some(NametableMapping::Mapper)
+        );
+        assert_eq!(cartridge.peek_nametable(0x2c00), 0x42);
+        assert_eq!(cartridge.peek_nametable(0x2fc0), 0xaa);
+
+        // Exram is only used as a nametable in modes 0 and 1
+        cartridge.cpu_write(0x5c05, 9);
+        assert_eq!(cartridge.peek_nametable(0x2805), 0);
+        cartridge.cpu_write(0x5104, 0);
+        cartridge.write_nametable(0x2805, 9);
+        assert_eq!(cartridge.peek_nametable(0x2805), 9);
+    }
+
+    #[test]
+    fn chr_upper_bits() {
+        // The first byte of each 1kb bank is the upper bits of its bank number
+        let banks: Vec<[u8; 1]> = (0..512).map(|i| [(i >> 8) as u8]).collect();
+        let banks: Vec<&[u8]> = banks.iter().map(|bank| bank.as_slice()).collect();
+        let mut cartridge = create_test_catridge(5, 8, &[&[0]], 1, &banks);
+        cartridge.cpu_write(0x5101, 3);
+        cartridge.cpu_write(0x5120, 2);
+        // Only applies to the registers written after it
+        cartridge.cpu_write(0x5130, 1);
+        cartridge.cpu_write(0x5121, 2);
+        assert_eq!(cartridge.ppu_read(0x0000), Some(0));
+        assert_eq!(cartridge.ppu_read(0x0400), Some(1));
+    }
+
+    #[test]
+    fn pcm_read_mode() {
+        let mut cartridge = create_test_catridge(5, 8, &[&[0], &[0x40]], 1, &[&[0]]);
+        cartridge.cpu_write(0x5010, 0x81);
+        cartridge.cpu_write(0x5114, 0x81);
+        cartridge.cpu_read(0x8000);
+        let (_, output) = cartridge.audio_samples()[0].unwrap();
+        assert!(output > 0.);
+        assert!(!cartridge.irq_status());
+
+        // Reading a 0 raises the IRQ
+        cartridge.cpu_write(0x5114, 0x80);
+        cartridge.cpu_read(0x8000);
+        assert!(cartridge.irq_status());
+        assert_eq!(cartridge.cpu_read(0x5010), Some(0x80));
+        assert!(!cartridge.irq_status());
+    }
+
+    #[test]
+    fn split_scroll() {
+        let mut split_bank = [0; 0x14];
+        split_bank[0x13] = 0x55;
+        let mut cartridge = create_test_catridge(5, 8, &[&[0]], 4, &[&[0], &split_bank]);
+        // Split on the left 2 tiles from CHR bank 1, 3 lines down
+        cartridge.cpu_write(0x5200, 0x82);
+        cartridge.cpu_write(0x5201, 3);
+        cartridge.cpu_write(0x5202, 1);
+        for _ in 0..3 {
+            cartridge.ppu_read(0x2000);
+        }
+        // Fine y of the PPU is replaced with the split's
+        assert_eq!(cartridge.ppu_read(0x0010), Some(0x55));
+    }
+
+    #[test]
+    fn scanline_irq() {
+        let mut cartridge = setup_catridge();
+        cartridge.cpu_write(0x5203, 2);
+        cartridge.cpu_write(0x5204, 0x80);
+        let end_scanline = |cartridge: &mut Cartridge| {
+            for _ in 0..3 {
+                cartridge.ppu_read(0x2000);
+            }
+            cartridge.ppu_read(0x23c0);
+        };
+
+        end_scanline(&mut cartridge);
+        assert_eq!(cartridge.cpu_read(0x5204), Some(0x40));
+        end_scanline(&mut cartridge);
+        assert!(!cartridge.irq_status());
+        end_scanline(&mut cartridge);
+        assert!(cartridge.irq_status());
+        assert_eq!(cartridge.cpu_read(0x5204), Some(0xc0));
+        assert!(!cartridge.irq_status());
+    }
+}
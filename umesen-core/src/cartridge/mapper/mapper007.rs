@@ -0,0 +1,53 @@
+use crate::cartridge::{Bank, BankMapping, Mapper, Mirroring};
+
+/// INES designation for AxROM boards
+/// https://www.nesdev.org/wiki/AxROM
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Mapper007 {
+    prg_bank: u8,
+    single_screen_high: bool,
+}
+
+impl Mapper for Mapper007 {
+    fn map_cpu_read(&self, address: u16) -> Option<BankMapping> {
+        Some(match address {
+            0x8000..=0xffff => (32, Bank::Number(self.prg_bank)),
+            _ => return None,
+        })
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x8000..=0xffff = address {
+            self.prg_bank = value & 0b111;
+            self.single_screen_high = value & 0b1_0000 != 0;
+        }
+    }
+
+    fn map_ppu(&self, _: u16) -> BankMapping {
+        (8, Bank::Number(0))
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(if self.single_screen_high {
+            Mirroring::SingleScreenHigh
+        } else {
+            Mirroring::SingleScreenLow
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cartridge::{Mirroring, mapper::test::create_test_catridge};
+
+    #[test]
+    fn test() {
+        let mut cartridge = create_test_catridge(7, 32, &[&[1], &[2]], 8, &[&[3]]);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(1));
+        assert_eq!(cartridge.mirroring(), Mirroring::SingleScreenLow);
+
+        cartridge.cpu_write(0x8000, 0b1_0001);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(2));
+        assert_eq!(cartridge.mirroring(), Mirroring::SingleScreenHigh);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::cartridge::{Bank, BankMapping, Mapper, Mirroring};
+
+/// INES designation for MMC2 boards, mapper 10 is MMC4 which uses 16kb PRG banks
+/// https://www.nesdev.org/wiki/MMC2
+/// https://www.nesdev.org/wiki/MMC4
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Mapper009 {
+    mmc4: bool,
+    prg_bank: u8,
+    /// Banks for $0000 and $1000 when the latch is $fd or $fe
+    chr_banks: [[u8; 2]; 2],
+    /// If the latch of each pattern table is $fe
+    latches: [bool; 2],
+    /// Latches change after the read that triggers them
+    pending_latch: Option<(usize, bool)>,
+    mirroring: Mirroring,
+}
+
+impl Mapper009 {
+    pub fn mmc4() -> Self {
+        Self {
+            mmc4: true,
+            ..Default::default()
+        }
+    }
+}
+
+impl Mapper for Mapper009 {
+    fn map_cpu_read(&self, address: u16) -> Option<BankMapping> {
+        Some(match address {
+            0x8000..=0xbfff if self.mmc4 => (16, Bank::Number(self.prg_bank)),
+            0xc000..=0xffff if self.mmc4 => (16, Bank::FromLast(0)),
+            0x8000..=0x9fff => (8, Bank::Number(self.prg_bank)),
+            0xa000..=0xffff => (8, Bank::FromLast(3 - ((address >> 13) as u8 & 0b11))),
+            _ => return None,
+        })
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0xa000..=0xafff => self.prg_bank = value & 0x0f,
+            0xb000..=0xbfff => self.chr_banks[0][0] = value & 0x1f,
+            0xc000..=0xcfff => self.chr_banks[0][1] = value & 0x1f,
+            0xd000..=0xdfff => self.chr_banks[1][0] = value & 0x1f,
+            0xe000..=0xefff => self.chr_banks[1][1] = value & 0x1f,
+            0xf000..=0xffff => {
+                self.mirroring = if value & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn monitor_ppu(&mut self, address: u16) {
+        if let Some((table, latch)) = self.pending_latch.take() {
+            self.latches[table] = latch;
+        }
+        // MMC2 only checks a single address for the first pattern table
+        let first_table_end = if self.mmc4 { 0x7 } else { 0x0 };
+        self.pending_latch = match address {
+            0x0fd8 => Some((0, false)),
+            0x0fe8 => Some((0, true)),
+            0x0fd9..=0x0fdf if address - 0x0fd8 <= first_table_end => Some((0, false)),
+            0x0fe9..=0x0fef if address - 0x0fe8 <= first_table_end => Some((0, true)),
+            0x1fd8..=0x1fdf => Some((1, false)),
+            0x1fe8..=0x1fef => Some((1, true)),
+            _ => None,
+        };
+    }
+
+    fn map_ppu(&self, address: u16) -> BankMapping {
+        let table = address as usize / 0x1000;
+        (
+            4,
+            Bank::Number(self.chr_banks[table][self.latches[table] as usize]),
+        )
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(self.mirroring)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cartridge::mapper::test::create_test_catridge;
+
+    #[test]
+    fn prg_rom() {
+        let mut cartridge = create_test_catridge(9, 8, &[&[1], &[2], &[3], &[4], &[5]], 4, &[&[6]]);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(1));
+        assert_eq!(cartridge.cpu_read(0xa000), Some(3));
+        assert_eq!(cartridge.cpu_read(0xe000), Some(5));
+        cartridge.cpu_write(0xa000, 1);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(2));
+
+        let mut cartridge = create_test_catridge(10, 16, &[&[1], &[2], &[3]], 4, &[&[6]]);
+        cartridge.cpu_write(0xa000, 1);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(2));
+        assert_eq!(cartridge.cpu_read(0xc000), Some(3));
+    }
+
+    #[test]
+    fn chr_latch() {
+        let mut cartridge = create_test_catridge(9, 8, &[&[0]], 4, &[&[0], &[1], &[2], &[3]]);
+        cartridge.cpu_write(0xb000, 0);
+        cartridge.cpu_write(0xc000, 1);
+        cartridge.cpu_write(0xd000, 2);
+        cartridge.cpu_write(0xe000, 3);
+        assert_eq!(cartridge.ppu_read(0x0000), Some(0));
+        assert_eq!(cartridge.ppu_read(0x1000), Some(2));
+
+        // The latch changes after the read of tile $fe
+        cartridge.ppu_read(0x0fe8);
+        assert_eq!(cartridge.ppu_read(0x0000), Some(1));
+        cartridge.ppu_read(0x1fe8);
+        assert_eq!(cartridge.ppu_read(0x1000), Some(3));
+        cartridge.ppu_read(0x1fdf);
+        assert_eq!(cartridge.ppu_read(0x1000), Some(2));
+
+        // MMC2 only uses $0fd8 for the first pattern table
+        cartridge.ppu_read(0x0fd9);
+        assert_eq!(cartridge.ppu_read(0x0000), Some(1));
+    }
+}
@@ -0,0 +1,45 @@
+use crate::cartridge::{Bank, BankMapping, Mapper};
+
+/// INES designation for Color Dreams boards
+/// https://www.nesdev.org/wiki/Color_Dreams
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Mapper011 {
+    prg_bank: u8,
+    chr_bank: u8,
+}
+
+impl Mapper for Mapper011 {
+    fn map_cpu_read(&self, address: u16) -> Option<BankMapping> {
+        Some(match address {
+            0x8000..=0xffff => (32, Bank::Number(self.prg_bank)),
+            _ => return None,
+        })
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x8000..=0xffff = address {
+            self.prg_bank = value & 0b11;
+            self.chr_bank = value >> 4;
+        }
+    }
+
+    fn map_ppu(&self, _: u16) -> BankMapping {
+        (8, Bank::Number(self.chr_bank))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cartridge::mapper::test::create_test_catridge;
+
+    #[test]
+    fn test() {
+        let mut cartridge = create_test_catridge(11, 32, &[&[1], &[2]], 8, &[&[3], &[4], &[5]]);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(1));
+        assert_eq!(cartridge.ppu_read(0x0000), Some(3));
+
+        cartridge.cpu_write(0x8000, 0x21);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(2));
+        assert_eq!(cartridge.ppu_read(0x0000), Some(5));
+    }
+}
@@ -0,0 +1,313 @@
+use crate::{
+    apu::{ExpansionChip, ExpansionSample},
+    cartridge::{Bank, BankMapping, FixedArray, Mapper, NametableMapping},
+};
+
+const SOUND_RAM_SIZE: usize = 0x80;
+/// Start of the channel registers in sound RAM, each channel uses 8 bytes
+const CHANNEL_REGISTERS_START: usize = 0x40;
+/// CPU cycles between the updates of each channel
+const CHANNEL_UPDATE_CYCLES: u8 = 15;
+/// Brings a channel at full volume to about the same level as an APU pulse channel
+const OUTPUT_SCALE: f32 = 0.0015;
+const IRQ_COUNTER_MAX: u16 = 0x7fff;
+
+/// INES designation for Namco 163 boards
+/// https://www.nesdev.org/wiki/Namco_163
+/// https://www.nesdev.org/wiki/Namco_163_audio
+/// CHR banks that map the pattern tables to nametable RAM are not supported
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Mapper019 {
+    prg_banks: [u8; 3],
+    /// 8 pattern table banks followed by 4 nametable banks
+    chr_banks: [u8; 12],
+    sound_disabled: bool,
+
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_status: bool,
+
+    /// Sound RAM, used for the wavetables and the channel registers
+    /// Some games also use it for battery backed saves
+    sound_ram: FixedArray<u8, SOUND_RAM_SIZE>,
+    sound_address: u8,
+    auto_increment: bool,
+    channel_outputs: [i8; 8],
+    current_channel: u8,
+    update_cycles: u8,
+}
+
+impl Mapper019 {
+    /// Number of channels enabled, channels 7 down to 8 - count are used
+    fn channel_count(&self) -> u8 {
+        ((self.sound_ram[0x7f] >> 4) & 0b111) + 1
+    }
+
+    fn update_channel(&mut self, channel: usize) {
+        let registers = CHANNEL_REGISTERS_START + channel * 8;
+        let ram = &mut self.sound_ram;
+        let frequency = ram[registers] as u32
+            | (ram[registers + 2] as u32) << 8
+            | (ram[registers + 4] as u32 & 0b11) << 16;
+        let length = (256 - (ram[registers + 4] as u32 & 0xfc)) << 16;
+        let mut phase = ram[registers + 1] as u32
+            | (ram[registers + 3] as u32) << 8
+            | (ram[registers + 5] as u32) << 16;
+
+        phase = (phase + frequency) % length;
+        ram[registers + 1] = phase as u8;
+        ram[registers + 3] = (phase >> 8) as u8;
+        ram[registers + 5] = (phase >> 16) as u8;
+
+        // Samples are 4 bits with the low nibble first
+        let sample_address = ((phase >> 16) + ram[registers + 6] as u32) as usize & 0xff;
+        let byte = ram[sample_address / 2];
+        let sample = if sample_address.is_multiple_of(2) {
+            byte & 0x0f
+        } else {
+            byte >> 4
+        };
+        let volume = ram[registers + 7] & 0x0f;
+        self.channel_outputs[channel] = (sample as i8 - 8) * volume as i8;
+    }
+}
+
+impl Mapper for Mapper019 {
+    fn map_cpu_read(&self, address: u16) -> Option<BankMapping> {
+        Some(match address {
+            0x8000..=0xdfff => (
+                8,
+                Bank::Number(self.prg_banks[(address as usize - 0x8000) / 0x2000]),
+            ),
+            0xe000..=0xffff => (8, Bank::FromLast(0)),
+            _ => return None,
+        })
+    }
+
+    fn peek_cpu_read(&self, address: u16) -> Option<u8> {
+        match address {
+            0x4800..=0x4fff => Some(self.sound_ram[self.sound_address as usize]),
+            0x5000..=0x57ff => Some(self.irq_counter as u8),
+            0x5800..=0x5fff => Some((self.irq_enabled as u8) << 7 | (self.irq_counter >> 8) as u8),
+            _ => None,
+        }
+    }
+
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        let value = self.peek_cpu_read(address);
+        if let 0x4800..=0x4fff = address
+            && self.auto_increment
+        {
+            self.sound_address = (self.sound_address + 1) % SOUND_RAM_SIZE as u8;
+        }
+        value
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x4800..=0x4fff => {
+                self.sound_ram[self.sound_address as usize] = value;
+                if self.auto_increment {
+                    self.sound_address = (self.sound_address + 1) % SOUND_RAM_SIZE as u8;
+                }
+            }
+            0x5000..=0x57ff => {
+                self.irq_counter = (self.irq_counter & 0x7f00) | value as u16;
+                self.irq_status = false;
+            }
+            0x5800..=0x5fff => {
+                self.irq_counter = (self.irq_counter & 0x00ff) | (value as u16 & 0x7f) << 8;
+                self.irq_enabled = value & 0x80 != 0;
+                self.irq_status = false;
+            }
+            0x8000..=0xdfff => self.chr_banks[(address as usize - 0x8000) / 0x800] = value,
+            0xe000..=0xe7ff => {
+                self.prg_banks[0] = value & 0x3f;
+                self.sound_disabled = value & 0x40 != 0;
+            }
+            0xe800..=0xefff => self.prg_banks[1] = value & 0x3f,
+            0xf000..=0xf7ff => self.prg_banks[2] = value & 0x3f,
+            0xf800..=0xffff => {
+                self.sound_address = value & 0x7f;
+                self.auto_increment = value & 0x80 != 0;
+            }
+            _ => (),
+        }
+    }
+
+    fn map_ppu(&self, address: u16) -> BankMapping {
+        (1, Bank::Number(self.chr_banks[address as usize / 0x400]))
+    }
+
+    fn map_nametable(&self, address: u16) -> Option<NametableMapping> {
+        let bank = self.chr_banks[8 + ((address as usize >> 10) & 0b11)];
+        Some(if bank >= 0xe0 {
+            NametableMapping::Ciram(bank & 1)
+        } else {
+            NametableMapping::Chr((1, Bank::Number(bank)))
+        })
+    }
+
+    fn clock(&mut self) {
+        if self.irq_enabled && self.irq_counter < IRQ_COUNTER_MAX {
+            self.irq_counter += 1;
+            if self.irq_counter == IRQ_COUNTER_MAX {
+                self.irq_status = true;
+            }
+        }
+
+        if self.sound_disabled {
+            return;
+        }
+        self.update_cycles += 1;
+        if self.update_cycles >= CHANNEL_UPDATE_CYCLES {
+            self.update_cycles = 0;
+            let first_channel = 8 - self.channel_count();
+            let channel = self.current_channel.max(first_channel);
+            self.update_channel(channel as usize);
+            self.current_channel = if channel == first_channel {
+                7
+            } else {
+                channel - 1
+            };
+        }
+    }
+
+    fn audio_sample(&self) -> Option<ExpansionSample> {
+        if self.sound_disabled {
+            return Some((ExpansionChip::Namco163, 0.));
+        }
+        // Channels are output one after the other, so more channels means each one is quieter
+        let count = self.channel_count();
+        let sum: i32 = self.channel_outputs[8 - count as usize..]
+            .iter()
+            .map(|output| *output as i32)
+            .sum();
+        let sample = sum as f32 / count as f32 * OUTPUT_SCALE;
+        Some((ExpansionChip::Namco163, sample))
+    }
+
+    fn irq_status(&self) -> bool {
+        self.irq_status
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        Some(self.sound_ram.as_slice())
+    }
+
+    fn battery_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(self.sound_ram.as_mut_slice())
+    }
+
+    fn internal_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(self.sound_ram.as_mut_slice())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Cartridge,
+        cartridge::{NametableMapping, mapper::test::create_test_catridge},
+    };
+
+    fn setup_catridge() -> Cartridge {
+        create_test_catridge(
+            19,
+            8,
+            &[&[0], &[1], &[2], &[3]],
+            1,
+            &[&[0], &[1], &[2], &[3]],
+        )
+    }
+
+    #[test]
+    fn prg_rom() {
+        let mut cartridge = setup_catridge();
+        assert_eq!(cartridge.cpu_read(0xe000), Some(3));
+        cartridge.cpu_write(0xe000, 2);
+        cartridge.cpu_write(0xe800, 1);
+        cartridge.cpu_write(0xf000, 0);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(2));
+        assert_eq!(cartridge.cpu_read(0xa000), Some(1));
+        assert_eq!(cartridge.cpu_read(0xc000), Some(0));
+    }
+
+    #[test]
+    fn nametables() {
+        let mut cartridge = setup_catridge();
+        cartridge.cpu_write(0xc000, 0xe0);
+        cartridge.cpu_write(0xc800, 0xe1);
+        cartridge.cpu_write(0xd000, 3);
+        assert_eq!(
+            cartridge.map_nametable(0x2000),
+            Some(NametableMapping::Ciram(0))
+        );
+        assert_eq!(
+            cartridge.map_nametable(0x2400),
+            Some(NametableMapping::Ciram(1))
+        );
+        assert_eq!(cartridge.peek_nametable(0x2800), 3);
+    }
+
+    #[test]
+    fn sound_ram() {
+        let mut cartridge = setup_catridge();
+        cartridge.cpu_write(0xf800, 0x80 | 0x7f);
+        cartridge.cpu_write(0x4800, 1);
+        cartridge.cpu_write(0x4800, 2);
+        cartridge.cpu_write(0xf800, 0x7f);
+        assert_eq!(cartridge.cpu_read(0x4800), Some(1));
+        cartridge.cpu_write(0xf800, 0);
+        assert_eq!(cartridge.cpu_read(0x4800), Some(2));
+    }
+
+    #[test]
+    fn irq() {
+        let mut cartridge = setup_catridge();
+        cartridge.cpu_write(0x5000, 0xfe);
+        cartridge.cpu_write(0x5800, 0xff);
+        assert_eq!(cartridge.cpu_read(0x5800), Some(0xff));
+        cartridge.clock();
+        assert!(cartridge.irq_status());
+        // Counter stops at the max value
+        cartridge.clock();
+        assert_eq!(cartridge.cpu_read(0x5000), Some(0xff));
+        cartridge.cpu_write(0x5000, 0);
+        assert!(!cartridge.irq_status());
+    }
+
+    #[test]
+    fn audio() {
+        let mut cartridge = setup_catridge();
+        // Wave of constant 15s at the start of sound RAM
+        cartridge.cpu_write(0xf800, 0x80);
+        cartridge.cpu_write(0x4800, 0xff);
+        // Channel 7, length of 4 samples and full volume
+        cartridge.cpu_write(0xf800, 0x80 | 0x7c);
+        cartridge.cpu_write(0x4800, 0xfc);
+        cartridge.cpu_write(0xf800, 0x7f);
+        cartridge.cpu_write(0x4800, 0x0f);
+        for _ in 0..15 {
+            cartridge.clock();
+        }
+        assert_eq!(cartridge.audio_sample().unwrap().1, 7. * 15. * 0.0015);
+    }
+
+    #[test]
+    fn battery() {
+        let mut cartridge = setup_catridge();
+        cartridge.header.has_battery = true;
+        cartridge.cpu_write(0xf800, 0x05);
+        cartridge.cpu_write(0x4800, 42);
+        let data = cartridge.battery_data().unwrap();
+        assert_eq!(data.len(), 1024 + 128);
+        assert_eq!(data[1024 + 5], 42);
+
+        let mut cartridge = setup_catridge();
+        cartridge.header.has_battery = true;
+        cartridge.load_battery_data(&data);
+        cartridge.cpu_write(0xf800, 0x05);
+        assert_eq!(cartridge.cpu_read(0x4800), Some(42));
+    }
+}
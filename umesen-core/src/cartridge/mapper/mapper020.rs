@@ -0,0 +1,392 @@
+use crate::{
+    apu::{ExpansionChip, ExpansionSample},
+    cartridge::{
+        Bank, BankMapping, Mapper, Mirroring,
+        fds_disk::{FdsDisk, update_crc},
+        mapper::fds_audio::FdsAudio,
+    },
+};
+
+/// CPU cycles for the drive to read or write one byte
+const BYTE_CYCLES: u32 = 150;
+/// CPU cycles for the drive to get the head back to the start of the disk
+const REWIND_CYCLES: u32 = 50000;
+/// CPU cycles the disk stays ejected when switching sides, about a second
+const SWITCH_SIDE_CYCLES: u32 = 1_800_000;
+
+/// INES designation for the Famicom Disk System RAM adapter
+/// Created by Cartridge::from_fds since it needs the disk image
+/// https://www.nesdev.org/wiki/Family_Computer_Disk_System
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Mapper020 {
+    disk: FdsDisk,
+    /// Side currently in the drive, None if ejected
+    inserted_side: Option<usize>,
+    /// Side to insert after the switch delay
+    next_side: usize,
+    switch_delay: u32,
+
+    disk_registers_enabled: bool,
+    sound_registers_enabled: bool,
+    mirroring: Mirroring,
+
+    irq_reload: u16,
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_repeat: bool,
+    timer_irq: bool,
+
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    crc_control: bool,
+    previous_crc_control: bool,
+    disk_ready: bool,
+    disk_irq_enabled: bool,
+    disk_irq: bool,
+    /// A byte has been read or written, cleared when accessing the data registers
+    transfer_complete: bool,
+    read_data: u8,
+    write_data: u8,
+    crc: u16,
+    bad_crc: bool,
+    /// Found the start of a block after a gap
+    gap_ended: bool,
+    scanning: bool,
+    end_of_head: bool,
+    position: usize,
+    delay: u32,
+
+    audio: FdsAudio,
+}
+
+impl Mapper020 {
+    pub fn new(disk: FdsDisk) -> Self {
+        Self {
+            disk,
+            inserted_side: Some(0),
+            end_of_head: true,
+            ..Default::default()
+        }
+    }
+
+    fn clock_irq(&mut self) {
+        if !self.irq_enabled {
+            return;
+        }
+        if self.irq_counter == 0 {
+            self.timer_irq = true;
+            self.irq_counter = self.irq_reload;
+            self.irq_enabled = self.irq_repeat;
+        } else {
+            self.irq_counter -= 1;
+        }
+    }
+
+    fn clock_disk(&mut self) {
+        if self.switch_delay > 0 {
+            self.switch_delay -= 1;
+            if self.switch_delay == 0 {
+                self.inserted_side = Some(self.next_side);
+            }
+        }
+
+        let Some(side) = self.inserted_side.filter(|_| self.motor_on) else {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        };
+        if self.reset_transfer && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            self.delay = REWIND_CYCLES;
+            self.end_of_head = false;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let data = self.disk.side_mut(side);
+        if self.read_mode {
+            let value = data[self.position];
+            if !self.disk_ready {
+                self.gap_ended = false;
+                self.crc = 0;
+            } else if value != 0 && !self.gap_ended {
+                // No IRQ for the start mark of a block
+                self.gap_ended = true;
+                self.crc = update_crc(0, value);
+                self.read_data = value;
+                self.transfer_complete = true;
+            } else if self.gap_ended {
+                self.crc = update_crc(self.crc, value);
+                self.read_data = value;
+                self.transfer_complete = true;
+                self.disk_irq |= self.disk_irq_enabled;
+            }
+            if self.crc_control {
+                self.bad_crc = self.crc != 0;
+            }
+        } else {
+            let value = if !self.disk_ready {
+                0
+            } else if self.crc_control {
+                // Finish the CRC then write it out one byte at a time
+                if !self.previous_crc_control {
+                    self.crc = update_crc(update_crc(self.crc, 0), 0);
+                }
+                let value = self.crc as u8;
+                self.crc >>= 8;
+                value
+            } else {
+                self.transfer_complete = true;
+                self.disk_irq |= self.disk_irq_enabled;
+                self.write_data
+            };
+            if !self.crc_control {
+                self.crc = if self.disk_ready {
+                    update_crc(self.crc, value)
+                } else {
+                    0
+                };
+            }
+            data[self.position] = value;
+            self.gap_ended = false;
+        }
+        self.previous_crc_control = self.crc_control;
+
+        self.position += 1;
+        if self.position >= data.len() {
+            self.motor_on = false;
+            self.end_of_head = true;
+        } else {
+            self.delay = BYTE_CYCLES;
+        }
+    }
+}
+
+impl Mapper for Mapper020 {
+    fn map_cpu_read(&self, address: u16) -> Option<BankMapping> {
+        matches!(address, 0xe000..=0xffff).then_some((8, Bank::Number(0)))
+    }
+
+    fn map_prg_ram(&self, address: u16, _write: bool) -> Option<BankMapping> {
+        matches!(address, 0x6000..=0xdfff).then_some((32, Bank::Number(0)))
+    }
+
+    fn peek_cpu_read(&self, address: u16) -> Option<u8> {
+        match address {
+            0x4030 if self.disk_registers_enabled => Some(
+                self.timer_irq as u8
+                    | (self.transfer_complete as u8) << 1
+                    | (self.bad_crc as u8) << 4
+                    | (self.end_of_head as u8) << 6,
+            ),
+            0x4031 if self.disk_registers_enabled => Some(self.read_data),
+            0x4032 if self.disk_registers_enabled => {
+                let ejected = self.inserted_side.is_none();
+                Some(
+                    0x40 | ejected as u8
+                        | ((ejected || !self.scanning) as u8) << 1
+                        | (ejected as u8) << 2,
+                )
+            }
+            // Battery of the drive is good
+            0x4033 if self.disk_registers_enabled => Some(0x80),
+            0x4040..=0x4097 if self.sound_registers_enabled => self.audio.read(address),
+            _ => None,
+        }
+    }
+
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        let value = self.peek_cpu_read(address);
+        if value.is_some() {
+            match address {
+                0x4030 => {
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                    self.transfer_complete = false;
+                }
+                0x4031 => {
+                    self.disk_irq = false;
+                    self.transfer_complete = false;
+                }
+                _ => (),
+            }
+        }
+        value
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x4023 => {
+                self.disk_registers_enabled = value & 0b01 != 0;
+                self.sound_registers_enabled = value & 0b10 != 0;
+                if !self.disk_registers_enabled {
+                    self.irq_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4020..=0x4026 if !self.disk_registers_enabled => (),
+            0x4020 => self.irq_reload = (self.irq_reload & 0xff00) | value as u16,
+            0x4021 => self.irq_reload = (self.irq_reload & 0x00ff) | (value as u16) << 8,
+            0x4022 => {
+                self.irq_repeat = value & 0b01 != 0;
+                self.irq_enabled = value & 0b10 != 0;
+                if self.irq_enabled {
+                    self.irq_counter = self.irq_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4024 => {
+                self.write_data = value;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 => {
+                self.motor_on = value & 0b0000_0001 != 0;
+                self.reset_transfer = value & 0b0000_0010 != 0;
+                self.read_mode = value & 0b0000_0100 != 0;
+                self.mirroring = if value & 0b0000_1000 != 0 {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+                self.crc_control = value & 0b0001_0000 != 0;
+                self.disk_ready = value & 0b0100_0000 != 0;
+                self.disk_irq_enabled = value & 0b1000_0000 != 0;
+                self.disk_irq = false;
+            }
+            0x4040..=0x4097 if self.sound_registers_enabled => self.audio.write(address, value),
+            _ => (),
+        }
+    }
+
+    fn map_ppu(&self, _address: u16) -> BankMapping {
+        (8, Bank::Number(0))
+    }
+
+    fn clock(&mut self) {
+        self.clock_irq();
+        self.clock_disk();
+        self.audio.clock();
+    }
+
+    fn audio_sample(&self) -> Option<ExpansionSample> {
+        Some((ExpansionChip::Fds, self.audio.sample()))
+    }
+
+    fn disk(&self) -> Option<&FdsDisk> {
+        Some(&self.disk)
+    }
+
+    fn disk_mut(&mut self) -> Option<&mut FdsDisk> {
+        Some(&mut self.disk)
+    }
+
+    fn disk_side(&self) -> Option<usize> {
+        self.inserted_side
+    }
+
+    fn switch_disk_side(&mut self) {
+        let current = self.inserted_side.take().unwrap_or(self.next_side);
+        self.next_side = (current + 1) % self.disk.side_count();
+        self.switch_delay = SWITCH_SIDE_CYCLES;
+    }
+
+    fn irq_status(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(self.mirroring)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Cartridge,
+        cartridge::{FDS_BIOS_SIZE, fds_disk::SIDE_SIZE},
+    };
+
+    use super::SWITCH_SIDE_CYCLES;
+
+    fn setup_catridge() -> Cartridge {
+        // Two sides that only have the disk info block
+        let mut image = vec![0; SIDE_SIZE * 2];
+        image[0] = 1;
+        image[SIDE_SIZE] = 1;
+        let mut cartridge = Cartridge::from_fds(&[0; FDS_BIOS_SIZE][..], &image[..]).unwrap();
+        cartridge.cpu_write(0x4023, 0b11);
+        cartridge
+    }
+
+    #[test]
+    fn timer_irq() {
+        let mut cartridge = setup_catridge();
+        cartridge.cpu_write(0x4020, 2);
+        cartridge.cpu_write(0x4021, 0);
+        cartridge.cpu_write(0x4022, 0b10);
+        cartridge.clock();
+        cartridge.clock();
+        assert!(!cartridge.irq_status());
+        cartridge.clock();
+        assert!(cartridge.irq_status());
+        assert_eq!(cartridge.cpu_read(0x4030).map(|v| v & 0b11), Some(0b01));
+        assert!(!cartridge.irq_status());
+    }
+
+    #[test]
+    fn read_disk() {
+        let mut cartridge = setup_catridge();
+        // Motor on, read mode, disk ready and transfer IRQs
+        cartridge.cpu_write(0x4025, 0b1100_0101);
+        assert_eq!(cartridge.cpu_read(0x4032).map(|v| v & 0b111), Some(0b010));
+
+        let mut data = Vec::new();
+        for _ in 0..1_000_000 {
+            cartridge.clock();
+            if cartridge.irq_status() {
+                data.push(cartridge.cpu_read(0x4031).unwrap());
+                if data.len() == 3 {
+                    break;
+                }
+            }
+        }
+        assert_eq!(data, [1, 0, 0]);
+        assert_eq!(cartridge.cpu_read(0x4032).map(|v| v & 0b111), Some(0b000));
+    }
+
+    #[test]
+    fn switch_side() {
+        let mut cartridge = setup_catridge();
+        assert_eq!(cartridge.disk_side(), Some(0));
+        cartridge.switch_disk_side();
+        assert_eq!(cartridge.disk_side(), None);
+        assert_eq!(cartridge.cpu_read(0x4032).map(|v| v & 0b111), Some(0b111));
+        for _ in 0..SWITCH_SIDE_CYCLES {
+            cartridge.clock();
+        }
+        assert_eq!(cartridge.disk_side(), Some(1));
+    }
+
+    #[test]
+    fn disk_image() {
+        let mut cartridge = setup_catridge();
+        let mut image = cartridge.disk_image().unwrap();
+        assert_eq!(image.len(), SIDE_SIZE * 2);
+        image[1] = b'*';
+        cartridge.load_disk_image(&image).unwrap();
+        assert_eq!(cartridge.disk_image(), Some(image));
+        assert!(cartridge.load_disk_image(&[0; SIDE_SIZE]).is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use crate::cartridge::{Bank, BankMapping, Mapper, Mirroring, mapper::vrc_irq::VrcIrq};
+
+/// INES designation for Konami VRC2 and VRC4 boards (mappers 21, 22, 23 and 25)
+/// The boards differ in which CPU address lines select the registers, this is decided by the
+/// submapper and when it's missing both possible wirings of the mapper are used at the same time
+/// https://www.nesdev.org/wiki/VRC2_and_VRC4
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Mapper021 {
+    /// Address lines used for bit 0 and bit 1 of the register number
+    address_lines: Vec<(u8, u8)>,
+    /// VRC2 has no IRQ or PRG swap mode and only 2 mirroring modes
+    vrc2: bool,
+    /// VRC2a ignores the lowest bit of the CHR banks
+    chr_shift: u8,
+
+    prg_banks: [u8; 2],
+    prg_mode_swap: bool,
+    chr_banks: [u16; 8],
+    mirroring: u8,
+    irq: VrcIrq,
+}
+
+impl Mapper021 {
+    pub fn new(mapper_id: u16, submapper_id: u8) -> Self {
+        let (address_lines, vrc2) = match (mapper_id, submapper_id) {
+            // VRC4a
+            (21, 1) => (vec![(1, 2)], false),
+            // VRC4c
+            (21, 2) => (vec![(6, 7)], false),
+            (21, _) => (vec![(1, 2), (6, 7)], false),
+            // VRC2a
+            (22, _) => (vec![(1, 0)], true),
+            // VRC4f
+            (23, 1) => (vec![(0, 1)], false),
+            // VRC4e
+            (23, 2) => (vec![(2, 3)], false),
+            // VRC2b
+            (23, 3) => (vec![(0, 1)], true),
+            (23, _) => (vec![(0, 1), (2, 3)], false),
+            // VRC4b
+            (25, 1) => (vec![(1, 0)], false),
+            // VRC4d
+            (25, 2) => (vec![(3, 2)], false),
+            // VRC2c
+            (25, 3) => (vec![(1, 0)], true),
+            _ => (vec![(1, 0), (3, 2)], false),
+        };
+        Self {
+            address_lines,
+            vrc2,
+            chr_shift: (mapper_id == 22) as u8,
+            ..Default::default()
+        }
+    }
+
+    /// Register number 0-3 selected by the address lines
+    fn register(&self, address: u16) -> u16 {
+        self.address_lines
+            .iter()
+            .fold(0, |register, (bit_0, bit_1)| {
+                register | ((address >> bit_0) & 1) | ((address >> bit_1) & 1) << 1
+            })
+    }
+}
+
+impl Mapper for Mapper021 {
+    fn map_cpu_read(&self, address: u16) -> Option<BankMapping> {
+        let swappable = Bank::Number(self.prg_banks[0]);
+        let (bank_8000, bank_c000) = if self.prg_mode_swap {
+            (Bank::FromLast(1), swappable)
+        } else {
+            (swappable, Bank::FromLast(1))
+        };
+        Some(match address {
+            0x8000..=0x9fff => (8, bank_8000),
+            0xa000..=0xbfff => (8, Bank::Number(self.prg_banks[1])),
+            0xc000..=0xdfff => (8, bank_c000),
+            0xe000..=0xffff => (8, Bank::FromLast(0)),
+            _ => return None,
+        })
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        let register = self.register(address);
+        match (address & 0xf000, register) {
+            (0x8000, _) => self.prg_banks[0] = value & 0x1f,
+            (0x9000, 0) | (0x9000, 1) if self.vrc2 => self.mirroring = value & 0b01,
+            (0x9000, 0) => self.mirroring = value & 0b11,
+            (0x9000, 2) if !self.vrc2 => self.prg_mode_swap = value & 0b10 != 0,
+            (0xa000, _) => self.prg_banks[1] = value & 0x1f,
+            (0xb000..=0xe000, _) => {
+                let i = ((address as usize & 0xf000) - 0xb000) / 0x800 + (register as usize >> 1);
+                let bank = &mut self.chr_banks[i];
+                if register & 1 == 0 {
+                    *bank = (*bank & 0x1f0) | (value as u16 & 0x0f);
+                } else {
+                    *bank = (*bank & 0x0f) | ((value as u16 & 0x1f) << 4);
+                }
+            }
+            (0xf000, _) if self.vrc2 => (),
+            (0xf000, 0) => self.irq.write_latch_low(value),
+            (0xf000, 1) => self.irq.write_latch_high(value),
+            (0xf000, 2) => self.irq.write_control(value),
+            (0xf000, 3) => self.irq.acknowledge(),
+            _ => (),
+        }
+    }
+
+    fn map_ppu(&self, address: u16) -> BankMapping {
+        // Bank numbers can only be 8 bits
+        let bank = self.chr_banks[address as usize / 0x400] >> self.chr_shift;
+        (1, Bank::Number(bank as u8))
+    }
+
+    fn clock(&mut self) {
+        self.irq.clock();
+    }
+
+    fn irq_status(&self) -> bool {
+        self.irq.status
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(match self.mirroring {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLow,
+            _ => Mirroring::SingleScreenHigh,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Cartridge,
+        cartridge::{CartridgeBanks, CartridgeHeader, Mirroring, mapper::test::create_banks_rom},
+    };
+
+    fn setup_catridge(mapper_id: u16, submapper_id: u8) -> Cartridge {
+        let prg_rom = create_banks_rom(8, &[&[0], &[1], &[2], &[3], &[4]]);
+        let chr_banks: Vec<[u8; 1]> = (0..32).map(|i| [i]).collect();
+        let chr_banks: Vec<&[u8]> = chr_banks.iter().map(|bank| &bank[..]).collect();
+        let chr_rom = create_banks_rom(1, &chr_banks);
+        let header = CartridgeHeader {
+            mapper_id,
+            submapper_id,
+            ..Default::default()
+        };
+        Cartridge::new(header, CartridgeBanks::new(vec![0; 1024], prg_rom, chr_rom)).unwrap()
+    }
+
+    #[test]
+    fn prg_rom() {
+        let mut cartridge = setup_catridge(21, 1);
+        assert_eq!(cartridge.cpu_read(0xc000), Some(3));
+        assert_eq!(cartridge.cpu_read(0xe000), Some(4));
+
+        cartridge.cpu_write(0x8000, 1);
+        cartridge.cpu_write(0xa000, 2);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(1));
+        assert_eq!(cartridge.cpu_read(0xa000), Some(2));
+
+        // VRC4a selects register 2 with A2
+        cartridge.cpu_write(0x9004, 0b10);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(3));
+        assert_eq!(cartridge.cpu_read(0xc000), Some(1));
+    }
+
+    #[test]
+    fn chr_address_lines() {
+        // Register 1 of the first CHR bank pair is the high bits of bank 0
+        // and register 2 is the low bits of bank 1
+        for (mapper_id, submapper_id, high_0, low_1) in [
+            (21, 1, 0xb002, 0xb004),
+            (21, 2, 0xb040, 0xb080),
+            (23, 1, 0xb001, 0xb002),
+            (23, 2, 0xb004, 0xb008),
+            (25, 1, 0xb002, 0xb001),
+            (25, 2, 0xb008, 0xb004),
+        ] {
+            let mut cartridge = setup_catridge(mapper_id, submapper_id);
+            cartridge.cpu_write(low_1, 5);
+            cartridge.cpu_write(high_0, 0);
+            assert_eq!(cartridge.ppu_read(0x0400), Some(5), "mapper {mapper_id}");
+            cartridge.cpu_write(high_0, 1);
+            assert_eq!(cartridge.ppu_read(0x0000), Some(16), "mapper {mapper_id}");
+        }
+
+        // VRC2a ignores the low bit of the bank
+        let mut cartridge = setup_catridge(22, 0);
+        cartridge.cpu_write(0xb000, 7);
+        assert_eq!(cartridge.ppu_read(0x0000), Some(3));
+    }
+
+    #[test]
+    fn mirroring() {
+        let mut cartridge = setup_catridge(25, 0);
+        cartridge.cpu_write(0x9000, 3);
+        assert_eq!(cartridge.mirroring(), Mirroring::SingleScreenHigh);
+
+        let mut cartridge = setup_catridge(23, 3);
+        cartridge.cpu_write(0x9000, 3);
+        assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn irq() {
+        let mut cartridge = setup_catridge(23, 2);
+        cartridge.cpu_write(0xf000, 0x0f);
+        cartridge.cpu_write(0xf004, 0x0f);
+        cartridge.cpu_write(0xf008, 0b110);
+        cartridge.clock();
+        assert!(cartridge.irq_status());
+        cartridge.cpu_write(0xf00c, 0);
+        assert!(!cartridge.irq_status());
+
+        // VRC2 has no IRQ
+        let mut cartridge = setup_catridge(23, 3);
+        cartridge.cpu_write(0xf000, 0x0f);
+        cartridge.cpu_write(0xf001, 0x0f);
+        cartridge.cpu_write(0xf002, 0b110);
+        cartridge.clock();
+        assert!(!cartridge.irq_status());
+    }
+}
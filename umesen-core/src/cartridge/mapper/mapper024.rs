@@ -0,0 +1,304 @@
+use crate::{
+    apu::{ExpansionChip, ExpansionSample},
+    cartridge::{Bank, BankMapping, Mapper, Mirroring, mapper::vrc_irq::VrcIrq},
+};
+
+/// Brings the VRC6 channels to about the same level as the APU pulse channels
+const OUTPUT_SCALE: f32 = 0.01;
+
+/// INES designation for Konami VRC6 boards, mapper 26 is VRC6b which swaps the A0 and A1 lines
+/// https://www.nesdev.org/wiki/VRC6
+/// Only the CHR banking mode used by the released games (1kb banks) is supported
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Mapper024 {
+    swapped_address_lines: bool,
+    prg_bank_16kb: u8,
+    prg_bank_8kb: u8,
+    chr_banks: [u8; 8],
+    /// $b003
+    banking_control: u8,
+    irq: VrcIrq,
+
+    pulse_0: Vrc6Pulse,
+    pulse_1: Vrc6Pulse,
+    sawtooth: Vrc6Sawtooth,
+    halt_audio: bool,
+    frequency_shift: u8,
+}
+
+impl Mapper024 {
+    pub fn vrc6b() -> Self {
+        Self {
+            swapped_address_lines: true,
+            ..Default::default()
+        }
+    }
+}
+
+impl Mapper for Mapper024 {
+    fn map_cpu_read(&self, address: u16) -> Option<BankMapping> {
+        Some(match address {
+            0x8000..=0xbfff => (16, Bank::Number(self.prg_bank_16kb)),
+            0xc000..=0xdfff => (8, Bank::Number(self.prg_bank_8kb)),
+            0xe000..=0xffff => (8, Bank::FromLast(0)),
+            _ => return None,
+        })
+    }
+
+    fn map_prg_ram(&self, address: u16, _write: bool) -> Option<BankMapping> {
+        match address {
+            0x6000..=0x7fff if self.banking_control & 0x80 != 0 => Some((8, Bank::Number(0))),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        let register = if self.swapped_address_lines {
+            (address & 0xf000) | (address & 0b01) << 1 | (address & 0b10) >> 1
+        } else {
+            address & 0xf003
+        };
+        match register {
+            0x8000..=0x8003 => self.prg_bank_16kb = value & 0x0f,
+            0x9000..=0x9002 => self.pulse_0.write(register & 0b11, value),
+            0x9003 => {
+                self.halt_audio = value & 0b001 != 0;
+                self.frequency_shift = match value {
+                    _ if value & 0b100 != 0 => 8,
+                    _ if value & 0b010 != 0 => 4,
+                    _ => 0,
+                };
+            }
+            0xa000..=0xa002 => self.pulse_1.write(register & 0b11, value),
+            0xb000..=0xb002 => self.sawtooth.write(register & 0b11, value),
+            0xb003 => self.banking_control = value,
+            0xc000..=0xc003 => self.prg_bank_8kb = value & 0x1f,
+            0xd000..=0xd003 => self.chr_banks[(register & 0b11) as usize] = value,
+            0xe000..=0xe003 => self.chr_banks[4 + (register & 0b11) as usize] = value,
+            0xf000 => self.irq.write_latch(value),
+            0xf001 => self.irq.write_control(value),
+            0xf002 => self.irq.acknowledge(),
+            _ => (),
+        }
+    }
+
+    fn map_ppu(&self, address: u16) -> BankMapping {
+        (1, Bank::Number(self.chr_banks[address as usize / 0x400]))
+    }
+
+    fn clock(&mut self) {
+        self.irq.clock();
+        if !self.halt_audio {
+            self.pulse_0.clock(self.frequency_shift);
+            self.pulse_1.clock(self.frequency_shift);
+            self.sawtooth.clock(self.frequency_shift);
+        }
+    }
+
+    fn audio_sample(&self) -> Option<ExpansionSample> {
+        let sum = self.pulse_0.sample() + self.pulse_1.sample() + self.sawtooth.sample();
+        Some((ExpansionChip::Vrc6, sum as f32 * OUTPUT_SCALE))
+    }
+
+    fn irq_status(&self) -> bool {
+        self.irq.status
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(match (self.banking_control >> 2) & 0b11 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLow,
+            _ => Mirroring::SingleScreenHigh,
+        })
+    }
+}
+
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+struct Vrc6Pulse {
+    volume: u8,
+    duty: u8,
+    /// Outputs the volume constantly
+    ignore_duty: bool,
+    enabled: bool,
+    period: u16,
+    timer: u16,
+    step: u8,
+}
+
+impl Vrc6Pulse {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.volume = value & 0x0f;
+                self.duty = (value >> 4) & 0b111;
+                self.ignore_duty = value & 0x80 != 0;
+            }
+            1 => self.period = (self.period & 0x0f00) | value as u16,
+            _ => {
+                self.period = (self.period & 0x00ff) | ((value as u16 & 0x0f) << 8);
+                self.enabled = value & 0x80 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = (self.step + 1) % 16;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn sample(&self) -> u8 {
+        if self.enabled && (self.ignore_duty || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+struct Vrc6Sawtooth {
+    rate: u8,
+    enabled: bool,
+    period: u16,
+    timer: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl Vrc6Sawtooth {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => self.rate = value & 0x3f,
+            1 => self.period = (self.period & 0x0f00) | value as u16,
+            _ => {
+                self.period = (self.period & 0x00ff) | ((value as u16 & 0x0f) << 8);
+                self.enabled = value & 0x80 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period >> shift;
+        // The rate is added on every other step and the accumulator resets on the 14th
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step.is_multiple_of(2) {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    fn sample(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Cartridge,
+        cartridge::{Mirroring, mapper::test::create_test_catridge},
+    };
+
+    fn setup_catridge(mapper_id: u16) -> Cartridge {
+        create_test_catridge(
+            mapper_id,
+            8,
+            &[&[0], &[1], &[2], &[3], &[4], &[5]],
+            1,
+            &[&[0], &[1], &[2], &[3], &[4], &[5], &[6], &[7], &[8]],
+        )
+    }
+
+    #[test]
+    fn prg_rom() {
+        let mut cartridge = setup_catridge(24);
+        assert_eq!(cartridge.cpu_read(0xe000), Some(5));
+
+        cartridge.cpu_write(0x8000, 1);
+        cartridge.cpu_write(0xc000, 4);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(2));
+        assert_eq!(cartridge.cpu_read(0xa000), Some(3));
+        assert_eq!(cartridge.cpu_read(0xc000), Some(4));
+    }
+
+    #[test]
+    fn chr_rom() {
+        let mut cartridge = setup_catridge(24);
+        cartridge.cpu_write(0xd001, 8);
+        cartridge.cpu_write(0xe002, 3);
+        assert_eq!(cartridge.ppu_read(0x0400), Some(8));
+        assert_eq!(cartridge.ppu_read(0x1800), Some(3));
+
+        // VRC6b has A0 and A1 swapped
+        let mut cartridge = setup_catridge(26);
+        cartridge.cpu_write(0xd001, 8);
+        assert_eq!(cartridge.ppu_read(0x0800), Some(8));
+    }
+
+    #[test]
+    fn mirroring() {
+        let mut cartridge = setup_catridge(24);
+        assert_eq!(cartridge.mirroring(), Mirroring::Vertical);
+        cartridge.cpu_write(0xb003, 0b0100);
+        assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+        cartridge.cpu_write(0xb003, 0b1100);
+        assert_eq!(cartridge.mirroring(), Mirroring::SingleScreenHigh);
+    }
+
+    #[test]
+    fn irq() {
+        let mut cartridge = setup_catridge(24);
+        cartridge.cpu_write(0xf000, 0xff);
+        cartridge.cpu_write(0xf001, 0b110);
+        assert!(!cartridge.irq_status());
+        cartridge.clock();
+        assert!(cartridge.irq_status());
+        cartridge.cpu_write(0xf002, 0);
+        assert!(!cartridge.irq_status());
+    }
+
+    #[test]
+    fn audio() {
+        let mut cartridge = setup_catridge(24);
+        let sample = |cartridge: &Cartridge| cartridge.audio_sample().unwrap().1;
+        assert_eq!(sample(&cartridge), 0.);
+
+        // Constant volume pulse
+        cartridge.cpu_write(0x9000, 0x8f);
+        cartridge.cpu_write(0x9002, 0x80);
+        assert!(sample(&cartridge) > 0.);
+        cartridge.cpu_write(0x9002, 0);
+
+        // Sawtooth rises with the accumulator
+        cartridge.cpu_write(0xb000, 0x3f);
+        cartridge.cpu_write(0xb002, 0x80);
+        for _ in 0..2 {
+            cartridge.clock();
+        }
+        assert!(sample(&cartridge) > 0.);
+    }
+}
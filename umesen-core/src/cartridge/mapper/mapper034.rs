@@ -0,0 +1,92 @@
+use crate::cartridge::{Bank, BankMapping, Mapper};
+
+/// INES designation for BNROM and NINA-001 boards
+/// NINA-001 is used if the submapper is 1 or it has more than 8kb of CHR ROM
+/// https://www.nesdev.org/wiki/INES_Mapper_034
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Mapper034 {
+    nina_001: bool,
+    prg_bank: u8,
+    chr_banks: [u8; 2],
+}
+
+impl Mapper034 {
+    pub fn nina_001() -> Self {
+        Self {
+            nina_001: true,
+            ..Default::default()
+        }
+    }
+}
+
+impl Mapper for Mapper034 {
+    fn map_cpu_read(&self, address: u16) -> Option<BankMapping> {
+        Some(match address {
+            0x8000..=0xffff => (32, Bank::Number(self.prg_bank)),
+            _ => return None,
+        })
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x7ffd if self.nina_001 => self.prg_bank = value & 0b1,
+            0x7ffe if self.nina_001 => self.chr_banks[0] = value & 0x0f,
+            0x7fff if self.nina_001 => self.chr_banks[1] = value & 0x0f,
+            0x8000..=0xffff if !self.nina_001 => self.prg_bank = value,
+            _ => (),
+        }
+    }
+
+    fn map_ppu(&self, address: u16) -> BankMapping {
+        if self.nina_001 {
+            (4, Bank::Number(self.chr_banks[address as usize / 0x1000]))
+        } else {
+            (8, Bank::Number(0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Cartridge,
+        cartridge::{
+            CartridgeBanks, CartridgeHeader,
+            mapper::test::{create_banks_rom, create_test_catridge},
+        },
+    };
+
+    #[test]
+    fn bnrom() {
+        let mut cartridge = create_test_catridge(34, 32, &[&[1], &[2]], 8, &[&[3]]);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(1));
+        cartridge.cpu_write(0x8000, 1);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(2));
+        cartridge.cpu_write(0x7ffd, 0);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(2));
+    }
+
+    #[test]
+    fn nina_001() {
+        let header = CartridgeHeader {
+            mapper_id: 34,
+            chr_mem_is_rom: true,
+            chr_mem_size: 0x3000,
+            ..Default::default()
+        };
+        let banks = CartridgeBanks::new(
+            vec![0; 0x2000],
+            create_banks_rom(32, &[&[1], &[2]]),
+            create_banks_rom(4, &[&[3], &[4], &[5]]),
+        );
+        let mut cartridge = Cartridge::new(header, banks).unwrap();
+        cartridge.cpu_write(0x7ffd, 1);
+        cartridge.cpu_write(0x7ffe, 2);
+        cartridge.cpu_write(0x7fff, 1);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(2));
+        assert_eq!(cartridge.ppu_read(0x0000), Some(5));
+        assert_eq!(cartridge.ppu_read(0x1000), Some(4));
+        // PRG RAM is still written
+        assert_eq!(cartridge.cpu_read(0x7ffd), Some(1));
+    }
+}
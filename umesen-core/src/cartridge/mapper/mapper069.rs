@@ -0,0 +1,351 @@
+use crate::{
+    apu::{ExpansionChip, ExpansionSample},
+    cartridge::{Bank, BankMapping, Mapper, Mirroring},
+};
+
+/// CPU cycles per tick of the tone, noise and envelope generators
+const AUDIO_DIVIDER: u8 = 16;
+/// Brings a channel at full volume to about the same level as an APU pulse channel
+const OUTPUT_SCALE: f32 = 0.15;
+
+/// INES designation for Sunsoft FME-7 and 5B boards
+/// https://www.nesdev.org/wiki/Sunsoft_FME-7
+/// https://www.nesdev.org/wiki/Sunsoft_5B_audio
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Mapper069 {
+    command: u8,
+    chr_banks: [u8; 8],
+    /// Bank at $6000, bit 6 selects RAM and bit 7 enables RAM
+    prg_bank_6000: u8,
+    prg_banks: [u8; 3],
+    mirroring: u8,
+
+    irq_enabled: bool,
+    irq_counter_enabled: bool,
+    irq_counter: u16,
+    irq_status: bool,
+
+    audio_register: u8,
+    audio: Sunsoft5b,
+}
+
+impl Mapper for Mapper069 {
+    fn map_cpu_read(&self, address: u16) -> Option<BankMapping> {
+        Some(match address {
+            0x6000..=0x7fff if self.prg_bank_6000 & 0x40 == 0 => {
+                (8, Bank::Number(self.prg_bank_6000 & 0x3f))
+            }
+            0x8000..=0xdfff => (
+                8,
+                Bank::Number(self.prg_banks[(address as usize - 0x8000) / 0x2000]),
+            ),
+            0xe000..=0xffff => (8, Bank::FromLast(0)),
+            _ => return None,
+        })
+    }
+
+    fn map_prg_ram(&self, address: u16, _write: bool) -> Option<BankMapping> {
+        match address {
+            0x6000..=0x7fff if self.prg_bank_6000 & 0xc0 == 0xc0 => {
+                Some((8, Bank::Number(self.prg_bank_6000 & 0x3f)))
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x8000..=0x9fff => self.command = value & 0x0f,
+            0xa000..=0xbfff => match self.command {
+                0x0..=0x7 => self.chr_banks[self.command as usize] = value,
+                0x8 => self.prg_bank_6000 = value,
+                0x9..=0xb => self.prg_banks[self.command as usize - 0x9] = value & 0x3f,
+                0xc => self.mirroring = value & 0b11,
+                0xd => {
+                    self.irq_enabled = value & 0x01 != 0;
+                    self.irq_counter_enabled = value & 0x80 != 0;
+                    self.irq_status = false;
+                }
+                0xe => self.irq_counter = (self.irq_counter & 0xff00) | value as u16,
+                _ => self.irq_counter = (self.irq_counter & 0x00ff) | (value as u16) << 8,
+            },
+            0xc000..=0xdfff => self.audio_register = value & 0x0f,
+            0xe000..=0xffff => self.audio.write(self.audio_register, value),
+            _ => (),
+        }
+    }
+
+    fn map_ppu(&self, address: u16) -> BankMapping {
+        (1, Bank::Number(self.chr_banks[address as usize / 0x400]))
+    }
+
+    fn clock(&mut self) {
+        if self.irq_counter_enabled {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xffff && self.irq_enabled {
+                self.irq_status = true;
+            }
+        }
+        self.audio.clock();
+    }
+
+    fn audio_sample(&self) -> Option<ExpansionSample> {
+        Some((ExpansionChip::Sunsoft5b, self.audio.sample()))
+    }
+
+    fn irq_status(&self) -> bool {
+        self.irq_status
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(match self.mirroring {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLow,
+            _ => Mirroring::SingleScreenHigh,
+        })
+    }
+}
+
+/// AY-3-8910 variant with 3 square channels, a noise generator and an envelope
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+struct Sunsoft5b {
+    divider: u8,
+    tones: [Tone; 3],
+    /// Bits 0-2 disable the tones and bits 3-5 disable the noise of each channel
+    mixer: u8,
+    /// Bits 0-3 are the volume, bit 4 uses the envelope instead
+    volumes: [u8; 3],
+
+    noise_period: u8,
+    noise_counter: u8,
+    /// 17 bit linear feedback shift register
+    noise_shift: u32,
+
+    envelope_period: u16,
+    envelope_counter: u16,
+    envelope_shape: u8,
+    envelope_step: u8,
+    envelope_attack: bool,
+    envelope_holding: bool,
+}
+
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+struct Tone {
+    period: u16,
+    counter: u16,
+    output: bool,
+}
+
+impl Sunsoft5b {
+    fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0x0..=0x5 => {
+                let tone = &mut self.tones[register as usize / 2];
+                if register.is_multiple_of(2) {
+                    tone.period = (tone.period & 0x0f00) | value as u16;
+                } else {
+                    tone.period = (tone.period & 0x00ff) | (value as u16 & 0x0f) << 8;
+                }
+            }
+            0x6 => self.noise_period = value & 0x1f,
+            0x7 => self.mixer = value,
+            0x8..=0xa => self.volumes[register as usize - 0x8] = value & 0x1f,
+            0xb => self.envelope_period = (self.envelope_period & 0xff00) | value as u16,
+            0xc => self.envelope_period = (self.envelope_period & 0x00ff) | (value as u16) << 8,
+            0xd => {
+                self.envelope_shape = value & 0x0f;
+                self.envelope_step = 0;
+                self.envelope_counter = 0;
+                self.envelope_attack = value & 0b0100 != 0;
+                self.envelope_holding = false;
+            }
+            _ => (),
+        }
+    }
+
+    fn clock(&mut self) {
+        self.divider += 1;
+        if self.divider < AUDIO_DIVIDER {
+            return;
+        }
+        self.divider = 0;
+
+        for tone in &mut self.tones {
+            tone.counter += 1;
+            if tone.counter >= tone.period.max(1) {
+                tone.counter = 0;
+                tone.output = !tone.output;
+            }
+        }
+
+        self.noise_counter += 1;
+        if self.noise_counter >= self.noise_period.max(1) {
+            self.noise_counter = 0;
+            if self.noise_shift == 0 {
+                self.noise_shift = 1;
+            }
+            let feedback = (self.noise_shift ^ (self.noise_shift >> 3)) & 1;
+            self.noise_shift = (self.noise_shift >> 1) | (feedback << 16);
+        }
+
+        self.envelope_counter += 1;
+        if self.envelope_counter >= self.envelope_period.max(1) {
+            self.envelope_counter = 0;
+            self.clock_envelope();
+        }
+    }
+
+    fn clock_envelope(&mut self) {
+        if self.envelope_holding {
+            return;
+        }
+        self.envelope_step += 1;
+        if self.envelope_step < 32 {
+            return;
+        }
+
+        let continue_ = self.envelope_shape & 0b1000 != 0;
+        let alternate = self.envelope_shape & 0b0010 != 0;
+        let hold = self.envelope_shape & 0b0001 != 0;
+        if !continue_ {
+            // Stays silent after the first cycle
+            self.envelope_holding = true;
+            self.envelope_attack = false;
+            self.envelope_step = 31;
+        } else if hold {
+            self.envelope_holding = true;
+            self.envelope_step = 31;
+            self.envelope_attack ^= alternate;
+        } else {
+            self.envelope_step = 0;
+            self.envelope_attack ^= alternate;
+        }
+    }
+
+    /// 5 bit level of a channel
+    fn level(&self, channel: usize) -> u8 {
+        let volume = self.volumes[channel];
+        if volume & 0x10 != 0 {
+            if self.envelope_attack {
+                self.envelope_step
+            } else {
+                31 - self.envelope_step
+            }
+        } else if volume == 0 {
+            0
+        } else {
+            volume * 2 + 1
+        }
+    }
+
+    fn sample(&self) -> f32 {
+        let noise = self.noise_shift & 1 != 0;
+        (0..3)
+            .map(|i| {
+                let tone_on = self.tones[i].output || self.mixer & (1 << i) != 0;
+                let noise_on = noise || self.mixer & (1 << (i + 3)) != 0;
+                let level = self.level(i);
+                if tone_on && noise_on && level != 0 {
+                    // Each level is 1.5 dB
+                    10f32.powf(-((31 - level) as f32) * 1.5 / 20.)
+                } else {
+                    0.
+                }
+            })
+            .sum::<f32>()
+            * OUTPUT_SCALE
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Cartridge,
+        cartridge::{Mirroring, mapper::test::create_test_catridge},
+    };
+
+    fn setup_catridge() -> Cartridge {
+        create_test_catridge(
+            69,
+            8,
+            &[&[0], &[1], &[2], &[3], &[4]],
+            1,
+            &[&[0], &[1], &[2], &[3]],
+        )
+    }
+
+    fn command(cartridge: &mut Cartridge, command: u8, parameter: u8) {
+        cartridge.cpu_write(0x8000, command);
+        cartridge.cpu_write(0xa000, parameter);
+    }
+
+    #[test]
+    fn banks() {
+        let mut cartridge = setup_catridge();
+        assert_eq!(cartridge.cpu_read(0xe000), Some(4));
+        command(&mut cartridge, 0x9, 3);
+        command(&mut cartridge, 0xb, 1);
+        command(&mut cartridge, 0x2, 2);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(3));
+        assert_eq!(cartridge.cpu_read(0xc000), Some(1));
+        assert_eq!(cartridge.ppu_read(0x0800), Some(2));
+
+        command(&mut cartridge, 0xc, 1);
+        assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn prg_ram() {
+        let mut cartridge = setup_catridge();
+        // ROM at $6000
+        command(&mut cartridge, 0x8, 2);
+        assert_eq!(cartridge.cpu_read(0x6000), Some(2));
+
+        // RAM selected but disabled
+        command(&mut cartridge, 0x8, 0x40);
+        cartridge.cpu_write(0x6000, 5);
+        assert_eq!(cartridge.cpu_read(0x6000), None);
+
+        command(&mut cartridge, 0x8, 0xc0);
+        cartridge.cpu_write(0x6000, 5);
+        assert_eq!(cartridge.cpu_read(0x6000), Some(5));
+    }
+
+    #[test]
+    fn irq() {
+        let mut cartridge = setup_catridge();
+        command(&mut cartridge, 0xe, 1);
+        command(&mut cartridge, 0xf, 0);
+        command(&mut cartridge, 0xd, 0x81);
+        cartridge.clock();
+        assert!(!cartridge.irq_status());
+        cartridge.clock();
+        assert!(cartridge.irq_status());
+        command(&mut cartridge, 0xd, 0x81);
+        assert!(!cartridge.irq_status());
+    }
+
+    #[test]
+    fn audio() {
+        let mut cartridge = setup_catridge();
+        let sample = |cartridge: &Cartridge| cartridge.audio_sample().unwrap().1;
+        let audio_write = |cartridge: &mut Cartridge, register: u8, value: u8| {
+            cartridge.cpu_write(0xc000, register);
+            cartridge.cpu_write(0xe000, value);
+        };
+        // Channel A with tone and noise disabled outputs its volume
+        audio_write(&mut cartridge, 0x7, 0b11_1111);
+        audio_write(&mut cartridge, 0x8, 0x0f);
+        let full = sample(&cartridge);
+        assert!(full > 0.);
+
+        // Volume is logarithmic
+        audio_write(&mut cartridge, 0x8, 0x07);
+        let half = sample(&cartridge);
+        assert!(half < full / 10.);
+
+        audio_write(&mut cartridge, 0x8, 0);
+        assert_eq!(sample(&cartridge), 0.);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::cartridge::{Bank, BankMapping, Mapper, Mirroring};
+
+/// INES designation for Camerica and Codemasters boards
+/// https://www.nesdev.org/wiki/INES_Mapper_071
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Mapper071 {
+    prg_bank: u8,
+    /// Only the Fire Hawk board can select the mirroring, otherwise the header is used
+    mirroring: Option<Mirroring>,
+}
+
+impl Mapper for Mapper071 {
+    fn map_cpu_read(&self, address: u16) -> Option<BankMapping> {
+        Some(match address {
+            0x8000..=0xbfff => (16, Bank::Number(self.prg_bank)),
+            0xc000..=0xffff => (16, Bank::FromLast(0)),
+            _ => return None,
+        })
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x9000..=0x9fff => {
+                self.mirroring = Some(if value & 0b1_0000 != 0 {
+                    Mirroring::SingleScreenHigh
+                } else {
+                    Mirroring::SingleScreenLow
+                });
+            }
+            0xc000..=0xffff => self.prg_bank = value & 0x0f,
+            _ => (),
+        }
+    }
+
+    fn map_ppu(&self, _: u16) -> BankMapping {
+        (8, Bank::Number(0))
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cartridge::{Mirroring, mapper::test::create_test_catridge};
+
+    #[test]
+    fn test() {
+        let mut cartridge = create_test_catridge(71, 16, &[&[1], &[2], &[3]], 8, &[&[4]]);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(1));
+        assert_eq!(cartridge.cpu_read(0xc000), Some(3));
+
+        cartridge.cpu_write(0xc000, 1);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(2));
+
+        assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+        cartridge.cpu_write(0x9000, 0b1_0000);
+        assert_eq!(cartridge.mirroring(), Mirroring::SingleScreenHigh);
+    }
+}
@@ -0,0 +1,51 @@
+use crate::cartridge::{Bank, BankMapping, Mapper};
+
+/// INES designation for NINA-03 and NINA-06 boards
+/// https://www.nesdev.org/wiki/NINA-003-006
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Mapper079 {
+    prg_bank: u8,
+    chr_bank: u8,
+}
+
+impl Mapper for Mapper079 {
+    fn map_cpu_read(&self, address: u16) -> Option<BankMapping> {
+        Some(match address {
+            0x8000..=0xffff => (32, Bank::Number(self.prg_bank)),
+            _ => return None,
+        })
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        // Register is mirrored in $4100-$5fff where A8 is high
+        if let 0x4100..=0x5fff = address
+            && address & 0x0100 != 0
+        {
+            self.prg_bank = (value >> 3) & 0b1;
+            self.chr_bank = value & 0b111;
+        }
+    }
+
+    fn map_ppu(&self, _: u16) -> BankMapping {
+        (8, Bank::Number(self.chr_bank))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cartridge::mapper::test::create_test_catridge;
+
+    #[test]
+    fn test() {
+        let mut cartridge = create_test_catridge(79, 32, &[&[1], &[2]], 8, &[&[3], &[4]]);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(1));
+
+        // A8 is low so the register isn't written
+        cartridge.cpu_write(0x4200, 0b1001);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(1));
+
+        cartridge.cpu_write(0x4100, 0b1001);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(2));
+        assert_eq!(cartridge.ppu_read(0x0000), Some(4));
+    }
+}
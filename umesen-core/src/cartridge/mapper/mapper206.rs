@@ -0,0 +1,78 @@
+use crate::cartridge::{Bank, BankMapping, Mapper};
+
+/// INES designation for Namcot 108 boards, an earlier version of MMC3 without IRQs or
+/// mirroring control
+/// https://www.nesdev.org/wiki/INES_Mapper_206
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Mapper206 {
+    registers: [u8; 8],
+    selected_register_index: usize,
+}
+
+impl Mapper for Mapper206 {
+    fn map_cpu_read(&self, address: u16) -> Option<BankMapping> {
+        Some(match address {
+            0x8000..=0x9fff => (8, Bank::Number(self.registers[6])),
+            0xa000..=0xbfff => (8, Bank::Number(self.registers[7])),
+            0xc000..=0xdfff => (8, Bank::FromLast(1)),
+            0xe000..=0xffff => (8, Bank::FromLast(0)),
+            _ => return None,
+        })
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x8000..=0x9fff if address.is_multiple_of(2) => {
+                self.selected_register_index = (value & 0b111) as usize;
+            }
+            0x8000..=0x9fff => {
+                let mask = match self.selected_register_index {
+                    // 2kb CHR banks ignore the low bit
+                    0 | 1 => 0x3e,
+                    2..=5 => 0x3f,
+                    _ => 0x0f,
+                };
+                self.registers[self.selected_register_index] = value & mask;
+            }
+            _ => (),
+        }
+    }
+
+    fn map_ppu(&self, address: u16) -> BankMapping {
+        let i = address as usize / 0x400;
+        let bank = match i {
+            0..=3 => self.registers[i / 2] | (i as u8 & 1),
+            _ => self.registers[i - 2],
+        };
+        (1, Bank::Number(bank))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cartridge::mapper::test::create_test_catridge;
+
+    #[test]
+    fn test() {
+        let mut cartridge = create_test_catridge(
+            206,
+            8,
+            &[&[1], &[2], &[3], &[4]],
+            1,
+            &[&[0], &[1], &[2], &[3], &[4], &[5], &[6], &[7]],
+        );
+        assert_eq!(cartridge.cpu_read(0xc000), Some(3));
+        assert_eq!(cartridge.cpu_read(0xe000), Some(4));
+
+        cartridge.cpu_write(0x8000, 6);
+        cartridge.cpu_write(0x8001, 1);
+        cartridge.cpu_write(0x8000, 1);
+        cartridge.cpu_write(0x8001, 5);
+        cartridge.cpu_write(0x8000, 5);
+        cartridge.cpu_write(0x8001, 7);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(2));
+        assert_eq!(cartridge.ppu_read(0x0800), Some(4));
+        assert_eq!(cartridge.ppu_read(0x0c00), Some(5));
+        assert_eq!(cartridge.ppu_read(0x1c00), Some(7));
+    }
+}
@@ -1,23 +1,147 @@
-use crate::cartridge::{BankMapping, Mirroring};
+use crate::{
+    apu::{ExpansionSample, ExpansionSamples},
+    cartridge::{Bank, BankMapping, CartridgeHeader, FdsDisk, Mirroring},
+};
 
 use mapper000::Mapper000;
 use mapper001::Mapper001;
 use mapper002::Mapper002;
 use mapper003::Mapper003;
 use mapper004::Mapper004;
+use mapper005::Mapper005;
+use mapper007::Mapper007;
+use mapper009::Mapper009;
+use mapper011::Mapper011;
+use mapper019::Mapper019;
+pub use mapper020::Mapper020;
+use mapper021::Mapper021;
+use mapper024::Mapper024;
+use mapper034::Mapper034;
+use mapper066::Mapper066;
+use mapper069::Mapper069;
+use mapper071::Mapper071;
+use mapper079::Mapper079;
+use mapper206::Mapper206;
+pub use nsf_mapper::NsfMapper;
 
+mod fds_audio;
 mod mapper000;
 mod mapper001;
 mod mapper002;
 mod mapper003;
 mod mapper004;
+mod mapper005;
+mod mapper007;
+mod mapper009;
+mod mapper011;
+mod mapper019;
+mod mapper020;
+mod mapper021;
+mod mapper024;
+mod mapper034;
+mod mapper066;
+mod mapper069;
+mod mapper071;
+mod mapper079;
+mod mapper206;
+mod nsf_mapper;
+mod vrc_irq;
+
+/// Where a nametable address at $2000-$3eff is mapped to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NametableMapping {
+    /// One of the two 1kb pages of nametable ram inside the console
+    Ciram(u8),
+    /// Handled by the mapper with peek_nametable and write_nametable
+    Mapper,
+    /// A bank of CHR memory
+    Chr(BankMapping),
+    /// One of the 1kb pages of extra nametable ram on the cartridge used for four screen mirroring
+    CartridgeRam(u8),
+}
 
 /// Generic trait for underlying circuitry inside a catridge that will read and write to a catridge memory bank
-pub trait Mapper: std::fmt::Debug {
+pub trait Mapper: std::fmt::Debug + MapperState {
     fn map_cpu_read(&self, address: u16) -> Option<BankMapping>;
     fn cpu_write(&mut self, address: u16, value: u8);
     fn map_ppu(&self, address: u16) -> BankMapping;
     fn monitor_ppu(&mut self, _address: u16) {}
+    /// Address CHR memory is read from, for mappers that drive some of the address lines
+    fn chr_read_address(&self, address: u16) -> u16 {
+        address
+    }
+    /// Read from CHR memory inside the mapper, this gets checked before map_ppu
+    fn peek_ppu_read(&self, _address: u16) -> Option<u8> {
+        None
+    }
+    /// Write to CHR memory inside the mapper, returns false if the address isn't mapped to it
+    fn ppu_write(&mut self, _address: u16, _value: u8) -> bool {
+        false
+    }
+    /// Read from registers inside the mapper without side effects
+    fn peek_cpu_read(&self, _address: u16) -> Option<u8> {
+        None
+    }
+    /// Read from registers inside the mapper, for reads that have side effects
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        self.peek_cpu_read(address)
+    }
+    /// Value the CPU read from the cartridge memory, for mappers that listen to the data bus
+    fn monitor_cpu_read(&mut self, _address: u16, _value: u8) {}
+    /// Map an address to the PRG RAM, this gets checked before map_cpu_read
+    fn map_prg_ram(&self, address: u16, _write: bool) -> Option<BankMapping> {
+        matches!(address, 0x6000..=0x7fff).then_some((8, Bank::Number(0)))
+    }
+    /// Option to override where nametables are mapped instead of using mirroring
+    fn map_nametable(&self, _address: u16) -> Option<NametableMapping> {
+        None
+    }
+    fn peek_nametable(&self, _address: u16) -> u8 {
+        0
+    }
+    fn write_nametable(&mut self, _address: u16, _value: u8) {}
+    /// Ran on every cpu cycle
+    fn clock(&mut self) {}
+    /// Output of the sound chip inside the cartridge, mixed into the APU output
+    fn audio_sample(&self) -> Option<ExpansionSample> {
+        None
+    }
+    /// Outputs of every sound chip, only needed when there can be more than one
+    fn audio_samples(&self) -> ExpansionSamples {
+        let mut samples = ExpansionSamples::default();
+        samples[0] = self.audio_sample();
+        samples
+    }
+    /// Memory inside the mapper that is kept by the battery, saved after the PRG RAM
+    fn battery_ram(&self) -> Option<&[u8]> {
+        None
+    }
+    fn battery_ram_mut(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+    /// Memory inside the mapper that is filled on power on, unless it's also the battery RAM
+    /// of a cartridge with a battery
+    fn internal_ram_mut(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+    /// Disk inside the Famicom Disk System drive
+    fn disk(&self) -> Option<&FdsDisk> {
+        None
+    }
+    fn disk_mut(&mut self) -> Option<&mut FdsDisk> {
+        None
+    }
+    /// Side of the disk that is inserted, None if the disk is ejected
+    fn disk_side(&self) -> Option<usize> {
+        None
+    }
+    /// Eject the disk and insert the next side after a delay
+    fn switch_disk_side(&mut self) {}
+    /// Track of the NSF that gets played after a reset
+    fn nsf_track(&self) -> Option<u8> {
+        None
+    }
+    fn select_nsf_track(&mut self, _track: u8) {}
     fn reset(&mut self) {}
     /// Used to send irq to cpu
     fn irq_status(&self) -> bool {
@@ -29,13 +153,53 @@ pub trait Mapper: std::fmt::Debug {
     }
 }
 
-pub fn create_mapper(id: u16) -> Option<Box<dyn Mapper>> {
-    Some(match id {
+/// Saving and loading the internal registers of a mapper for save states
+/// This is implemented automatically for every mapper that derives serde's Serialize and Deserialize
+pub trait MapperState {
+    fn save_state(&self) -> Vec<u8>;
+    fn load_state(&mut self, bytes: &[u8]) -> bincode::Result<()>;
+}
+
+impl<T: serde::Serialize + serde::de::DeserializeOwned> MapperState for T {
+    fn save_state(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("mapper state should always be serializable")
+    }
+
+    fn load_state(&mut self, bytes: &[u8]) -> bincode::Result<()> {
+        *self = bincode::deserialize(bytes)?;
+        Ok(())
+    }
+}
+
+pub fn create_mapper(header: &CartridgeHeader) -> Option<Box<dyn Mapper>> {
+    Some(match header.mapper_id {
         0 => Box::new(Mapper000::default()),
         1 => Box::new(Mapper001::default()),
         2 => Box::new(Mapper002::default()),
         3 => Box::new(Mapper003::default()),
-        4 => Box::new(Mapper004::default()),
+        4 | 118 | 119 => Box::new(Mapper004::new(header)),
+        5 => Box::new(Mapper005::default()),
+        7 => Box::new(Mapper007::default()),
+        9 => Box::new(Mapper009::default()),
+        10 => Box::new(Mapper009::mmc4()),
+        11 => Box::new(Mapper011::default()),
+        19 => Box::new(Mapper019::default()),
+        21 | 22 | 23 | 25 => Box::new(Mapper021::new(header.mapper_id, header.submapper_id)),
+        24 => Box::new(Mapper024::default()),
+        26 => Box::new(Mapper024::vrc6b()),
+        34 if header.submapper_id == 1
+            || (header.submapper_id == 0
+                && header.chr_mem_is_rom
+                && header.chr_mem_size > 8 * 1024) =>
+        {
+            Box::new(Mapper034::nina_001())
+        }
+        34 => Box::new(Mapper034::default()),
+        66 => Box::new(Mapper066::default()),
+        69 => Box::new(Mapper069::default()),
+        71 => Box::new(Mapper071::default()),
+        79 => Box::new(Mapper079::default()),
+        206 => Box::new(Mapper206::default()),
         _ => return None,
     })
 }
@@ -56,7 +220,7 @@ mod test {
         Cartridge::from_mapper(mapper_id, vec![0; 1024], prg_rom, chr_rom).unwrap()
     }
 
-    fn create_banks_rom(bank_size: usize, banks_values: &[&[u8]]) -> Vec<u8> {
+    pub fn create_banks_rom(bank_size: usize, banks_values: &[&[u8]]) -> Vec<u8> {
         let mut rom = vec![0; bank_size * 1024 * banks_values.len()];
         for (i, bank) in banks_values.iter().enumerate() {
             for (j, value) in bank.iter().enumerate() {
@@ -0,0 +1,390 @@
+use crate::{
+    apu::{ExpansionChip, ExpansionSamples},
+    cartridge::{
+        Bank, BankMapping, FixedArray, Mapper,
+        mapper::{
+            fds_audio::FdsAudio, mapper005::Mapper005, mapper019::Mapper019, mapper024::Mapper024,
+            mapper069::Mapper069,
+        },
+        nsf::{Nsf, NsfChips},
+    },
+    region::Region,
+};
+
+/// Where the driver code is mapped, no sound chip uses this area
+const DRIVER_ADDRESS: u16 = 0x4100;
+/// Offsets into the driver code of the values that depend on the file
+const TRACK_OFFSET: usize = 0x34;
+const REGION_OFFSET: usize = 0x36;
+const INIT_OFFSET: usize = 0x38;
+const PLAY_OFFSET: usize = 0x40;
+/// Address of an RTI used for the NMI and IRQ vectors
+const RTI_ADDRESS: u16 = DRIVER_ADDRESS + 0x45;
+/// Reads 1 once it is time to call the play routine, reading clears it
+const PLAY_FLAG_ADDRESS: u16 = DRIVER_ADDRESS + 0x46;
+
+/// Code ran on reset that sets up the console the way the NSF spec requires, calls the init routine
+/// then calls the play routine every time the play flag is set
+#[rustfmt::skip]
+const DRIVER: [u8; 0x46] = [
+    0x78,             // sei
+    0xd8,             // cld
+    0xa2, 0xff,       // ldx #$ff
+    0x9a,             // txs
+    0xe8,             // inx
+    0x8a,             // txa
+    // Clear the internal RAM
+    0x95, 0x00,       // sta $00,x
+    0x9d, 0x00, 0x01, // sta $0100,x
+    0x9d, 0x00, 0x02, // sta $0200,x
+    0x9d, 0x00, 0x03, // sta $0300,x
+    0x9d, 0x00, 0x04, // sta $0400,x
+    0x9d, 0x00, 0x05, // sta $0500,x
+    0x9d, 0x00, 0x06, // sta $0600,x
+    0x9d, 0x00, 0x07, // sta $0700,x
+    0xe8,             // inx
+    0xd0, 0xe6,       // bne $4107
+    // Silence the APU
+    0xa2, 0x13,       // ldx #$13
+    0x9d, 0x00, 0x40, // sta $4000,x
+    0xca,             // dex
+    0x10, 0xfa,       // bpl $4123
+    0xa9, 0x0f,       // lda #$0f
+    0x8d, 0x15, 0x40, // sta $4015
+    0xa9, 0x40,       // lda #$40
+    0x8d, 0x17, 0x40, // sta $4017
+    // Call init with the track in A and the region in X
+    0xa9, 0x00,       // lda #track
+    0xa2, 0x00,       // ldx #region
+    0x20, 0x00, 0x00, // jsr init
+    // Call play whenever the play flag is set
+    0xad, 0x46, 0x41, // lda $4146
+    0xf0, 0xfb,       // beq $413a
+    0x20, 0x00, 0x00, // jsr play
+    0x4c, 0x3a, 0x41, // jmp $413a
+    0x40,             // rti
+];
+
+/// Cartridge made up to play an NSF, with the bank switching at $5ff8-$5fff from the NSF spec,
+/// a small driver that calls the init and play routines and every sound chip the file uses
+/// Created by Cartridge::from_nsf since it needs the file
+/// https://www.nesdev.org/wiki/NSF
+/// FDS files run from RAM, so the whole file is put in PRG RAM and banks at $5ff6-$5fff map
+/// $6000-$ffff into it
+/// VRC7 audio is not supported
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct NsfMapper {
+    driver: Vec<u8>,
+    track: u8,
+    /// Banks for each 4kb at $6000-$ffff, $6000-$7fff is only banked for FDS files
+    bank_init: [u8; 10],
+    banks: [u8; 10],
+    /// CPU cycles between each call of the play routine
+    play_period: u32,
+    play_timer: u32,
+    play_pending: bool,
+    /// Kept in the mapper so it can be cleared when a track starts
+    prg_ram: FixedArray<u8, 0x2000>,
+
+    chips: NsfChips,
+    vrc6: Option<Mapper024>,
+    namco163: Option<Mapper019>,
+    sunsoft5b: Option<Mapper069>,
+    mmc5: Option<Mapper005>,
+    fds: Option<FdsAudio>,
+}
+
+impl NsfMapper {
+    pub fn new(nsf: &Nsf) -> Self {
+        let region = nsf.region();
+        let play_period = match region {
+            Region::Pal => nsf.pal_play_period,
+            Region::Ntsc | Region::Dendy => nsf.ntsc_play_period,
+        };
+
+        let mut driver = DRIVER.to_vec();
+        driver[REGION_OFFSET] = (region == Region::Pal) as u8;
+        driver[INIT_OFFSET..INIT_OFFSET + 2].copy_from_slice(&nsf.init_address.to_le_bytes());
+        driver[PLAY_OFFSET..PLAY_OFFSET + 2].copy_from_slice(&nsf.play_address.to_le_bytes());
+
+        if nsf.chips.contains(NsfChips::VRC7) {
+            log::warn!("VRC7 audio is not supported");
+        }
+
+        let fds = nsf.is_fds();
+        let bank_init = match nsf.bank_init {
+            // FDS files use the $e000 and $f000 banks for $6000 and $7000 too
+            Some(init) => [
+                init[6], init[7], init[0], init[1], init[2], init[3], init[4], init[5], init[6],
+                init[7],
+            ],
+            None if fds => [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+            None => [0, 0, 0, 1, 2, 3, 4, 5, 6, 7],
+        };
+
+        let mut mapper = Self {
+            driver,
+            bank_init,
+            play_period: (play_period as f32 * region.cpu_clock_hz() / 1_000_000.) as u32,
+            chips: nsf.chips,
+            ..Default::default()
+        };
+        mapper.select_nsf_track(nsf.starting_track);
+        mapper
+    }
+
+    fn bank(&self, address: u16) -> Bank {
+        Bank::Number(self.banks[(address as usize - 0x6000) / 0x1000])
+    }
+}
+
+impl Mapper for NsfMapper {
+    fn map_cpu_read(&self, address: u16) -> Option<BankMapping> {
+        match address {
+            0x8000..=0xffff => Some((4, self.bank(address))),
+            _ => None,
+        }
+    }
+
+    fn map_prg_ram(&self, address: u16, _write: bool) -> Option<BankMapping> {
+        match address {
+            0x6000..=0xffff if self.chips.contains(NsfChips::FDS) => Some((4, self.bank(address))),
+            _ => None,
+        }
+    }
+
+    fn peek_cpu_read(&self, address: u16) -> Option<u8> {
+        let driver_end = DRIVER_ADDRESS + self.driver.len() as u16;
+        match address {
+            _ if (DRIVER_ADDRESS..driver_end).contains(&address) => {
+                Some(self.driver[(address - DRIVER_ADDRESS) as usize])
+            }
+            PLAY_FLAG_ADDRESS => Some(self.play_pending as u8),
+            0xfffa | 0xfffe => Some(RTI_ADDRESS as u8),
+            0xfffb | 0xffff => Some((RTI_ADDRESS >> 8) as u8),
+            0xfffc => Some(DRIVER_ADDRESS as u8),
+            0xfffd => Some((DRIVER_ADDRESS >> 8) as u8),
+            0x6000..=0x7fff if !self.chips.contains(NsfChips::FDS) => {
+                Some(self.prg_ram[address as usize - 0x6000])
+            }
+            0x4040..=0x4097 => self.fds.as_ref().and_then(|fds| fds.read(address)),
+            0x4800..=0x4fff => self.namco163.as_ref()?.peek_cpu_read(address),
+            0x5000..=0x5fff => self.mmc5.as_ref()?.peek_cpu_read(address),
+            _ => None,
+        }
+    }
+
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            PLAY_FLAG_ADDRESS => Some(std::mem::take(&mut self.play_pending) as u8),
+            0x4800..=0x4fff => self.namco163.as_mut()?.cpu_read(address),
+            _ => self.peek_cpu_read(address),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x5ff6..=0x5ff7 if self.chips.contains(NsfChips::FDS) => {
+                self.banks[address as usize - 0x5ff6] = value
+            }
+            0x5ff8..=0x5fff => self.banks[address as usize - 0x5ff6] = value,
+            0x6000..=0x7fff => self.prg_ram[address as usize - 0x6000] = value,
+            _ => (),
+        }
+
+        if let Some(vrc6) = &mut self.vrc6
+            && matches!(address, 0x9000..=0x9003 | 0xa000..=0xa002 | 0xb000..=0xb002)
+        {
+            vrc6.cpu_write(address, value);
+        }
+        if let Some(namco163) = &mut self.namco163
+            && matches!(address, 0x4800..=0x4fff | 0xf800..=0xffff)
+        {
+            namco163.cpu_write(address, value);
+        }
+        if let Some(sunsoft5b) = &mut self.sunsoft5b
+            && matches!(address, 0xc000..=0xffff)
+        {
+            sunsoft5b.cpu_write(address, value);
+        }
+        if let Some(mmc5) = &mut self.mmc5
+            && matches!(address, 0x5000..=0x5015 | 0x5205 | 0x5206 | 0x5c00..=0x5ff5)
+        {
+            mmc5.cpu_write(address, value);
+        }
+        if let Some(fds) = &mut self.fds {
+            fds.write(address, value);
+        }
+    }
+
+    fn map_ppu(&self, _address: u16) -> BankMapping {
+        (8, Bank::Number(0))
+    }
+
+    fn clock(&mut self) {
+        self.play_timer = self.play_timer.saturating_sub(1);
+        if self.play_timer == 0 {
+            self.play_timer = self.play_period;
+            self.play_pending = true;
+        }
+
+        if let Some(vrc6) = &mut self.vrc6 {
+            vrc6.clock();
+        }
+        if let Some(namco163) = &mut self.namco163 {
+            namco163.clock();
+        }
+        if let Some(sunsoft5b) = &mut self.sunsoft5b {
+            sunsoft5b.clock();
+        }
+        if let Some(mmc5) = &mut self.mmc5 {
+            mmc5.clock();
+        }
+        if let Some(fds) = &mut self.fds {
+            fds.clock();
+        }
+    }
+
+    fn audio_samples(&self) -> ExpansionSamples {
+        [
+            self.vrc6.as_ref().and_then(|vrc6| vrc6.audio_sample()),
+            self.namco163.as_ref().and_then(|n163| n163.audio_sample()),
+            self.sunsoft5b.as_ref().and_then(|s5b| s5b.audio_sample()),
+            self.mmc5.as_ref().and_then(|mmc5| mmc5.audio_sample()),
+            self.fds
+                .as_ref()
+                .map(|fds| (ExpansionChip::Fds, fds.sample())),
+            None,
+        ]
+    }
+
+    fn nsf_track(&self) -> Option<u8> {
+        Some(self.track)
+    }
+
+    fn select_nsf_track(&mut self, track: u8) {
+        self.track = track;
+        self.driver[TRACK_OFFSET] = track;
+    }
+
+    fn reset(&mut self) {
+        self.banks = self.bank_init;
+        self.play_timer = self.play_period;
+        self.play_pending = false;
+        self.prg_ram = FixedArray::default();
+
+        // Start every track with the sound chips silent
+        let chips = self.chips;
+        self.vrc6 = chips.contains(NsfChips::VRC6).then(Mapper024::default);
+        self.namco163 = chips.contains(NsfChips::NAMCO163).then(Mapper019::default);
+        self.sunsoft5b = chips.contains(NsfChips::SUNSOFT5B).then(Mapper069::default);
+        self.mmc5 = chips.contains(NsfChips::MMC5).then(|| {
+            // Let the ExRAM be used as normal RAM
+            let mut mmc5 = Mapper005::default();
+            mmc5.cpu_write(0x5104, 2);
+            mmc5
+        });
+        self.fds = chips.contains(NsfChips::FDS).then(FdsAudio::default);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Cartridge, Cpu,
+        cartridge::{NsfChips, mapper::nsf_mapper::PLAY_FLAG_ADDRESS},
+    };
+
+    /// NSF with init storing the track at $00 and play counting up at $01
+    fn create_test_nsf(bank_switched: bool) -> Vec<u8> {
+        let mut bytes = b"NESM\x1a\x01\x04\x02".to_vec();
+        bytes.extend_from_slice(&[0x00, 0x80, 0x00, 0x80, 0x03, 0x80]);
+        bytes.resize(0x80, 0);
+        if bank_switched {
+            bytes[0x77] = 2;
+        }
+        bytes.extend_from_slice(&[
+            0x85, 0x00, // sta $00
+            0x60, // rts
+            0xe6, 0x01, // inc $01
+            0x60, // rts
+        ]);
+        bytes.resize(0x80 + 0x3000, 0);
+        // Bank 2 at $f000
+        bytes[0x80 + 0x2000] = 0xaa;
+        bytes
+    }
+
+    fn setup_cpu(bank_switched: bool) -> Cpu {
+        let cartridge = Cartridge::from_nsf(&create_test_nsf(bank_switched)[..]).unwrap();
+        let mut cpu = Cpu::default();
+        cpu.bus.attach_catridge(cartridge);
+        cpu.reset();
+        cpu
+    }
+
+    #[test]
+    fn init_and_play() {
+        let mut cpu = setup_cpu(false);
+        // Init and 3 calls of play at 60hz
+        while cpu.bus.cpu_cycles_total < 29780 * 3 + 1000 {
+            cpu.execute_next().unwrap();
+        }
+        assert_eq!(cpu.bus.peek_read(0x00), 1);
+        assert_eq!(cpu.bus.peek_read(0x01), 3);
+
+        cpu.bus.cartridge_mut().unwrap().select_nsf_track(3);
+        cpu.reset();
+        while cpu.bus.cpu_cycles_total < 20000 {
+            cpu.execute_next().unwrap();
+        }
+        assert_eq!(cpu.bus.peek_read(0x00), 3);
+        assert_eq!(cpu.bus.peek_read(0x01), 0);
+        assert_eq!(cpu.bus.cartridge().unwrap().nsf_track(), Some(3));
+    }
+
+    #[test]
+    fn bank_switching() {
+        let mut cpu = setup_cpu(true);
+        assert_eq!(cpu.bus.peek_read(0xf000), 0xaa);
+        cpu.bus.write(0x5fff, 0);
+        assert_eq!(cpu.bus.peek_read(0xf000), 0x85);
+        // Vectors always point to the driver
+        assert_eq!(cpu.bus.peek_read(0xfffd), 0x41);
+    }
+
+    #[test]
+    fn fds_ram() {
+        let mut bytes = create_test_nsf(false);
+        bytes[0x08..0x0e].copy_from_slice(&[0x00, 0x60, 0x00, 0x60, 0x03, 0x60]);
+        bytes[0x7b] = NsfChips::FDS.bits();
+        let mut cpu = Cpu::default();
+        cpu.bus
+            .attach_catridge(Cartridge::from_nsf(&bytes[..]).unwrap());
+        cpu.reset();
+        assert_eq!(cpu.bus.peek_read(0x6000), 0x85);
+
+        cpu.bus.write(0x9000, 0x12);
+        assert_eq!(cpu.bus.peek_read(0x9000), 0x12);
+        // $6000 is switched to the bank at $8000
+        cpu.bus.write(0x5ff6, 2);
+        assert_eq!(cpu.bus.peek_read(0x6000), 0xaa);
+
+        // Starting a track loads the file again
+        cpu.reset();
+        assert_eq!(cpu.bus.peek_read(0x9000), 0);
+        assert_eq!(cpu.bus.peek_read(0x6000), 0x85);
+    }
+
+    #[test]
+    fn play_flag() {
+        let mut cpu = setup_cpu(false);
+        let cartridge = cpu.bus.cartridge_mut().unwrap();
+        assert_eq!(cartridge.cpu_read(PLAY_FLAG_ADDRESS), Some(0));
+        for _ in 0..29780 {
+            cartridge.clock();
+        }
+        assert_eq!(cartridge.cpu_read(PLAY_FLAG_ADDRESS), Some(1));
+        assert_eq!(cartridge.cpu_read(PLAY_FLAG_ADDRESS), Some(0));
+    }
+}
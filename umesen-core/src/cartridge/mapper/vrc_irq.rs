@@ -0,0 +1,97 @@
+/// CPU cycles per scanline times 3
+const PRESCALER_PERIOD: i16 = 341;
+
+/// IRQ counter shared by the Konami VRC mappers
+/// https://www.nesdev.org/wiki/VRC_IRQ
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enabled: bool,
+    enabled_after_ack: bool,
+    /// Counts CPU cycles instead of scanlines
+    cycle_mode: bool,
+    pub status: bool,
+}
+
+impl VrcIrq {
+    pub fn write_latch(&mut self, value: u8) {
+        self.latch = value;
+    }
+
+    pub fn write_latch_low(&mut self, value: u8) {
+        self.latch = (self.latch & 0xf0) | (value & 0x0f);
+    }
+
+    pub fn write_latch_high(&mut self, value: u8) {
+        self.latch = (self.latch & 0x0f) | (value << 4);
+    }
+
+    pub fn write_control(&mut self, value: u8) {
+        self.enabled_after_ack = value & 0b001 != 0;
+        self.enabled = value & 0b010 != 0;
+        self.cycle_mode = value & 0b100 != 0;
+        self.status = false;
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = PRESCALER_PERIOD;
+        }
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.status = false;
+        self.enabled = self.enabled_after_ack;
+    }
+
+    /// Ran on every CPU cycle
+    pub fn clock(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if self.cycle_mode {
+            self.clock_counter();
+        } else {
+            self.prescaler -= 3;
+            if self.prescaler <= 0 {
+                self.prescaler += PRESCALER_PERIOD;
+                self.clock_counter();
+            }
+        }
+    }
+
+    fn clock_counter(&mut self) {
+        if self.counter == 0xff {
+            self.counter = self.latch;
+            self.status = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::VrcIrq;
+
+    #[test]
+    fn scanline_mode() {
+        let mut irq = VrcIrq::default();
+        irq.write_latch(0xfe);
+        irq.write_control(0b011);
+        // 2 scanlines of 113.67 CPU cycles
+        for _ in 0..227 {
+            irq.clock();
+        }
+        assert!(!irq.status);
+        irq.clock();
+        assert!(irq.status);
+
+        irq.acknowledge();
+        assert!(!irq.status);
+        irq.write_control(0b110);
+        irq.clock();
+        irq.clock();
+        assert!(irq.status);
+    }
+}
@@ -1,33 +1,102 @@
 mod cartridge_banks;
 mod cartridge_header;
+mod fds_disk;
+mod game_database;
 mod mapper;
+mod nsf;
 
 pub use cartridge_banks::*;
 pub use cartridge_header::*;
-pub use mapper::{Mapper, create_mapper};
+pub use fds_disk::FdsDisk;
+pub use game_database::{GameDatabase, GameDatabaseEntry, RomHash, convert_nes20db};
+pub use mapper::{Mapper, MapperState, NametableMapping, create_mapper};
+pub use nsf::{Nsf, NsfChips};
+
+use mapper::{Mapper020, NsfMapper};
+
+use crate::{
+    apu::{ExpansionSample, ExpansionSamples},
+    emulator::SaveStateError,
+    power_on::PowerOnConfig,
+};
+
+pub const FDS_BIOS_SIZE: usize = 0x2000;
+/// Mapper id given to NSF files, which is outside the range of INES mapper ids
+pub const NSF_MAPPER_ID: u16 = 0xffff;
+
+/// The parts of the cartridge that can change while running, used for save states
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct CartridgeState {
+    mapper_id: u16,
+    prg_ram: MemoryBanks,
+    /// Only saved when chr memory is ram
+    chr_ram: Option<MemoryBanks>,
+    nametable_ram: MemoryBanks,
+    mapper: Vec<u8>,
+}
 
 pub struct Cartridge {
     banks: CartridgeBanks,
     header: CartridgeHeader,
     mapper: Box<dyn Mapper>,
+    /// Extra 2kb of nametable ram for four screen mirroring, empty otherwise
+    nametable_ram: MemoryBanks,
+    rom_crc32: u32,
+    /// Header from the ROM file if it was corrected using the game database
+    original_header: Option<CartridgeHeader>,
+    /// Set when playing an NSF file
+    nsf: Option<Nsf>,
 }
 
 impl Cartridge {
     fn new(header: CartridgeHeader, banks: CartridgeBanks) -> Result<Self, NesParseError> {
-        let mut mapper = create_mapper(header.mapper_id)
-            .ok_or(NesParseError::UnsupportedMapper(header.mapper_id))?;
+        let mapper =
+            create_mapper(&header).ok_or(NesParseError::UnsupportedMapper(header.mapper_id))?;
+        Ok(Self::with_mapper(header, banks, mapper))
+    }
+
+    fn with_mapper(
+        header: CartridgeHeader,
+        banks: CartridgeBanks,
+        mut mapper: Box<dyn Mapper>,
+    ) -> Self {
         mapper.reset();
-        Ok(Cartridge {
+
+        let chr_rom = if header.chr_mem_is_rom {
+            banks.chr_mem.as_slice()
+        } else {
+            &[]
+        };
+
+        let nametable_ram = if header.mirroring == Mirroring::FourScreen {
+            vec![0; 0x800]
+        } else {
+            Vec::new()
+        };
+
+        Cartridge {
             mapper,
+            nametable_ram: MemoryBanks::new(nametable_ram),
+            rom_crc32: rom_crc32(banks.prg_rom.as_slice(), chr_rom),
             header,
             banks,
-        })
+            original_header: None,
+            nsf: None,
+        }
+    }
+
+    pub fn from_nes(bytes: impl std::io::Read) -> Result<Self, NesParseError> {
+        Self::from_nes_with_database(bytes, GameDatabase::embedded())
     }
 
-    pub fn from_nes(mut bytes: impl std::io::Read) -> Result<Self, NesParseError> {
+    /// Load a .nes file and correct its header from a game database
+    pub fn from_nes_with_database(
+        mut bytes: impl std::io::Read,
+        database: &GameDatabase,
+    ) -> Result<Self, NesParseError> {
         let mut header_data = [0; 16];
         bytes.read_exact(&mut header_data)?;
-        let header = CartridgeHeader::from_nes(header_data)?;
+        let mut header = CartridgeHeader::from_nes(header_data)?;
 
         let mut trainer_data = vec![0; CartridgeHeader::TRAINER_SIZE];
         if header.has_trainer {
@@ -36,19 +105,88 @@ impl Cartridge {
 
         let mut prg_rom = vec![0; header.prg_rom_size];
         bytes.read_exact(&mut prg_rom)?;
-        let mut chr_mem = vec![0; header.chr_mem_size];
+        let mut chr_rom = vec![0; header.chr_mem_size];
         if header.chr_mem_is_rom {
-            bytes.read_exact(&mut chr_mem)?;
+            bytes.read_exact(&mut chr_rom)?;
+        } else {
+            chr_rom.clear();
         }
 
+        let original_header = header.clone();
+        if let Some(entry) = database.find(&prg_rom, &chr_rom) {
+            entry.apply(&mut header);
+            if header != original_header {
+                log::info!("Corrected header of '{}' from game database", entry.name);
+            }
+        }
+
+        let chr_mem = if header.chr_mem_is_rom {
+            chr_rom
+        } else {
+            vec![0; header.chr_mem_size]
+        };
         let banks = CartridgeBanks::new(vec![0; header.prg_ram_size], prg_rom, chr_mem);
+        let corrected = header != original_header;
         let mut cartridge = Self::new(header, banks)?;
+        cartridge.original_header = corrected.then_some(original_header);
         for (i, byte) in trainer_data.iter().enumerate() {
             cartridge.cpu_write((0x7000 + i) as u16, *byte);
         }
         Ok(cartridge)
     }
 
+    /// Creates a Famicom Disk System with the disk image inserted
+    pub fn from_fds(
+        mut bios: impl std::io::Read,
+        mut image: impl std::io::Read,
+    ) -> Result<Self, NesParseError> {
+        let mut bios_data = vec![0; FDS_BIOS_SIZE];
+        bios.read_exact(&mut bios_data)?;
+        let mut image_data = Vec::new();
+        image.read_to_end(&mut image_data)?;
+        let disk = FdsDisk::from_fds(&image_data)?;
+
+        let header = CartridgeHeader {
+            mapper_id: 20,
+            prg_rom_size: FDS_BIOS_SIZE,
+            prg_ram_size: 0x8000,
+            chr_mem_size: 0x2000,
+            ..Default::default()
+        };
+        let banks = CartridgeBanks::new(
+            vec![0; header.prg_ram_size],
+            bios_data,
+            vec![0; header.chr_mem_size],
+        );
+        let mut cartridge = Self::with_mapper(header, banks, Box::new(Mapper020::new(disk)));
+        // The BIOS is the same for every game so only the disk identifies it
+        cartridge.rom_crc32 = rom_crc32(&image_data, &[]);
+        Ok(cartridge)
+    }
+
+    /// Creates a cartridge that plays an NSF or NSFe file
+    pub fn from_nsf(mut bytes: impl std::io::Read) -> Result<Self, NesParseError> {
+        let mut data = Vec::new();
+        bytes.read_to_end(&mut data)?;
+        let nsf = Nsf::from_bytes(&data)?;
+
+        let prg_rom = nsf.prg_rom();
+        let header = CartridgeHeader {
+            mapper_id: NSF_MAPPER_ID,
+            prg_rom_size: prg_rom.len(),
+            chr_mem_size: 0x2000,
+            region: nsf.region(),
+            ..Default::default()
+        };
+        // FDS files run from RAM which gets the file copied in on reset
+        let prg_ram = vec![0; if nsf.is_fds() { prg_rom.len() } else { 0 }];
+        let banks = CartridgeBanks::new(prg_ram, prg_rom, vec![0; header.chr_mem_size]);
+        let mapper = Box::new(NsfMapper::new(&nsf));
+        let mut cartridge = Self::with_mapper(header, banks, mapper);
+        cartridge.nsf = Some(nsf);
+        Ok(cartridge)
+    }
+
     pub fn from_mapper(
         mapper_id: u16,
         prg_ram: Vec<u8>,
@@ -64,28 +202,43 @@ impl Cartridge {
         )
     }
 
-    pub fn cpu_read(&self, address: u16) -> Option<u8> {
-        if let Some(mapping) = self.mapper.map_cpu_read(address) {
+    pub fn cpu_peek_read(&self, address: u16) -> Option<u8> {
+        if let Some(value) = self.mapper.peek_cpu_read(address) {
+            Some(value)
+        } else if let Some(mapping) = self.mapper.map_prg_ram(address, false) {
+            self.banks.prg_ram.read(mapping, address)
+        } else if let Some(mapping) = self.mapper.map_cpu_read(address) {
             self.banks.prg_rom.read(mapping, address)
-        } else if let 0x6000..=0x7fff = address {
-            self.banks.prg_ram.read((8, Bank::Number(0)), address)
         } else {
             None
         }
     }
 
+    pub fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        if let Some(value) = self.mapper.cpu_read(address) {
+            return Some(value);
+        }
+        let value = self.cpu_peek_read(address);
+        if let Some(value) = value {
+            self.mapper.monitor_cpu_read(address, value);
+        }
+        value
+    }
+
     pub fn cpu_write(&mut self, address: u16, value: u8) {
-        if let 0x6000..=0x7fff = address {
-            self.banks
-                .prg_ram
-                .write((8, Bank::Number(0)), address, value);
+        if let Some(mapping) = self.mapper.map_prg_ram(address, true) {
+            self.banks.prg_ram.write(mapping, address, value);
         }
         self.mapper.cpu_write(address, value);
     }
 
     pub fn ppu_peek_read(&self, address: u16) -> Option<u8> {
         if let 0x0000..=0x1fff = address {
+            if let Some(value) = self.mapper.peek_ppu_read(address) {
+                return Some(value);
+            }
             let mapping = self.mapper.map_ppu(address);
+            let address = self.mapper.chr_read_address(address);
             self.banks.chr_mem.read(mapping, address)
         } else {
             None
@@ -99,7 +252,9 @@ impl Cartridge {
 
     pub fn ppu_write(&mut self, address: u16, value: u8) {
         self.mapper.monitor_ppu(address);
-        if let 0x0000..=0x1fff = address {
+        if let 0x0000..=0x1fff = address
+            && !self.mapper.ppu_write(address, value)
+        {
             let mapping = self.mapper.map_ppu(address);
             if !self.header.chr_mem_is_rom {
                 self.banks.chr_mem.write(mapping, address, value);
@@ -107,11 +262,69 @@ impl Cartridge {
         }
     }
 
+    /// Where a nametable address is mapped to if the mapper or four screen mirroring overrides the mirroring
+    pub fn map_nametable(&self, address: u16) -> Option<NametableMapping> {
+        self.mapper.map_nametable(address).or_else(|| {
+            // The first two nametables still use the ram inside the console
+            (self.mirroring() == Mirroring::FourScreen).then(|| {
+                let table_number = ((address >> 10) & 0b11) as u8;
+                match table_number {
+                    0 | 1 => NametableMapping::Ciram(table_number),
+                    _ => NametableMapping::CartridgeRam(table_number - 2),
+                }
+            })
+        })
+    }
+
+    pub fn peek_nametable(&self, address: u16) -> u8 {
+        match self.map_nametable(address) {
+            Some(NametableMapping::Chr(mapping)) => {
+                self.banks.chr_mem.read(mapping, address).unwrap_or(0)
+            }
+            Some(NametableMapping::CartridgeRam(page)) => self
+                .nametable_ram
+                .read((1, Bank::Number(page)), address)
+                .unwrap_or(0),
+            _ => self.mapper.peek_nametable(address),
+        }
+    }
+
+    pub fn write_nametable(&mut self, address: u16, value: u8) {
+        match self.map_nametable(address) {
+            Some(NametableMapping::Chr(mapping)) => {
+                if !self.header.chr_mem_is_rom {
+                    self.banks.chr_mem.write(mapping, address, value);
+                }
+            }
+            Some(NametableMapping::CartridgeRam(page)) => {
+                self.nametable_ram
+                    .write((1, Bank::Number(page)), address, value);
+            }
+            _ => self.mapper.write_nametable(address, value),
+        }
+    }
+
+    pub fn clock(&mut self) {
+        self.mapper.clock();
+    }
+
+    pub fn audio_sample(&self) -> Option<ExpansionSample> {
+        self.mapper.audio_sample()
+    }
+
+    pub fn audio_samples(&self) -> ExpansionSamples {
+        self.mapper.audio_samples()
+    }
+
     pub fn irq_status(&self) -> bool {
         self.mapper.irq_status()
     }
 
     pub fn mirroring(&self) -> Mirroring {
+        // Four screen boards have the nametables wired directly so the mapper can't change them
+        if self.header.mirroring == Mirroring::FourScreen {
+            return Mirroring::FourScreen;
+        }
         self.mapper.mirroring().unwrap_or(self.header.mirroring)
     }
 
@@ -119,11 +332,153 @@ impl Cartridge {
         &self.header
     }
 
+    /// The header from the ROM file if it was corrected from the game database
+    pub fn original_header(&self) -> Option<&CartridgeHeader> {
+        self.original_header.as_ref()
+    }
+
+    /// Gets the data that should be saved to a .sav file if the cartridge has a battery
+    /// This is the PRG RAM followed by the RAM inside the mapper
+    pub fn battery_data(&self) -> Option<Vec<u8>> {
+        self.header.has_battery.then(|| {
+            let mut data = self.banks.prg_ram.as_slice().to_vec();
+            data.extend_from_slice(self.mapper.battery_ram().unwrap_or_default());
+            data
+        })
+    }
+
+    /// Restores the data from battery_data, ignoring data that doesn't fit
+    pub fn load_battery_data(&mut self, data: &[u8]) {
+        if !self.header.has_battery {
+            return;
+        }
+
+        let prg_ram = self.banks.prg_ram.as_mut_slice();
+        let mapper_ram = self.mapper.battery_ram_mut().unwrap_or_default();
+        let expected_len = prg_ram.len() + mapper_ram.len();
+        if data.len() != expected_len {
+            log::warn!(
+                "Battery data size {} does not match the expected size {expected_len}",
+                data.len(),
+            );
+        }
+        let (prg_ram_data, mapper_ram_data) = data.split_at(data.len().min(prg_ram.len()));
+        prg_ram[..prg_ram_data.len()].copy_from_slice(prg_ram_data);
+        let len = mapper_ram_data.len().min(mapper_ram.len());
+        mapper_ram[..len].copy_from_slice(&mapper_ram_data[..len]);
+    }
+
+    /// Gets the disk as a .fds image if this is a Famicom Disk System
+    pub fn disk_image(&self) -> Option<Vec<u8>> {
+        self.mapper.disk().map(|disk| disk.to_fds())
+    }
+
+    /// Replaces the disk with a .fds image, used to restore the changes made to a disk
+    pub fn load_disk_image(&mut self, image: &[u8]) -> Result<(), NesParseError> {
+        if let Some(disk) = self.mapper.disk_mut() {
+            let new_disk = FdsDisk::from_fds(image)?;
+            if new_disk.side_count() != disk.side_count() {
+                return Err(NesParseError::InvalidFdsImage);
+            }
+            *disk = new_disk;
+        }
+        Ok(())
+    }
+
+    /// Side of the disk that is inserted, None if ejected or the cartridge has no disk
+    pub fn disk_side(&self) -> Option<usize> {
+        self.mapper.disk_side()
+    }
+
+    pub fn disk_side_count(&self) -> usize {
+        self.mapper.disk().map_or(0, |disk| disk.side_count())
+    }
+
+    /// Eject the disk and insert the next side after a short delay
+    pub fn switch_disk_side(&mut self) {
+        self.mapper.switch_disk_side();
+    }
+
+    /// The NSF file being played if this cartridge was made from one
+    pub fn nsf(&self) -> Option<&Nsf> {
+        self.nsf.as_ref()
+    }
+
+    pub fn nsf_track(&self) -> Option<u8> {
+        self.mapper.nsf_track()
+    }
+
+    /// Choose the track of the NSF to play, which starts on the next reset
+    pub fn select_nsf_track(&mut self, track: u8) {
+        self.mapper.select_nsf_track(track);
+    }
+
+    /// CRC32 of the PRG ROM and CHR ROM, used to identify the game regardless of the header
+    pub fn rom_crc32(&self) -> u32 {
+        self.rom_crc32
+    }
+
     pub fn debug_mapper(&self) -> String {
         format!("{:?}", self.mapper)
     }
 
     pub fn reset(&mut self) {
         self.mapper.reset();
+        if self.nsf.as_ref().is_some_and(|nsf| nsf.is_fds()) {
+            let prg_rom = self.banks.prg_rom.as_slice();
+            self.banks.prg_ram.as_mut_slice().copy_from_slice(prg_rom);
+        }
     }
+
+    /// Fill the RAM like when the console is turned on, battery backed RAM is kept
+    pub fn power_on(&mut self, config: &PowerOnConfig) {
+        if !self.header.has_battery {
+            config.fill(self.banks.prg_ram.as_mut_slice(), 3);
+        }
+        if !self.header.chr_mem_is_rom {
+            config.fill(self.banks.chr_mem.as_mut_slice(), 4);
+        }
+        config.fill(self.nametable_ram.as_mut_slice(), 5);
+        let battery_backed = self.header.has_battery && self.mapper.battery_ram().is_some();
+        if !battery_backed && let Some(ram) = self.mapper.internal_ram_mut() {
+            config.fill(ram, 6);
+        }
+    }
+
+    pub fn save_state(&self) -> CartridgeState {
+        CartridgeState {
+            mapper_id: self.header.mapper_id,
+            prg_ram: self.banks.prg_ram.clone(),
+            chr_ram: (!self.header.chr_mem_is_rom).then(|| self.banks.chr_mem.clone()),
+            nametable_ram: self.nametable_ram.clone(),
+            mapper: self.mapper.save_state(),
+        }
+    }
+
+    /// Restores the state, the state must have been saved from a cartridge with the same ROM
+    pub fn load_state(&mut self, state: CartridgeState) -> Result<(), SaveStateError> {
+        let chr_ram_len = (!self.header.chr_mem_is_rom).then(|| self.banks.chr_mem.len());
+        if state.mapper_id != self.header.mapper_id
+            || state.prg_ram.len() != self.banks.prg_ram.len()
+            || state.chr_ram.as_ref().map(|c| c.len()) != chr_ram_len
+            || state.nametable_ram.len() != self.nametable_ram.len()
+        {
+            return Err(SaveStateError::CartridgeMismatch);
+        }
+
+        self.mapper.load_state(&state.mapper)?;
+        self.banks.prg_ram = state.prg_ram;
+        self.nametable_ram = state.nametable_ram;
+        if let Some(chr_ram) = state.chr_ram {
+            self.banks.chr_mem = chr_ram;
+        }
+        Ok(())
+    }
+}
+
+fn rom_crc32(prg_rom: &[u8], chr_rom: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(prg_rom);
+    hasher.update(chr_rom);
+    hasher.finalize()
 }
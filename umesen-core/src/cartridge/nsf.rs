@@ -0,0 +1,360 @@
+use crate::{cartridge::NesParseError, region::Region};
+
+const NSF_HEADER_SIZE: usize = 0x80;
+/// Default play periods in microseconds for files that don't specify them
+const DEFAULT_NTSC_PLAY_PERIOD: u16 = 16639;
+const DEFAULT_PAL_PLAY_PERIOD: u16 = 19997;
+
+bitflags::bitflags! {
+    /// Expansion sound chips an NSF uses
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+    pub struct NsfChips: u8 {
+        const VRC6 = 1;
+        const VRC7 = 1 << 1;
+        const FDS = 1 << 2;
+        const MMC5 = 1 << 3;
+        const NAMCO163 = 1 << 4;
+        const SUNSOFT5B = 1 << 5;
+    }
+}
+
+/// Music ripped from a game, stored as an NSF or NSFe file
+/// https://www.nesdev.org/wiki/NSF
+/// https://www.nesdev.org/wiki/NSFe
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Nsf {
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    pub track_count: u8,
+    /// Zero based
+    pub starting_track: u8,
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    /// Microseconds between each call of the play routine
+    pub ntsc_play_period: u16,
+    pub pal_play_period: u16,
+    /// Initial values of the bank registers at $5ff8-$5fff, None if the file doesn't use bank switching
+    pub bank_init: Option<[u8; 8]>,
+    /// Only plays correctly on a PAL console
+    pub pal_only: bool,
+    pub chips: NsfChips,
+    /// Names of each track, empty if the file doesn't have them
+    pub track_labels: Vec<String>,
+    /// Lengths of each track in milliseconds, empty if the file doesn't have them
+    pub track_lengths: Vec<Option<u32>>,
+    data: Vec<u8>,
+}
+
+impl Nsf {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, NesParseError> {
+        let nsf = if bytes.starts_with(b"NESM\x1a") {
+            Self::from_nsf(bytes)?
+        } else if bytes.starts_with(b"NSFE") {
+            Self::from_nsfe(bytes)?
+        } else {
+            return Err(NesParseError::InvalidNsf(
+                "missing NESM or NSFE magic number",
+            ));
+        };
+
+        // Only FDS files have RAM below $8000 to load into
+        if nsf.load_address < nsf.base_address() {
+            return Err(NesParseError::InvalidNsf(if nsf.is_fds() {
+                "load address is below $6000"
+            } else {
+                "load address is below $8000"
+            }));
+        }
+        Ok(nsf)
+    }
+
+    fn from_nsf(bytes: &[u8]) -> Result<Self, NesParseError> {
+        let Some((header, data)) = bytes.split_at_checked(NSF_HEADER_SIZE) else {
+            return Err(NesParseError::InvalidNsf("header is too short"));
+        };
+        let u16_at = |i: usize| u16::from_le_bytes([header[i], header[i + 1]]);
+        let bank_init: [u8; 8] = header[0x70..0x78].try_into().unwrap();
+
+        Ok(Self {
+            title: read_string(&header[0x0e..0x2e]),
+            artist: read_string(&header[0x2e..0x4e]),
+            copyright: read_string(&header[0x4e..0x6e]),
+            track_count: header[0x06],
+            starting_track: header[0x07].saturating_sub(1),
+            load_address: u16_at(0x08),
+            init_address: u16_at(0x0a),
+            play_address: u16_at(0x0c),
+            ntsc_play_period: u16_at(0x6e),
+            pal_play_period: u16_at(0x78),
+            bank_init: bank_init.iter().any(|bank| *bank != 0).then_some(bank_init),
+            pal_only: header[0x7a] & 0b11 == 0b01,
+            chips: NsfChips::from_bits_truncate(header[0x7b]),
+            track_labels: Vec::new(),
+            track_lengths: Vec::new(),
+            data: data.to_vec(),
+        }
+        .with_default_periods())
+    }
+
+    fn from_nsfe(bytes: &[u8]) -> Result<Self, NesParseError> {
+        let mut nsf = Self {
+            ntsc_play_period: DEFAULT_NTSC_PLAY_PERIOD,
+            pal_play_period: DEFAULT_PAL_PLAY_PERIOD,
+            ..Default::default()
+        };
+        let mut has_info = false;
+        let mut rest = &bytes[4..];
+        loop {
+            let Some((chunk_header, after_header)) = rest.split_at_checked(8) else {
+                return Err(NesParseError::InvalidNsf("missing NEND chunk"));
+            };
+            let length = u32::from_le_bytes(chunk_header[0..4].try_into().unwrap()) as usize;
+            let id = &chunk_header[4..8];
+            let Some((chunk, after_chunk)) = after_header.split_at_checked(length) else {
+                return Err(NesParseError::InvalidNsf("chunk is longer than the file"));
+            };
+            rest = after_chunk;
+
+            match id {
+                b"INFO" => {
+                    if chunk.len() < 9 {
+                        return Err(NesParseError::InvalidNsf("INFO chunk is too short"));
+                    }
+                    let u16_at = |i: usize| u16::from_le_bytes([chunk[i], chunk[i + 1]]);
+                    nsf.load_address = u16_at(0);
+                    nsf.init_address = u16_at(2);
+                    nsf.play_address = u16_at(4);
+                    nsf.pal_only = chunk[6] & 0b11 == 0b01;
+                    nsf.chips = NsfChips::from_bits_truncate(chunk[7]);
+                    nsf.track_count = chunk[8];
+                    nsf.starting_track = chunk.get(9).copied().unwrap_or(0);
+                    has_info = true;
+                }
+                b"DATA" => nsf.data = chunk.to_vec(),
+                b"BANK" => {
+                    let mut bank_init = [0; 8];
+                    let len = chunk.len().min(8);
+                    bank_init[..len].copy_from_slice(&chunk[..len]);
+                    nsf.bank_init = Some(bank_init);
+                }
+                b"RATE" => {
+                    if let Some(period) = chunk.get(0..2) {
+                        nsf.ntsc_play_period = u16::from_le_bytes([period[0], period[1]]);
+                    }
+                    if let Some(period) = chunk.get(2..4) {
+                        nsf.pal_play_period = u16::from_le_bytes([period[0], period[1]]);
+                    }
+                }
+                b"auth" => {
+                    let mut strings = chunk.split(|byte| *byte == 0).map(read_string);
+                    nsf.title = strings.next().unwrap_or_default();
+                    nsf.artist = strings.next().unwrap_or_default();
+                    nsf.copyright = strings.next().unwrap_or_default();
+                }
+                b"tlbl" => {
+                    nsf.track_labels = chunk
+                        .split(|byte| *byte == 0)
+                        .map(read_string)
+                        .take(nsf.track_count.max(1) as usize)
+                        .collect();
+                }
+                b"time" => {
+                    // Negative lengths mean the length isn't known
+                    nsf.track_lengths = chunk
+                        .chunks_exact(4)
+                        .map(|length| {
+                            let length = i32::from_le_bytes(length.try_into().unwrap());
+                            u32::try_from(length).ok()
+                        })
+                        .collect();
+                }
+                b"NEND" => break,
+                // Chunks starting with an uppercase letter have to be understood to play the file
+                [first, ..] if first.is_ascii_uppercase() => {
+                    return Err(NesParseError::InvalidNsf("unsupported required chunk"));
+                }
+                _ => (),
+            }
+        }
+
+        if !has_info || nsf.data.is_empty() {
+            return Err(NesParseError::InvalidNsf("missing INFO or DATA chunk"));
+        }
+        Ok(nsf.with_default_periods())
+    }
+
+    fn with_default_periods(mut self) -> Self {
+        if self.ntsc_play_period == 0 {
+            self.ntsc_play_period = DEFAULT_NTSC_PLAY_PERIOD;
+        }
+        if self.pal_play_period == 0 {
+            self.pal_play_period = DEFAULT_PAL_PLAY_PERIOD;
+        }
+        self
+    }
+
+    /// Name of the track from the NSFe track labels
+    pub fn track_label(&self, track: u8) -> Option<&str> {
+        self.track_labels
+            .get(track as usize)
+            .map(String::as_str)
+            .filter(|label| !label.is_empty())
+    }
+
+    /// Region the tunes are made for, files that support both play as NTSC
+    pub fn region(&self) -> Region {
+        if self.pal_only {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        }
+    }
+
+    /// Length of the track in milliseconds if the file has it
+    pub fn track_length(&self, track: u8) -> Option<u32> {
+        self.track_lengths.get(track as usize).copied().flatten()
+    }
+
+    /// FDS files run from RAM at $6000-$dfff instead of ROM
+    pub fn is_fds(&self) -> bool {
+        self.chips.contains(NsfChips::FDS)
+    }
+
+    /// Lowest address the data can be loaded to
+    fn base_address(&self) -> u16 {
+        if self.is_fds() { 0x6000 } else { 0x8000 }
+    }
+
+    /// Lays out the data in 4kb banks so bank n of the data is bank n of the ROM
+    /// Without bank switching the data is placed at the load address with bank 0 at $8000,
+    /// or at $6000 for FDS files
+    pub fn prg_rom(&self) -> Vec<u8> {
+        let padding = if self.bank_init.is_some() {
+            self.load_address & 0x0fff
+        } else {
+            self.load_address - self.base_address()
+        };
+        let banks_size = 0x10000 - self.base_address() as usize;
+        let mut prg_rom = vec![0; padding as usize];
+        prg_rom.extend_from_slice(&self.data);
+        prg_rom.resize(prg_rom.len().next_multiple_of(0x1000).max(banks_size), 0);
+        prg_rom
+    }
+}
+
+/// Reads a null terminated string, ignoring anything after the null
+fn read_string(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn create_test_nsf() -> Vec<u8> {
+        let mut bytes = b"NESM\x1a\x01\x03\x02".to_vec();
+        bytes.extend_from_slice(&[0x00, 0x80, 0x03, 0x80, 0x06, 0x80]);
+        bytes.resize(NSF_HEADER_SIZE, 0);
+        bytes[0x0e..0x12].copy_from_slice(b"Song");
+        bytes[0x2e..0x34].copy_from_slice(b"Artist");
+        bytes[0x6e..0x70].copy_from_slice(&16666u16.to_le_bytes());
+        bytes[0x7b] = 0b0000_0001;
+        bytes.extend_from_slice(&[0x60, 0x00, 0x00, 0x60]);
+        bytes
+    }
+
+    #[test]
+    fn nsf() {
+        let nsf = Nsf::from_bytes(&create_test_nsf()).unwrap();
+        assert_eq!(nsf.title, "Song");
+        assert_eq!(nsf.artist, "Artist");
+        assert_eq!(nsf.track_count, 3);
+        assert_eq!(nsf.starting_track, 1);
+        assert_eq!(nsf.init_address, 0x8003);
+        assert_eq!(nsf.ntsc_play_period, 16666);
+        assert_eq!(nsf.pal_play_period, DEFAULT_PAL_PLAY_PERIOD);
+        assert_eq!(nsf.bank_init, None);
+        assert_eq!(nsf.chips, NsfChips::VRC6);
+
+        let prg_rom = nsf.prg_rom();
+        assert_eq!(prg_rom.len(), 0x8000);
+        assert_eq!(prg_rom[0..4], [0x60, 0x00, 0x00, 0x60]);
+
+        assert!(Nsf::from_bytes(&create_test_nsf()[..0x40]).is_err());
+    }
+
+    #[test]
+    fn bank_switched_prg_rom() {
+        let mut bytes = create_test_nsf();
+        bytes[0x08..0x0a].copy_from_slice(&0x8100u16.to_le_bytes());
+        bytes[0x71] = 1;
+        bytes.resize(bytes.len() + 0x1000, 0xea);
+        let nsf = Nsf::from_bytes(&bytes).unwrap();
+        assert_eq!(nsf.bank_init, Some([0, 1, 0, 0, 0, 0, 0, 0]));
+
+        let prg_rom = nsf.prg_rom();
+        assert_eq!(prg_rom.len(), 0x8000);
+        assert_eq!(prg_rom[0x100], 0x60);
+        assert_eq!(prg_rom[0x1103], 0xea);
+    }
+
+    #[test]
+    fn low_load_address() {
+        let mut bytes = create_test_nsf();
+        bytes[0x08..0x0a].copy_from_slice(&0x7000u16.to_le_bytes());
+        assert!(matches!(
+            Nsf::from_bytes(&bytes),
+            Err(NesParseError::InvalidNsf(_))
+        ));
+
+        // FDS files are loaded into RAM starting at $6000
+        bytes[0x7b] = NsfChips::FDS.bits();
+        let nsf = Nsf::from_bytes(&bytes).unwrap();
+        let prg_rom = nsf.prg_rom();
+        assert_eq!(prg_rom.len(), 0xa000);
+        assert_eq!(prg_rom[0x1000..0x1004], [0x60, 0x00, 0x00, 0x60]);
+    }
+
+    #[test]
+    fn nsfe() {
+        let chunk = |id: &[u8], data: &[u8]| {
+            let mut chunk = (data.len() as u32).to_le_bytes().to_vec();
+            chunk.extend_from_slice(id);
+            chunk.extend_from_slice(data);
+            chunk
+        };
+        let mut bytes = b"NSFE".to_vec();
+        bytes.extend(chunk(
+            b"INFO",
+            &[0x00, 0x80, 0x03, 0x80, 0x06, 0x80, 0, 0b0010_0000, 2],
+        ));
+        bytes.extend(chunk(b"DATA", &[0x60, 0x00, 0x00, 0x60]));
+        bytes.extend(chunk(b"auth", b"Song\0Artist\0\0Ripper\0"));
+        bytes.extend(chunk(b"tlbl", b"Intro\0Boss\0"));
+        bytes.extend(chunk(b"time", &[0x10, 0x27, 0, 0, 0xff, 0xff, 0xff, 0xff]));
+        bytes.extend(chunk(b"text", b"ignored"));
+        bytes.extend(chunk(b"NEND", &[]));
+
+        let nsf = Nsf::from_bytes(&bytes).unwrap();
+        assert_eq!(nsf.title, "Song");
+        assert_eq!(nsf.artist, "Artist");
+        assert_eq!(nsf.track_count, 2);
+        assert_eq!(nsf.starting_track, 0);
+        assert_eq!(nsf.chips, NsfChips::SUNSOFT5B);
+        assert_eq!(nsf.track_label(1), Some("Boss"));
+        assert_eq!(nsf.track_length(0), Some(10000));
+        assert_eq!(nsf.track_length(1), None);
+        assert_eq!(nsf.ntsc_play_period, DEFAULT_NTSC_PLAY_PERIOD);
+
+        // Unknown required chunk
+        let mut bytes = bytes[..bytes.len() - 8].to_vec();
+        bytes.extend(chunk(b"ABCD", &[]));
+        assert!(Nsf::from_bytes(&bytes).is_err());
+    }
+}
@@ -30,7 +30,7 @@ impl Button {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct Controller {
     strobe_active: bool,
     shift_register: u8,
@@ -46,6 +46,15 @@ impl Controller {
         }
     }
 
+    /// Bit the next read returns without shifting
+    pub fn peek(&self) -> u8 {
+        if self.strobe_active {
+            self.state.contains(Button::A) as u8
+        } else {
+            self.shift_register & 0b1
+        }
+    }
+
     pub fn read(&mut self) -> u8 {
         if self.strobe_active {
             // Always return A when strobe active
@@ -70,6 +79,16 @@ impl Controller {
         }
     }
 
+    /// Bit flag of all buttons held down
+    pub fn state(&self) -> Button {
+        self.state
+    }
+
+    /// Set all the buttons held down at once without any illegal press checks
+    pub fn set_state(&mut self, state: Button) {
+        self.state = state;
+    }
+
     fn check_pressed_combo(&self, button: Button, a: Button, b: Button) -> bool {
         (button == a && self.state.contains(b)) || (button == b && self.state.contains(a))
     }
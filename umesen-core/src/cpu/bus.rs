@@ -1,28 +1,49 @@
 use crate::{
     Apu, Controller, Ppu,
+    breakpoint::{BreakpointKind, Breakpoints},
     cartridge::{Cartridge, FixedArray},
-    ppu::PpuClockReport,
+    region::Region,
 };
 
-#[derive(Default)]
+#[derive(Default, serde::Serialize, serde::Deserialize)]
 pub struct CpuBus {
     // 2kb of cpu ram
     pub ram: FixedArray<u8, 0x800>,
     pub apu: Apu,
     /// Number of cycles added when executing the previous instruction
     pub(crate) cpu_cycles_since_inst: u32,
+    /// Cycles DMA took from the CPU while executing the previous instruction
+    #[serde(skip)]
+    pub(crate) dma_cycles_since_inst: u32,
+    /// The CPU is halted for DMA so the cycles aren't part of the instruction
+    #[serde(skip)]
+    in_dma: bool,
     pub cpu_cycles_total: u64,
     pub ppu: Ppu,
     open_bus: u8,
+    /// Address read on the previous cycle, None if it was a write or the CPU didn't use the bus
+    previous_read: Option<u16>,
     pub controllers: [Controller; 2],
-    require_nmi: bool,
+    /// Level of the NMI line on the previous cycle for detecting the rising edge
+    nmi_line: bool,
+    /// Rising edge of NMI was detected and hasn't been serviced yet
+    nmi_pending: bool,
+    /// Interrupts at the end of the last 3 cycles, newest first
+    interrupt_polls: [InterruptPoll; 3],
+}
+
+/// Interrupts the CPU sees when polling at the end of a cycle
+#[derive(Default, Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub(crate) struct InterruptPoll {
+    pub nmi: bool,
+    pub irq: bool,
 }
 
 impl CpuBus {
     /// Immutable read function for peeking into memory
     /// Reads into some address cause side effects
     pub fn peek_read(&self, address: u16) -> u8 {
-        if let Some(value) = self.cartridge().and_then(|c| c.cpu_read(address)) {
+        if let Some(value) = self.cartridge().and_then(|c| c.cpu_peek_read(address)) {
             return value;
         }
 
@@ -35,22 +56,53 @@ impl CpuBus {
     }
 
     pub fn read(&mut self, address: u16) -> u8 {
+        if let Some(dmc_address) = self.apu.channels.dmc.require_dma_at {
+            self.dmc_dma(address, dmc_address);
+        }
+        let value = self.read_cycle(address);
+        if !self.breakpoints().is_empty() {
+            self.breakpoints_mut()
+                .check(BreakpointKind::Read, address, value);
+        }
+        value
+    }
+
+    /// Read without checking for DMA
+    fn read_cycle(&mut self, address: u16) -> u8 {
         // https://www.nesdev.org/wiki/CPU_memory_map
+        let repeated = self.previous_read == Some(address);
         self.clock();
+        self.previous_read = Some(address);
         let output = match address {
             0x2000..=0x3fff => self.ppu.registers.read(address),
             // Top 3 high controller bits always have open bus
-            0x4016 => self.controllers[0].read() | (0b1110_0000 & self.open_bus),
+            // Controllers are clocked when /OE is asserted so reads on consecutive cycles
+            // only shift once
+            0x4016 | 0x4017 => {
+                let controller = &mut self.controllers[address as usize - 0x4016];
+                let bit = if repeated {
+                    controller.peek()
+                } else {
+                    controller.read()
+                };
+                bit | (0b1110_0000 & self.open_bus)
+            }
             // APU does not contribute to open bus
             0x4015 => return self.apu.read_status() | (0b0010_0000 & self.open_bus),
-            0x4017 => self.controllers[1].read() | (0b1110_0000 & self.open_bus),
-            _ => self.peek_read(address),
+            _ => match self.cartridge_mut().and_then(|c| c.cpu_read(address)) {
+                Some(value) => value,
+                None => self.peek_read(address),
+            },
         };
         self.open_bus = output;
         output
     }
 
     pub fn write(&mut self, address: u16, value: u8) {
+        if !self.breakpoints().is_empty() {
+            self.breakpoints_mut()
+                .check(BreakpointKind::Write, address, value);
+        }
         if let Some(cartridge) = self.cartridge_mut() {
             cartridge.cpu_write(address, value);
         }
@@ -95,19 +147,45 @@ impl CpuBus {
 
     // Clock all devices on the cpu bus relative to a cpu cycle
     pub fn clock(&mut self) {
-        self.apu.clock(self.cpu_cycles_total);
-        for _ in 0..3 {
-            match self.ppu.clock() {
-                PpuClockReport::None => (),
-                PpuClockReport::Nmi => self.require_nmi = true,
-            }
+        self.previous_read = None;
+        if let Some(cartridge) = self.ppu.registers.bus.cartridge.as_mut() {
+            cartridge.clock();
+        }
+        let expansion_audio = self.ppu.registers.bus.cartridge.as_ref();
+        self.apu.clock(self.cpu_cycles_total, || {
+            expansion_audio.map_or_else(Default::default, |c| c.audio_samples())
+        });
+        for _ in 0..self.region().ppu_dots(self.cpu_cycles_total) {
+            self.ppu.clock();
         }
         self.cpu_cycles_since_inst += 1;
         self.cpu_cycles_total += 1;
-        if let Some(address) = self.apu.channels.dmc.require_dma_at {
-            // TODO: stall cycles and some register conflict stuff
-            self.apu.channels.dmc.on_dma_read(self.peek_read(address));
+
+        let nmi_line = self.ppu.nmi_line();
+        if nmi_line && !self.nmi_line {
+            self.nmi_pending = true;
+        }
+        self.nmi_line = nmi_line;
+        // The CPU doesn't poll interrupts while halted, so the polls stay the ones of the
+        // instruction's own cycles
+        if self.in_dma {
+            self.dma_cycles_since_inst += 1;
+            return;
         }
+        self.interrupt_polls.rotate_right(1);
+        self.interrupt_polls[0] = InterruptPoll {
+            nmi: self.nmi_pending,
+            irq: self.irq_status(),
+        };
+    }
+
+    pub fn region(&self) -> Region {
+        self.ppu.registers.region
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.ppu.registers.region = region;
+        self.apu.region = region;
     }
 
     pub fn attach_catridge(&mut self, catridge: Cartridge) {
@@ -118,10 +196,18 @@ impl CpuBus {
         self.apu.irq_status() | self.cartridge().map(|c| c.irq_status()).unwrap_or(false)
     }
 
-    pub fn require_nmi(&mut self) -> bool {
-        let status = self.require_nmi;
-        self.require_nmi = false;
-        status
+    /// Interrupts polled at the end of the cycle that was `cycles_ago` before the last one
+    pub(crate) fn polled_interrupts(&self, cycles_ago: usize) -> InterruptPoll {
+        self.interrupt_polls[cycles_ago]
+    }
+
+    /// Clears the detected NMI once it starts being serviced
+    /// Returns true if there was one
+    pub(crate) fn take_nmi(&mut self) -> bool {
+        for poll in &mut self.interrupt_polls {
+            poll.nmi = false;
+        }
+        std::mem::take(&mut self.nmi_pending)
     }
 
     pub fn cartridge_mut(&mut self) -> Option<&mut Cartridge> {
@@ -132,7 +218,30 @@ impl CpuBus {
         self.ppu.registers.bus.cartridge.as_ref()
     }
 
+    pub fn breakpoints(&self) -> &Breakpoints {
+        &self.ppu.registers.bus.breakpoints
+    }
+
+    pub fn breakpoints_mut(&mut self) -> &mut Breakpoints {
+        &mut self.ppu.registers.bus.breakpoints
+    }
+
+    /// Moves over everything that isn't part of a save state from another bus
+    pub(crate) fn take_unsaved_from(&mut self, other: &mut CpuBus) {
+        self.ppu.registers.bus.cartridge = other.ppu.registers.bus.cartridge.take();
+        self.ppu.registers.bus.breakpoints = std::mem::take(other.breakpoints_mut());
+        std::mem::swap(&mut self.ppu.palette, &mut other.ppu.palette);
+        std::mem::swap(&mut self.ppu.screen_pixels, &mut other.ppu.screen_pixels);
+        self.ppu.config = other.ppu.config.clone();
+        self.apu.buffer_prod = other.apu.buffer_prod.take();
+        self.apu.sample_rate = other.apu.sample_rate;
+        self.apu.config = other.apu.config.clone();
+    }
+
+    /// Reads happen on even cycles and writes on odd cycles
+    /// https://www.nesdev.org/wiki/DMA
     fn oam_dma(&mut self, address_start: u16) {
+        self.in_dma = true;
         // 1 (or 2 if odd) idle cycles
         self.clock();
         if self.cpu_cycles_total % 2 == 1 {
@@ -141,14 +250,39 @@ impl CpuBus {
 
         // 512 r/w cycles
         for i in 0..256 {
-            let value = self.read(address_start + i);
+            // The DMC takes over a read cycle and OAM DMA needs a cycle to realign
+            if let Some(dmc_address) = self.apu.channels.dmc.require_dma_at {
+                let value = self.read_cycle(dmc_address);
+                self.apu.channels.dmc.on_dma_read(value);
+                self.clock();
+            }
+            let value = self.read_cycle(address_start + i);
             self.clock();
             self.ppu.registers.write_oam_data(value);
         }
+        self.in_dma = false;
+    }
+
+    /// Halts the CPU to read a byte of the DMC sample, which steals 3 or 4 cycles
+    /// The CPU can only be halted on a read and keeps reading the same address while halted,
+    /// so registers with read side effects like $2007 and $4016 are read multiple times
+    /// https://www.nesdev.org/wiki/DMA
+    fn dmc_dma(&mut self, cpu_address: u16, dmc_address: u16) {
+        self.in_dma = true;
+        // Halt and dummy cycles
+        self.read_cycle(cpu_address);
+        self.read_cycle(cpu_address);
+        // Alignment cycle since the DMC can only read on even cycles
+        if self.cpu_cycles_total % 2 == 1 {
+            self.read_cycle(cpu_address);
+        }
+        let value = self.read_cycle(dmc_address);
+        self.apu.channels.dmc.on_dma_read(value);
+        self.in_dma = false;
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
 pub struct IrqStatus {
     pub status: bool,
     enabled: bool,
@@ -180,3 +314,70 @@ impl IrqStatus {
         status
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::controller::Button;
+
+    fn setup_bus() -> CpuBus {
+        let mut bus = CpuBus::default();
+        let cartridge =
+            Cartridge::from_mapper(0, vec![0; 0x2000], vec![0; 0x8000], vec![0; 0x2000]).unwrap();
+        bus.attach_catridge(cartridge);
+        bus
+    }
+
+    #[test]
+    fn dmc_dma_controller_conflict() {
+        let mut bus = setup_bus();
+        bus.controllers[0].set_state(Button::A);
+        bus.write(0x4016, 1);
+        bus.write(0x4016, 0);
+        bus.write(0x4010, 0);
+        bus.write(0x4013, 1);
+        bus.write(0x4015, 0x10);
+        // Wait for the DMC to request its first byte
+        bus.clock();
+        bus.clock();
+
+        let start = bus.cpu_cycles_total;
+        let bit = bus.read(0x4016) & 1;
+        assert!(matches!(bus.cpu_cycles_total - start, 4 | 5));
+        // The halted read already shifted out A
+        assert_eq!(bit, 0);
+
+        let start = bus.cpu_cycles_total;
+        bus.read(0x4016);
+        assert_eq!(bus.cpu_cycles_total - start, 1);
+    }
+
+    #[test]
+    fn dmc_dma_during_oam_dma() {
+        let mut bus = setup_bus();
+        bus.write(0x4010, 0);
+        bus.write(0x4013, 1);
+        bus.write(0x4015, 0x10);
+        bus.clock();
+        bus.clock();
+
+        let start = bus.cpu_cycles_total;
+        bus.write(0x4014, 0x02);
+        // 513 or 514 cycles and 2 for the DMC read, plus the write cycle
+        let cycles = bus.cpu_cycles_total - start;
+        assert!(matches!(cycles, 516 | 517), "{cycles}");
+    }
+
+    #[test]
+    fn no_interrupt_polls_during_dma() {
+        let mut bus = setup_bus();
+        bus.apu.channels.dmc.irq.status = true;
+        bus.clock();
+        bus.apu.channels.dmc.irq.status = false;
+        bus.write(0x4014, 0x02);
+        // The cycle before the write is still the second-to-last one
+        assert!(!bus.polled_interrupts(0).irq);
+        assert!(bus.polled_interrupts(1).irq);
+        assert!(matches!(bus.dma_cycles_since_inst, 513 | 514));
+    }
+}
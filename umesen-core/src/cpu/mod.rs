@@ -6,13 +6,11 @@ pub use bus::{CpuBus, IrqStatus};
 pub use disassembler::Disassembler;
 pub use opcode::{AddrMode, Inst, Opcode};
 
-/// Number of clock cycles per second
-pub const CLOCK_SPEED_HZ: f32 = 1789773.;
-pub const CYCLES_PER_FRAME: f32 = 29780.5;
+use crate::breakpoint::CpuRegisters;
 
 bitflags::bitflags! {
     /// Flags for the cpu register
-    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
     pub struct Flags: u8 {
         const CARRY = 1;
         const ZERO = 1 << 1;
@@ -47,7 +45,7 @@ const RESET_LOAD_VECTOR: u16 = 0xfffc;
 const IRQ_LOAD_VECTOR: u16 = 0xfffe;
 
 /// Emulated 6502 CPU
-#[derive(Default)]
+#[derive(Default, serde::Serialize, serde::Deserialize)]
 pub struct Cpu {
     /// Program counter
     pub pc: u16,
@@ -61,6 +59,9 @@ pub struct Cpu {
     pub y: u8,
     pub flags: Flags,
     pub bus: CpuBus,
+    /// Interrupts polled during the previous instruction that run before the next one
+    pending_nmi: bool,
+    pending_irq: bool,
 }
 
 impl Cpu {
@@ -68,20 +69,79 @@ impl Cpu {
     /// Returns the number of cpu cycles that the instruction took to execute
     pub fn execute_next(&mut self) -> Result<u32, CpuError> {
         self.bus.cpu_cycles_since_inst = 0;
+        self.bus.dma_cycles_since_inst = 0;
 
-        if self.bus.require_nmi() {
+        if self.pending_nmi {
             self.interrupt(NMI_LOAD_VECTOR);
-        } else if self.bus.irq_status() && !self.flags.contains(Flags::INTERRUPT) {
+        } else if self.pending_irq {
             self.interrupt(IRQ_LOAD_VECTOR);
         }
+        // Already serviced, and they are polled again after the next instruction
+        self.pending_nmi = false;
+        self.pending_irq = false;
+
+        if !self.bus.breakpoints().is_empty() {
+            let opcode = self.bus.peek_read(self.pc);
+            let registers = self.registers();
+            if self.bus.breakpoints_mut().check_execute(registers, opcode) {
+                return Ok(self.bus.cpu_cycles_since_inst);
+            }
+        }
 
+        let interrupt_disable = self.flags.contains(Flags::INTERRUPT);
+        let start_cycle = self.bus.cpu_cycles_total;
+        let start_dma_cycles = self.bus.dma_cycles_since_inst;
         let byte = self.read_at_pc();
         let opcode = Opcode::from_byte(byte);
         self.execute(opcode)?;
+        // Only the instruction's own cycles, without the ones DMA took
+        let dma_cycles = self.bus.dma_cycles_since_inst - start_dma_cycles;
+        self.poll_interrupts(
+            opcode,
+            interrupt_disable,
+            self.bus.cpu_cycles_total - start_cycle - dma_cycles as u64,
+        );
 
         Ok(self.bus.cpu_cycles_since_inst)
     }
 
+    /// Interrupts are polled on the second-to-last cycle of an instruction so the ones that
+    /// happen after are delayed by another instruction
+    /// https://www.nesdev.org/wiki/CPU_interrupts
+    fn poll_interrupts(&mut self, opcode: Opcode, interrupt_disable: bool, cycles: u64) {
+        // Interrupt sequences don't poll so the first instruction of the handler always runs
+        if opcode.instruction == Inst::Brk {
+            self.pending_nmi = false;
+            self.pending_irq = false;
+            return;
+        }
+        // A taken branch that doesn't cross a page polls a cycle earlier
+        let cycles_ago = if opcode.addr_mode == AddrMode::Relative && cycles == 3 {
+            2
+        } else {
+            1
+        };
+        // These change the I flag after polling so the old value is used
+        let interrupt_disable = match opcode.instruction {
+            Inst::Cli | Inst::Sei | Inst::Plp => interrupt_disable,
+            _ => self.flags.contains(Flags::INTERRUPT),
+        };
+        let poll = self.bus.polled_interrupts(cycles_ago);
+        self.pending_nmi = poll.nmi;
+        self.pending_irq = poll.irq && !interrupt_disable;
+    }
+
+    pub fn registers(&self) -> CpuRegisters {
+        CpuRegisters {
+            pc: self.pc,
+            sp: self.sp,
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            flags: self.flags.bits(),
+        }
+    }
+
     pub fn reset(&mut self) {
         self.a = 0;
         self.x = 0;
@@ -90,6 +150,8 @@ impl Cpu {
 
         self.bus.cpu_cycles_total = 0;
         self.bus.cpu_cycles_since_inst = 0;
+        self.pending_nmi = false;
+        self.pending_irq = false;
         self.pc = self.bus.read_u16(RESET_LOAD_VECTOR);
         self.sp = 0xfd;
         // Some roms freeze when soft loading if nmi is enabled for some reason
@@ -393,7 +455,7 @@ impl Cpu {
         self.stack_push(self.flags.bits());
         // If there is a nmi when we're about the load the load vector then the nmi hijacks it
         // No later than cycle 4
-        if self.bus.require_nmi() {
+        if self.bus.take_nmi() {
             load_vector = NMI_LOAD_VECTOR;
         }
         self.bus.clock();
@@ -793,3 +855,58 @@ impl Cpu {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Cartridge;
+
+    /// Cpu with the program at $8000 and the IRQ handler at $9000
+    fn setup_cpu(program: &[u8]) -> Cpu {
+        let mut prg_rom = vec![0xea; 0x8000];
+        prg_rom[..program.len()].copy_from_slice(program);
+        prg_rom[0x7ffc..].copy_from_slice(&[0x00, 0x80, 0x00, 0x90]);
+        let cartridge =
+            Cartridge::from_mapper(0, vec![0; 0x2000], prg_rom, vec![0; 0x2000]).unwrap();
+        let mut cpu = Cpu::default();
+        cpu.bus.attach_catridge(cartridge);
+        cpu.reset();
+        cpu
+    }
+
+    #[test]
+    fn cli_sei_latency() {
+        // CLI, SEI
+        let mut cpu = setup_cpu(&[0x58, 0x78]);
+        cpu.bus.apu.channels.dmc.irq.status = true;
+        cpu.execute_next().unwrap();
+        assert_eq!(cpu.pc, 0x8001);
+        // SEI polls before setting I so the IRQ still happens after it
+        cpu.execute_next().unwrap();
+        assert_eq!(cpu.pc, 0x8002);
+        cpu.execute_next().unwrap();
+        // First instruction of the handler always runs
+        assert_eq!(cpu.pc, 0x9001);
+        let pushed_flags = Flags::from_bits_truncate(cpu.bus.peek_read(0x100 + cpu.sp as u16 + 1));
+        assert!(pushed_flags.contains(Flags::INTERRUPT));
+    }
+
+    #[test]
+    fn branch_cycles_without_dma() {
+        // BNE to the next instruction, taken without crossing a page
+        let mut cpu = setup_cpu(&[0xd0, 0x00]);
+        cpu.bus.write(0x4010, 0);
+        cpu.bus.write(0x4013, 1);
+        cpu.bus.write(0x4015, 0x10);
+        cpu.bus.clock();
+        cpu.bus.clock();
+        cpu.bus.cpu_cycles_since_inst = 0;
+        cpu.execute_next().unwrap();
+        // The DMC stall doesn't count towards the 3 cycles that delay interrupts
+        assert!(cpu.bus.dma_cycles_since_inst > 0);
+        assert_eq!(
+            cpu.bus.cpu_cycles_since_inst - cpu.bus.dma_cycles_since_inst,
+            3
+        );
+    }
+}
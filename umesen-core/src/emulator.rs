@@ -2,16 +2,63 @@ use ringbuf::traits::Split;
 
 use crate::{
     Apu, Cartridge, Controller, Cpu, Ppu,
-    cartridge::NesParseError,
-    cpu::{CLOCK_SPEED_HZ, CYCLES_PER_FRAME, CpuError},
-    ppu::ScreenPixels,
+    breakpoint::{BreakReason, Breakpoints},
+    cartridge::{CartridgeState, NesParseError},
+    cpu::CpuError,
+    movie::{ActiveMovie, Movie, MovieCommand, MovieFrame, MovieMode},
+    power_on::PowerOnConfig,
+    ppu::{HEIGHT, ScreenPixels, WIDTH},
+    region::Region,
+    rewind::Rewind,
 };
 
+/// Size of the frame stored after the save state in rewind snapshots
+const SNAPSHOT_SCREEN_SIZE: usize = WIDTH * HEIGHT * 3;
+
+/// Increment this whenever the layout of any saved struct changes
+const SAVE_STATE_VERSION: u32 = 8;
+
+#[derive(thiserror::Error, Debug)]
+pub enum SaveStateError {
+    #[error("Save state version {0} is not compatible with version {SAVE_STATE_VERSION}")]
+    IncompatibleVersion(u32),
+    #[error("Save state was made with a different cartridge")]
+    CartridgeMismatch,
+    #[error(transparent)]
+    InvalidData(#[from] bincode::Error),
+}
+
+#[derive(serde::Serialize)]
+struct SaveStateRef<'a> {
+    version: u32,
+    cpu: &'a Cpu,
+    cartridge: Option<CartridgeState>,
+    /// Frame of the movie that was active when the state was saved
+    movie_frame: Option<usize>,
+}
+
+#[derive(serde::Deserialize)]
+struct SaveState {
+    // Already checked before deserializing the whole state
+    _version: u32,
+    cpu: Cpu,
+    cartridge: Option<CartridgeState>,
+    movie_frame: Option<usize>,
+}
+
 /// High level struct for controlling the cpu
 pub struct Emulator {
     pub cpu: Cpu,
     pub speed: f32,
     pub running: bool,
+    pub rewind: Rewind,
+    /// Used when loading a ROM and on hard resets
+    pub power_on: PowerOnConfig,
+    movie: Option<ActiveMovie>,
+    /// Region used instead of the one from the cartridge header
+    region_override: Option<Region>,
+    /// Part of a frame has been emulated without it completing
+    mid_frame: bool,
     clocks_remaining: f32,
     last_update_time: std::time::Instant,
     last_frame_time: std::time::Instant,
@@ -24,6 +71,11 @@ impl Default for Emulator {
         Self {
             last_update_time: std::time::Instant::now(),
             running: true,
+            rewind: Rewind::default(),
+            power_on: PowerOnConfig::default(),
+            movie: None,
+            region_override: None,
+            mid_frame: false,
             cpu: Cpu::default(),
             last_frame_time: std::time::Instant::now(),
             audio_sample_rate: 0.,
@@ -35,38 +87,68 @@ impl Default for Emulator {
 }
 
 impl Emulator {
-    /// Keep stepping until a frame is generated
-    pub fn next_frame(&mut self) -> Result<(), CpuError> {
+    /// Keep stepping until a frame is generated or a breakpoint is hit
+    pub fn next_frame(&mut self) -> Result<Option<BreakReason>, CpuError> {
+        self.apply_movie_input();
+        self.skip_breakpoint_at_pc();
         while !self.ppu().frame_complete() {
             self.cpu.execute_next()?;
+            self.mid_frame = true;
+            if let Some(reason) = self.breakpoints_mut().take_hit() {
+                return Ok(Some(reason));
+            }
         }
+        self.mid_frame = false;
+        self.on_frame_completed();
+        Ok(None)
+    }
+
+    /// Execute a single instruction, ignoring any breakpoint it hits
+    pub fn step(&mut self) -> Result<(), CpuError> {
+        self.skip_breakpoint_at_pc();
+        self.cpu.execute_next()?;
+        self.mid_frame = true;
+        self.breakpoints_mut().take_hit();
         Ok(())
     }
 
     /// Calculates the delta time that has passed since calling this function and clock the cpu
     /// required for that amount of time
+    /// Emulation is paused when a breakpoint is hit, which returns why
     pub fn update(
         &mut self,
         mut on_frame_completed: impl FnMut(&ScreenPixels),
-    ) -> Result<(), CpuError> {
+    ) -> Result<Option<BreakReason>, CpuError> {
         let delta = self.last_update_time.elapsed().as_secs_f32().min(0.05) * self.speed;
         self.apu().sample_rate = self.audio_sample_rate / self.speed;
         self.last_update_time = std::time::Instant::now();
         if !self.running {
             self.clocks_remaining = 0.;
-            return Ok(());
+            return Ok(None);
         }
 
-        self.clocks_remaining += delta * CLOCK_SPEED_HZ;
-        while self.clocks_remaining > 0. {
+        self.apply_movie_input();
+        self.clocks_remaining += delta * self.region().cpu_clock_hz();
+        // Movies only allow input to change between frames so always finish the frame
+        while self.clocks_remaining > 0. || (self.mid_frame && self.movie.is_some()) {
             self.clocks_remaining -= self.cpu.execute_next()? as f32;
-            if self.ppu().frame_complete() && self.clocks_remaining < CYCLES_PER_FRAME {
-                self.frame_rate = 1. / self.last_frame_time.elapsed().as_secs_f32();
-                self.last_frame_time = std::time::Instant::now();
-                on_frame_completed(&self.ppu().screen_pixels);
+            self.mid_frame = true;
+            if let Some(reason) = self.breakpoints_mut().take_hit() {
+                self.running = false;
+                self.clocks_remaining = 0.;
+                return Ok(Some(reason));
+            }
+            if self.ppu().frame_complete() {
+                self.mid_frame = false;
+                self.on_frame_completed();
+                if self.clocks_remaining < self.region().cycles_per_frame() {
+                    self.frame_rate = 1. / self.last_frame_time.elapsed().as_secs_f32();
+                    self.last_frame_time = std::time::Instant::now();
+                    on_frame_completed(&self.ppu().screen_pixels);
+                }
             }
         }
-        Ok(())
+        Ok(None)
     }
 
     /// Setup the audio buffer
@@ -93,12 +175,312 @@ impl Emulator {
     }
 
     pub fn load_nes_rom(&mut self, bytes: impl std::io::Read) -> Result<(), NesParseError> {
-        self.cpu.bus.attach_catridge(Cartridge::from_nes(bytes)?);
+        self.insert_cartridge(Cartridge::from_nes(bytes)?);
+        Ok(())
+    }
+
+    /// Load a Famicom Disk System image, which needs the FDS BIOS ROM to run
+    pub fn load_fds_file(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+        bios_path: impl AsRef<std::path::Path>,
+    ) -> Result<(), NesParseError> {
+        self.load_fds_rom(std::fs::File::open(bios_path)?, std::fs::File::open(path)?)
+    }
+
+    pub fn load_fds_rom(
+        &mut self,
+        bios: impl std::io::Read,
+        image: impl std::io::Read,
+    ) -> Result<(), NesParseError> {
+        self.insert_cartridge(Cartridge::from_fds(bios, image)?);
+        Ok(())
+    }
+
+    /// Load an NSF or NSFe file, which plays its starting track
+    pub fn load_nsf_file(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), NesParseError> {
+        self.load_nsf_rom(std::fs::File::open(path)?)
+    }
+
+    pub fn load_nsf_rom(&mut self, bytes: impl std::io::Read) -> Result<(), NesParseError> {
+        self.insert_cartridge(Cartridge::from_nsf(bytes)?);
+        Ok(())
+    }
+
+    /// Start playing a track of the loaded NSF from the beginning
+    pub fn play_nsf_track(&mut self, track: u8) {
+        if let Some(cartridge) = self.cartridge_mut() {
+            cartridge.select_nsf_track(track);
+        }
         self.cpu.reset();
+        self.rewind.clear();
+    }
+
+    /// Seconds since the current NSF track started
+    pub fn nsf_position(&self) -> f32 {
+        self.cpu.bus.cpu_cycles_total as f32 / self.region().cpu_clock_hz()
+    }
+
+    /// Jump to a position in the current NSF track by emulating up to it without any audio,
+    /// going back restarts the track
+    pub fn seek_nsf(&mut self, seconds: f32) -> Result<(), CpuError> {
+        let Some(track) = self.cartridge().and_then(|c| c.nsf_track()) else {
+            return Ok(());
+        };
+        if seconds < self.nsf_position() {
+            self.play_nsf_track(track);
+        }
+
+        let target_cycles = (seconds * self.region().cpu_clock_hz()) as u64;
+        let buffer_prod = self.apu().buffer_prod.take();
+        let mut result = Ok(());
+        while result.is_ok() && self.cpu.bus.cpu_cycles_total < target_cycles {
+            result = self.cpu.execute_next().map(|_| ());
+        }
+        self.apu().buffer_prod = buffer_prod;
+        // Seeking runs through the track too fast to stop at anything
+        self.breakpoints_mut().take_hit();
         self.last_update_time = std::time::Instant::now();
+        result
+    }
+
+    fn insert_cartridge(&mut self, cartridge: Cartridge) {
+        let region = self.region_override.unwrap_or(cartridge.header().region);
+        self.cpu.bus.set_region(region);
+        self.cpu.bus.attach_catridge(cartridge);
+        self.power_cycle();
+        self.rewind.clear();
+        self.movie = None;
+        self.mid_frame = false;
+        self.last_update_time = std::time::Instant::now();
+    }
+
+    pub fn region(&self) -> Region {
+        self.cpu.bus.region()
+    }
+
+    /// Force a region instead of the one from the cartridge header, None goes back to the header
+    pub fn set_region_override(&mut self, region_override: Option<Region>) {
+        if self.region_override == region_override {
+            return;
+        }
+        self.region_override = region_override;
+        let region = region_override
+            .or(self.cartridge().map(|c| c.header().region))
+            .unwrap_or_default();
+        self.cpu.bus.set_region(region);
+    }
+
+    /// Go back to the previous rewind snapshot
+    /// Returns false if there was nothing to rewind to
+    pub fn rewind_step(&mut self) -> bool {
+        let Some(snapshot) = self.rewind.pop() else {
+            return false;
+        };
+        // Snapshots are always made from this emulator and cartridge
+        let snapshot = snapshot.to_vec();
+        let (state, screen) = snapshot.split_at(snapshot.len() - SNAPSHOT_SCREEN_SIZE);
+        if let Err(err) = self.load_state(state) {
+            log::error!("Failed to load rewind snapshot: {err}");
+            self.rewind.clear();
+            return false;
+        }
+        for (pixel, rgb) in self
+            .ppu()
+            .screen_pixels
+            .iter_mut()
+            .zip(screen.chunks_exact(3))
+        {
+            pixel.copy_from_slice(rgb);
+        }
+        self.last_update_time = std::time::Instant::now();
+        true
+    }
+
+    /// Reset the cpu, which is recorded if a movie is being recorded
+    pub fn soft_reset(&mut self) {
+        self.cpu.reset();
+        if let Some(active) = self.movie.as_mut() {
+            active.pending_commands |= MovieCommand::SOFT_RESET;
+        }
+    }
+
+    /// Turn the console off and on, which is recorded if a movie is being recorded
+    pub fn hard_reset(&mut self) {
+        self.power_cycle();
+        if let Some(active) = self.movie.as_mut() {
+            active.pending_commands |= MovieCommand::HARD_RESET;
+        }
+    }
+
+    /// Replaces the whole console state with the power on state, keeping the cartridge
+    fn power_cycle(&mut self) {
+        let mut cpu = Cpu::default();
+        cpu.bus.set_region(self.region());
+        cpu.bus.take_unsaved_from(&mut self.cpu.bus);
+        self.power_on.fill(&mut cpu.bus.ram[..], 0);
+        self.power_on
+            .fill(&mut cpu.bus.ppu.registers.bus.nametable_ram[..], 1);
+        self.power_on
+            .fill(&mut cpu.bus.ppu.registers.oam_data[..], 2);
+        if let Some(cartridge) = cpu.bus.cartridge_mut() {
+            cartridge.power_on(&self.power_on);
+        }
+        self.cpu = cpu;
+        self.cpu.reset();
+        self.mid_frame = false;
+    }
+
+    /// Start recording controller input into a movie
+    /// If from_save_state is false the movie is played back from power on, so this should be
+    /// called right after loading the ROM
+    pub fn record_movie(&mut self, from_save_state: bool) {
+        self.movie = None;
+        let movie = Movie {
+            start_state: from_save_state.then(|| self.save_state()),
+            ..Default::default()
+        };
+        self.movie = Some(ActiveMovie::new(movie, MovieMode::Recording));
+    }
+
+    /// Start playing back a movie, loading its save state if it has one
+    /// If the movie starts from power on the ROM should have just been loaded
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), SaveStateError> {
+        self.movie = None;
+        if let Some(state) = &movie.start_state {
+            self.load_state(state)?;
+        }
+        self.movie = Some(ActiveMovie::new(movie, MovieMode::Playing));
+        self.mid_frame = false;
+        self.apply_movie_commands();
+        self.apply_movie_input();
+        Ok(())
+    }
+
+    /// Stop recording or playing back the movie and return it
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        self.movie.take().map(|active| active.movie)
+    }
+
+    pub fn movie(&self) -> Option<&ActiveMovie> {
+        self.movie.as_ref()
+    }
+
+    fn on_frame_completed(&mut self) {
+        if self.rewind.should_snapshot() {
+            // The screen isn't part of save states so it's stored after it to show when rewinding
+            let mut snapshot = self.save_state();
+            snapshot.extend(self.ppu().screen_pixels.iter().flat_map(|pixel| **pixel));
+            self.rewind.push(snapshot);
+        }
+
+        let buttons = [0, 1].map(|i| self.cpu.bus.controllers[i].state());
+        if let Some(active) = self.movie.as_mut() {
+            if active.mode == MovieMode::Recording {
+                let commands = std::mem::take(&mut active.pending_commands);
+                active.movie.frames.push(MovieFrame { commands, buttons });
+            }
+            active.frame += 1;
+
+            if active.mode == MovieMode::Playing && active.current_frame().is_none() {
+                log::info!("Movie playback finished");
+                self.movie = None;
+            } else {
+                self.apply_movie_commands();
+                self.apply_movie_input();
+            }
+        }
+    }
+
+    /// Run the commands of the movie frame that is about to start
+    fn apply_movie_commands(&mut self) {
+        let Some(frame) = self.playing_movie_frame() else {
+            return;
+        };
+        if frame.commands.contains(MovieCommand::HARD_RESET) {
+            self.power_cycle();
+        } else if frame.commands.contains(MovieCommand::SOFT_RESET) {
+            self.cpu.reset();
+        }
+    }
+
+    /// Overwrite the controller states with the current frame of the movie being played
+    fn apply_movie_input(&mut self) {
+        if let Some(frame) = self.playing_movie_frame() {
+            for (controller, buttons) in self.cpu.bus.controllers.iter_mut().zip(frame.buttons) {
+                controller.set_state(buttons);
+            }
+        }
+    }
+
+    fn playing_movie_frame(&self) -> Option<MovieFrame> {
+        self.movie
+            .as_ref()
+            .filter(|active| active.mode == MovieMode::Playing)
+            .and_then(|active| active.current_frame().copied())
+    }
+
+    /// Serializes the entire state of the emulator (except the cartridge ROM) into bytes
+    pub fn save_state(&self) -> Vec<u8> {
+        let state = SaveStateRef {
+            version: SAVE_STATE_VERSION,
+            cpu: &self.cpu,
+            cartridge: self.cartridge().map(|c| c.save_state()),
+            movie_frame: self.movie.as_ref().map(|active| active.frame),
+        };
+        bincode::serialize(&state).expect("emulator state should always be serializable")
+    }
+
+    /// Restores the emulator to a state from save_state
+    /// The same ROM that was used to make the save state must be loaded
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), SaveStateError> {
+        // Version is always the first field so check it first before the layout could be different
+        let version: u32 = bincode::deserialize(bytes)?;
+        if version != SAVE_STATE_VERSION {
+            return Err(SaveStateError::IncompatibleVersion(version));
+        }
+
+        let state: SaveState = bincode::deserialize(bytes)?;
+        match (self.cpu.bus.cartridge_mut(), state.cartridge) {
+            (Some(cartridge), Some(cartridge_state)) => cartridge.load_state(cartridge_state)?,
+            (None, None) => (),
+            _ => return Err(SaveStateError::CartridgeMismatch),
+        }
+
+        let mut cpu = state.cpu;
+        cpu.bus.take_unsaved_from(&mut self.cpu.bus);
+        self.cpu = cpu;
+        self.rerecord_movie(state.movie_frame);
         Ok(())
     }
 
+    /// Move the active movie back to the frame of a loaded state
+    /// Recording continues from there and drops the frames after it like FM2 rerecording
+    fn rerecord_movie(&mut self, movie_frame: Option<usize>) {
+        let Some(active) = self.movie.as_mut() else {
+            return;
+        };
+        match movie_frame {
+            Some(frame) if frame <= active.movie.frames.len() => {
+                active.frame = frame;
+                active.pending_commands = MovieCommand::empty();
+                if active.mode == MovieMode::Recording {
+                    active.movie.frames.truncate(frame);
+                } else if active.current_frame().is_none() {
+                    log::info!("Movie playback finished");
+                    self.movie = None;
+                }
+            }
+            _ => {
+                log::warn!("Stopped the movie since the loaded state isn't part of it");
+                self.movie = None;
+            }
+        }
+    }
+
     pub fn frame_rate(&self) -> f32 {
         self.frame_rate
     }
@@ -115,6 +497,24 @@ impl Emulator {
         self.cpu.bus.cartridge()
     }
 
+    pub fn cartridge_mut(&mut self) -> Option<&mut Cartridge> {
+        self.cpu.bus.cartridge_mut()
+    }
+
+    pub fn breakpoints(&self) -> &Breakpoints {
+        self.cpu.bus.breakpoints()
+    }
+
+    pub fn breakpoints_mut(&mut self) -> &mut Breakpoints {
+        self.cpu.bus.breakpoints_mut()
+    }
+
+    /// Stepping or running from a pause always runs the instruction it stopped at
+    fn skip_breakpoint_at_pc(&mut self) {
+        let pc = self.cpu.pc;
+        self.breakpoints_mut().skip_execute_at(pc);
+    }
+
     pub fn controller(&mut self, number: u8) -> &mut Controller {
         &mut self.cpu.bus.controllers[number as usize]
     }
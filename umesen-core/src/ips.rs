@@ -0,0 +1,162 @@
+//! Creating and applying IPS patches, used to store changes to disk images without modifying them
+//! https://zerosoft.zophar.net/ips.php
+
+const HEADER: &[u8] = b"PATCH";
+const FOOTER: &[u8] = b"EOF";
+/// A record at this offset would be read as the footer
+const FOOTER_OFFSET: usize = 0x454f46;
+const MAX_OFFSET: usize = 0xffffff;
+const MAX_RECORD_SIZE: usize = 0xffff;
+/// Unchanged bytes between two changes that still get merged into one record, since each
+/// record has 5 bytes of overhead
+const MERGE_DISTANCE: usize = 5;
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum IpsError {
+    #[error("Patch does not start with 'PATCH'")]
+    InvalidHeader,
+    #[error("Patch ended before the 'EOF' marker")]
+    UnexpectedEnd,
+    #[error("Data is too large for an IPS patch")]
+    TooLarge,
+}
+
+/// Create a patch that turns original into modified
+pub fn create_patch(original: &[u8], modified: &[u8]) -> Result<Vec<u8>, IpsError> {
+    if modified.len() > MAX_OFFSET {
+        return Err(IpsError::TooLarge);
+    }
+
+    let mut patch = HEADER.to_vec();
+    let differs = |i: usize| original.get(i) != Some(&modified[i]);
+    let mut i = 0;
+    while i < modified.len() {
+        if !differs(i) {
+            i += 1;
+            continue;
+        }
+
+        let mut start = i;
+        if start == FOOTER_OFFSET {
+            start -= 1;
+        }
+        // Extend the record until there are enough unchanged bytes in a row
+        let mut end = i + 1;
+        while end - start < MAX_RECORD_SIZE
+            && (end..modified.len().min(end + MERGE_DISTANCE)).any(differs)
+        {
+            end += 1;
+        }
+
+        let size = end - start;
+        patch.extend_from_slice(&(start as u32).to_be_bytes()[1..]);
+        patch.extend_from_slice(&(size as u16).to_be_bytes());
+        patch.extend_from_slice(&modified[start..end]);
+        i = end;
+    }
+    patch.extend_from_slice(FOOTER);
+
+    if modified.len() < original.len() {
+        patch.extend_from_slice(&(modified.len() as u32).to_be_bytes()[1..]);
+    }
+    Ok(patch)
+}
+
+/// Apply a patch to data, the data is extended if the patch writes past the end
+pub fn apply_patch(data: &[u8], patch: &[u8]) -> Result<Vec<u8>, IpsError> {
+    let rest = patch.strip_prefix(HEADER).ok_or(IpsError::InvalidHeader)?;
+    let mut output = data.to_vec();
+    let mut reader = PatchReader(rest);
+
+    loop {
+        if let Some(rest) = reader.0.strip_prefix(FOOTER) {
+            reader.0 = rest;
+            break;
+        }
+
+        let offset = reader.read_u24()?;
+        let size = reader.read_u16()? as usize;
+        let (size, bytes) = if size == 0 {
+            // Run length encoded record
+            let size = reader.read_u16()? as usize;
+            (size, vec![reader.take(1)?[0]; size])
+        } else {
+            (size, reader.take(size)?.to_vec())
+        };
+
+        if output.len() < offset + size {
+            output.resize(offset + size, 0);
+        }
+        output[offset..offset + size].copy_from_slice(&bytes);
+    }
+
+    // Optional truncation extension
+    if let Ok(len) = reader.read_u24() {
+        output.truncate(len);
+    }
+    Ok(output)
+}
+
+struct PatchReader<'a>(&'a [u8]);
+
+impl<'a> PatchReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], IpsError> {
+        if self.0.len() < len {
+            return Err(IpsError::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn read_u16(&mut self) -> Result<u16, IpsError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_u24(&mut self) -> Result<usize, IpsError> {
+        let bytes = self.take(3)?;
+        Ok(u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]) as usize)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let original: Vec<u8> = (0..=255).cycle().take(0x1000).collect();
+        let mut modified = original.clone();
+        modified[0] = 99;
+        modified[10] = 1;
+        modified[12] = 2;
+        for byte in &mut modified[0x800..0x900] {
+            *byte = !*byte;
+        }
+        modified.extend_from_slice(&[1, 2, 3]);
+
+        let patch = create_patch(&original, &modified).unwrap();
+        assert_eq!(apply_patch(&original, &patch), Ok(modified.clone()));
+        // Nearby changes are merged into one record
+        assert_eq!(
+            patch.len(),
+            5 + (5 + 1) + (5 + 3) + (5 + 0x100) + (5 + 3) + 3
+        );
+
+        modified.truncate(10);
+        let patch = create_patch(&original, &modified).unwrap();
+        assert_eq!(apply_patch(&original, &patch), Ok(modified));
+    }
+
+    #[test]
+    fn run_length_encoded() {
+        let patch = b"PATCH\x00\x00\x02\x00\x00\x00\x04\x07EOF";
+        assert_eq!(
+            apply_patch(&[1; 8], patch),
+            Ok(vec![1, 1, 7, 7, 7, 7, 1, 1])
+        );
+        assert_eq!(apply_patch(&[], b"PATCH\x00"), Err(IpsError::UnexpectedEnd));
+        assert_eq!(apply_patch(&[], b"PAT"), Err(IpsError::InvalidHeader));
+    }
+}
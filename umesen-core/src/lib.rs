@@ -1,13 +1,21 @@
 pub mod apu;
+pub mod breakpoint;
 pub mod cartridge;
 pub mod controller;
 pub mod cpu;
 mod emulator;
+pub mod ips;
+pub mod movie;
+pub mod power_on;
 pub mod ppu;
+pub mod region;
+pub mod rewind;
+pub mod test_rom;
 
 pub use apu::Apu;
 pub use cartridge::Cartridge;
 pub use controller::Controller;
 pub use cpu::Cpu;
-pub use emulator::Emulator;
+pub use emulator::{Emulator, SaveStateError};
 pub use ppu::Ppu;
+pub use region::Region;
@@ -0,0 +1,260 @@
+use std::fmt::Write;
+
+use crate::controller::Button;
+
+#[derive(thiserror::Error, Debug)]
+pub enum MovieError {
+    #[error("Movie version '{0}' is not supported")]
+    UnsupportedVersion(String),
+    #[error("Movie uses {0} which is not supported")]
+    Unsupported(&'static str),
+    #[error("Invalid input on line {0}")]
+    InvalidInput(usize),
+    #[error("Invalid save state in movie header")]
+    InvalidSaveState,
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+bitflags::bitflags! {
+    /// Commands that can happen at the start of a frame, same bits as FM2
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MovieCommand: u8 {
+        const SOFT_RESET = 1 << 0;
+        const HARD_RESET = 1 << 1;
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovieFrame {
+    pub commands: MovieCommand,
+    pub buttons: [Button; 2],
+}
+
+/// Recorded button states of both controllers for every frame
+/// https://fceux.com/web/help/fm2.html
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub rom_filename: String,
+    pub rerecord_count: u32,
+    pub comments: Vec<String>,
+    /// Save state the movie starts from, otherwise the movie starts from power on
+    /// FCEUX save states are not supported so this is stored under a separate key in FM2 files
+    pub start_state: Option<Vec<u8>>,
+    pub frames: Vec<MovieFrame>,
+}
+
+/// Button order of the gamepad fields in a FM2 input line, the highest bit comes first
+const FM2_BUTTONS: &[u8; 8] = b"RLDUTSBA";
+const START_STATE_KEY: &str = "umesenSaveState";
+
+impl Movie {
+    pub fn load_fm2_file(path: impl AsRef<std::path::Path>) -> Result<Self, MovieError> {
+        Self::from_fm2(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn save_fm2_file(&self, path: impl AsRef<std::path::Path>) -> Result<(), MovieError> {
+        Ok(std::fs::write(path, self.to_fm2())?)
+    }
+
+    pub fn from_fm2(text: &str) -> Result<Self, MovieError> {
+        let mut movie = Movie::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim_end();
+            if line.starts_with('|') {
+                movie
+                    .frames
+                    .push(parse_fm2_input(line).ok_or(MovieError::InvalidInput(i + 1))?);
+                continue;
+            }
+
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "version" if value != "3" => {
+                    return Err(MovieError::UnsupportedVersion(value.to_owned()));
+                }
+                "binary" if value != "0" => return Err(MovieError::Unsupported("binary input")),
+                "palFlag" if value != "0" => return Err(MovieError::Unsupported("PAL timing")),
+                "fourscore" if value != "0" => return Err(MovieError::Unsupported("Four Score")),
+                "port0" | "port1" if value != "0" && value != "1" => {
+                    return Err(MovieError::Unsupported("a non standard controller"));
+                }
+                "port2" if value != "0" => {
+                    return Err(MovieError::Unsupported("Famicom expansion"));
+                }
+                "savestate" => return Err(MovieError::Unsupported("a FCEUX save state")),
+                "romFilename" => movie.rom_filename = value.to_owned(),
+                "rerecordCount" => movie.rerecord_count = value.parse().unwrap_or_default(),
+                "comment" => movie.comments.push(value.to_owned()),
+                START_STATE_KEY => {
+                    movie.start_state =
+                        Some(decode_hex(value).ok_or(MovieError::InvalidSaveState)?);
+                }
+                _ => (),
+            }
+        }
+        Ok(movie)
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut text = String::new();
+        // Writing into a string can't fail
+        let _ = writeln!(text, "version 3");
+        let _ = writeln!(text, "emuVersion 22020");
+        let _ = writeln!(text, "rerecordCount {}", self.rerecord_count);
+        let _ = writeln!(text, "palFlag 0");
+        let _ = writeln!(text, "romFilename {}", self.rom_filename);
+        let _ = writeln!(text, "fourscore 0");
+        let _ = writeln!(text, "port0 1");
+        let _ = writeln!(text, "port1 1");
+        let _ = writeln!(text, "port2 0");
+        for comment in &self.comments {
+            let _ = writeln!(text, "comment {comment}");
+        }
+        if let Some(state) = &self.start_state {
+            let _ = writeln!(text, "{START_STATE_KEY} {}", encode_hex(state));
+        }
+
+        for frame in &self.frames {
+            let _ = write!(text, "|{}", frame.commands.bits());
+            for buttons in frame.buttons {
+                text.push('|');
+                for (i, c) in FM2_BUTTONS.iter().enumerate() {
+                    let pressed = buttons.bits() & (0x80 >> i) != 0;
+                    text.push(if pressed { *c as char } else { '.' });
+                }
+            }
+            text.push_str("||\n");
+        }
+        text
+    }
+}
+
+/// Parse a line like "|0|R..U...A|........||"
+fn parse_fm2_input(line: &str) -> Option<MovieFrame> {
+    let mut fields = line.split('|').skip(1);
+    let commands = MovieCommand::from_bits_truncate(fields.next()?.trim().parse().ok()?);
+
+    let mut buttons = [Button::empty(); 2];
+    for port_buttons in buttons.iter_mut() {
+        let field = fields.next()?.as_bytes();
+        // Field is empty if no controller is connected to the port
+        if field.is_empty() {
+            continue;
+        }
+        if field.len() != FM2_BUTTONS.len() {
+            return None;
+        }
+        for (i, c) in field.iter().enumerate() {
+            if *c != b'.' && *c != b' ' {
+                *port_buttons |= Button::from_bits_retain(0x80 >> i);
+            }
+        }
+    }
+
+    Some(MovieFrame { commands, buttons })
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut text, b| {
+        let _ = write!(text, "{b:02x}");
+        text
+    })
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovieMode {
+    Recording,
+    Playing,
+}
+
+/// Movie currently being recorded or played back by the emulator
+#[derive(Debug)]
+pub struct ActiveMovie {
+    pub movie: Movie,
+    pub mode: MovieMode,
+    /// Index of the frame currently being emulated
+    pub frame: usize,
+    /// Commands to record on the current frame
+    pub(crate) pending_commands: MovieCommand,
+}
+
+impl ActiveMovie {
+    pub fn new(movie: Movie, mode: MovieMode) -> Self {
+        Self {
+            movie,
+            mode,
+            frame: 0,
+            pending_commands: MovieCommand::empty(),
+        }
+    }
+
+    pub fn current_frame(&self) -> Option<&MovieFrame> {
+        self.movie.frames.get(self.frame)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_fm2() {
+        let text = "version 3\nromFilename test\ncomment hello world\nport1 0\n\
+            |0|R..U...A|||\n|1|........|||\n|0|.L..TS..|||\n";
+        let movie = Movie::from_fm2(text).unwrap();
+        assert_eq!(movie.rom_filename, "test");
+        assert_eq!(movie.comments, ["hello world"]);
+        assert_eq!(movie.frames.len(), 3);
+        assert_eq!(
+            movie.frames[0].buttons[0],
+            Button::RIGHT | Button::UP | Button::A
+        );
+        assert_eq!(movie.frames[1].commands, MovieCommand::SOFT_RESET);
+        assert_eq!(
+            movie.frames[2].buttons[0],
+            Button::LEFT | Button::START | Button::SELECT
+        );
+        assert!(movie.frames.iter().all(|f| f.buttons[1].is_empty()));
+
+        assert!(matches!(
+            Movie::from_fm2("version 2\n"),
+            Err(MovieError::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            Movie::from_fm2("|0|R..|||\n"),
+            Err(MovieError::InvalidInput(1))
+        ));
+    }
+
+    #[test]
+    fn fm2_roundtrip() {
+        let movie = Movie {
+            rom_filename: "game".to_owned(),
+            rerecord_count: 5,
+            comments: vec!["author me".to_owned()],
+            start_state: Some(vec![0, 1, 0xab, 0xff]),
+            frames: vec![
+                MovieFrame {
+                    commands: MovieCommand::empty(),
+                    buttons: [Button::A | Button::DOWN, Button::B],
+                },
+                MovieFrame {
+                    commands: MovieCommand::HARD_RESET,
+                    buttons: [Button::empty(), Button::all()],
+                },
+            ],
+        };
+        assert_eq!(Movie::from_fm2(&movie.to_fm2()).unwrap(), movie);
+    }
+}
@@ -0,0 +1,90 @@
+/// What RAM contains when the console is turned on, real hardware is mostly random
+/// https://www.nesdev.org/wiki/CPU_power_up_state
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum RamState {
+    #[default]
+    Zeros,
+    Ones,
+    Random,
+    /// 4 bytes of $00 then 4 bytes of $FF
+    Pattern,
+}
+
+impl RamState {
+    pub const ALL: [RamState; 4] = [
+        RamState::Zeros,
+        RamState::Ones,
+        RamState::Random,
+        RamState::Pattern,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RamState::Zeros => "All $00",
+            RamState::Ones => "All $FF",
+            RamState::Random => "Random",
+            RamState::Pattern => "$00/$FF pattern",
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct PowerOnConfig {
+    /// Applied to CPU RAM, nametable RAM, PRG RAM without a battery, CHR RAM and OAM
+    pub ram_state: RamState,
+    /// Seed for RamState::Random so runs can be reproduced
+    pub seed: u64,
+}
+
+impl PowerOnConfig {
+    /// Fill a block of memory, salt makes each block get different random values
+    pub fn fill(&self, data: &mut [u8], salt: u64) {
+        match self.ram_state {
+            RamState::Zeros => data.fill(0),
+            RamState::Ones => data.fill(0xff),
+            RamState::Random => {
+                // xorshift64, which can't have a zero state
+                let mut state = (self.seed ^ salt.wrapping_mul(0x9e37_79b9_7f4a_7c15)).max(1);
+                for byte in data {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    *byte = state as u8;
+                }
+            }
+            RamState::Pattern => {
+                for (i, byte) in data.iter_mut().enumerate() {
+                    *byte = if i % 8 < 4 { 0 } else { 0xff };
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fill() {
+        let mut data = [1; 16];
+        let config = PowerOnConfig {
+            ram_state: RamState::Pattern,
+            seed: 0,
+        };
+        config.fill(&mut data, 0);
+        assert_eq!(data[..8], [0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]);
+
+        let config = PowerOnConfig {
+            ram_state: RamState::Random,
+            seed: 1234,
+        };
+        let mut other = [0; 16];
+        config.fill(&mut data, 0);
+        config.fill(&mut other, 0);
+        assert_eq!(data, other);
+        config.fill(&mut other, 1);
+        assert_ne!(data, other);
+    }
+}
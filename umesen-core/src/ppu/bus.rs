@@ -1,6 +1,7 @@
 use crate::{
     Cartridge,
-    cartridge::{FixedArray, Mirroring},
+    breakpoint::{BreakpointKind, Breakpoints},
+    cartridge::{FixedArray, Mirroring, NametableMapping},
 };
 
 const PALETTE_RAM_SIZE: usize = 0x20;
@@ -10,11 +11,16 @@ pub const PALETTE_START: u16 = 0x3f00;
 pub const NAMETABLE_SIZE_X: u16 = 32;
 pub const NAMETABLE_SIZE_Y: u16 = 30;
 
-#[derive(Default)]
+#[derive(Default, serde::Serialize, serde::Deserialize)]
 pub struct PpuBus {
     pub palette_ram: FixedArray<u8, PALETTE_RAM_SIZE>,
     pub nametable_ram: FixedArray<u8, 0x800>,
+    /// Cartridge state is saved seperately since the ROM isn't part of a save state
+    #[serde(skip)]
     pub(crate) cartridge: Option<Cartridge>,
+    /// Kept here with the cartridge so both the CPU and PPU buses can check them
+    #[serde(skip)]
+    pub(crate) breakpoints: Breakpoints,
 }
 
 impl PpuBus {
@@ -27,7 +33,10 @@ impl PpuBus {
         }
 
         match address {
-            0x2000..=0x3eff => self.nametable_ram[self.mirror_nametable(address)],
+            0x2000..=0x3eff => match self.map_nametable(address) {
+                Ok(index) => self.nametable_ram[index],
+                Err(cart) => cart.peek_nametable(address),
+            },
             // Palette byte is only 6 bit
             PALETTE_START..=0x3fff => self.palette_ram[mirror_palette(address)],
             _ => 0,
@@ -35,32 +44,53 @@ impl PpuBus {
     }
 
     pub fn read(&mut self, address: u16) -> u8 {
-        if let Some(value) = self.cartridge.as_mut().and_then(|c| c.ppu_read(address)) {
-            value
-        } else {
-            self.peek_read(address)
+        let value = match self.cartridge.as_mut().and_then(|c| c.ppu_read(address)) {
+            Some(value) => value,
+            None => self.peek_read(address),
+        };
+        if !self.breakpoints.is_empty() {
+            self.breakpoints
+                .check(BreakpointKind::PpuRead, address, value);
         }
+        value
     }
 
     pub fn write(&mut self, address: u16, value: u8) {
         std::debug_assert_matches!(address, 0x0000..=0x3fff);
+        if !self.breakpoints.is_empty() {
+            self.breakpoints
+                .check(BreakpointKind::PpuWrite, address, value);
+        }
         if let Some(cartridge) = self.cartridge.as_mut() {
             cartridge.ppu_write(address, value);
         }
 
         match address {
-            0x2000..=0x3eff => {
-                let address = self.mirror_nametable(address);
-                self.nametable_ram[address] = value;
-            }
+            0x2000..=0x3eff => match self.map_nametable(address) {
+                Ok(index) => self.nametable_ram[index] = value,
+                Err(_) => {
+                    if let Some(cart) = self.cartridge.as_mut() {
+                        cart.write_nametable(address, value);
+                    }
+                }
+            },
             PALETTE_START..=0x3fff => self.palette_ram[mirror_palette(address)] = value,
             _ => (),
         }
     }
 
-    fn mirror_nametable(&self, address: u16) -> usize {
-        let cart = self.cartridge.as_ref();
-        mirror_nametable(address, cart.map(|c| c.mirroring()).unwrap_or_default())
+    /// Gets the index into nametable ram or the cartridge if the mapper handles the address
+    fn map_nametable(&self, address: u16) -> Result<usize, &Cartridge> {
+        let Some(cart) = self.cartridge.as_ref() else {
+            return Ok(mirror_nametable(address, Mirroring::default()));
+        };
+        match cart.map_nametable(address) {
+            Some(NametableMapping::Ciram(page)) => {
+                Ok(page as usize * 0x400 + address as usize % 0x400)
+            }
+            Some(_) => Err(cart),
+            None => Ok(mirror_nametable(address, cart.mirroring())),
+        }
     }
 }
 
@@ -73,7 +103,8 @@ fn mirror_nametable(address: u16, mirroring: Mirroring) -> usize {
             0x000..=0x7ff => 0,
             0x800.. => 1,
         },
-        Mirroring::Vertical => match table_address {
+        // Four screen nametables after the first two are mapped to the cartridge
+        Mirroring::Vertical | Mirroring::FourScreen => match table_address {
             0x000..=0x3ff => 0,
             0x400..=0x7ff => 1,
             0x800..=0xbff => 0,
@@ -81,7 +112,6 @@ fn mirror_nametable(address: u16, mirroring: Mirroring) -> usize {
         },
         Mirroring::SingleScreenLow => 0,
         Mirroring::SingleScreenHigh => 1,
-        Mirroring::FourScreen => todo!(),
     };
     (table_number * 0x400 + (address % 0x400)) as usize
 }
@@ -130,4 +160,22 @@ mod test {
         assert_eq!(mirror_palette(0x3f10), 0);
         assert_eq!(mirror_palette(0x3f11), 17);
     }
+
+    #[test]
+    fn four_screen() {
+        let mut rom = b"NES\x1a\x01\x01\x08".to_vec();
+        rom.resize(16 + 0x4000 + 0x2000, 0);
+        let mut bus = PpuBus {
+            cartridge: Some(Cartridge::from_nes(&rom[..]).unwrap()),
+            ..Default::default()
+        };
+
+        for (i, address) in [0x2000, 0x2400, 0x2800, 0x2c00].into_iter().enumerate() {
+            bus.write(address + 5, i as u8 + 1);
+        }
+        for (i, address) in [0x2000, 0x2400, 0x2800, 0x2c00].into_iter().enumerate() {
+            assert_eq!(bus.peek_read(address + 5), i as u8 + 1);
+        }
+        assert_eq!(bus.nametable_ram[0x405], 2);
+    }
 }
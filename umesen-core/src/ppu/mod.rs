@@ -1,4 +1,4 @@
-use crate::{cartridge::FixedArray, ppu::sprite::Attributes};
+use crate::{Region, cartridge::FixedArray, ppu::sprite::Attributes};
 
 mod bus;
 mod palette;
@@ -15,28 +15,26 @@ pub use vram::VramRegister;
 pub const WIDTH: usize = 256;
 pub const HEIGHT: usize = 240;
 pub const MAX_SPRITES_PER_SCAN: usize = 8;
-pub const PRERENDER_SCANLINE: usize = 261;
 
 pub type ScreenPixels = FixedArray<FixedArray<u8, 3>, { WIDTH * HEIGHT }>;
 
-pub enum PpuClockReport {
-    None,
-    Nmi,
-}
-
 #[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq, Debug, Default)]
 #[serde(default)]
 pub struct PpuConfig {
     pub unlimited_sprites: bool,
 }
 
-/// Emulated 2C02 NTSC PPU
-#[derive(Default)]
+/// Emulated 2C02 PPU, or the 2C07 with the PAL timings
+#[derive(Default, serde::Serialize, serde::Deserialize)]
 pub struct Ppu {
+    #[serde(skip)]
     pub config: PpuConfig,
     pub registers: Registers,
+    #[serde(skip)]
     pub palette: Palette,
-    pub screen_pixels: ScreenPixels,
+    /// Boxed since it's too big to be moved around on the stack
+    #[serde(skip)]
+    pub screen_pixels: Box<ScreenPixels>,
     frame_complete: bool,
 
     // Bits shifted left every render dot so leftmost bit contains low and high bit of the current pixel index in the palette
@@ -76,11 +74,17 @@ impl Ppu {
         }
     }
 
-    pub(crate) fn clock(&mut self) -> PpuClockReport {
-        let mut report = PpuClockReport::None;
+    /// The CPU gets an NMI when this goes from low to high
+    pub fn nmi_line(&self) -> bool {
+        self.registers.status.contains(Status::VBLANK)
+            && self.registers.control.contains(Control::VBLANK_NMI)
+    }
 
+    pub(crate) fn clock(&mut self) {
         // Specific scanline timings
         // See https://www.nesdev.org/w/images/default/4/4f/Ppu.svg
+        let vblank_scanline = self.registers.region.vblank_scanline();
+        let prerender_scanline = self.registers.region.prerender_scanline();
         match self.registers.scanline {
             0..=239 => {
                 self.frame_complete = false;
@@ -92,19 +96,17 @@ impl Ppu {
                     self.render_pixel();
                 }
             }
-            241 if self.registers.dot == 1 => {
+            scanline if scanline == vblank_scanline && self.registers.dot == 1 => {
                 self.frame_complete = true;
                 self.registers.status.set(Status::VBLANK, true);
-                if self.registers.control.contains(Control::VBLANK_NMI) {
-                    report = PpuClockReport::Nmi
-                }
             }
-            PRERENDER_SCANLINE if self.registers.dot == 1 => {
+            scanline if scanline == prerender_scanline && self.registers.dot == 1 => {
+                self.corrupt_oam();
                 self.registers.status.remove(Status::VBLANK);
                 self.registers.status.remove(Status::SPRITE_OVERFLOW);
                 self.registers.status.remove(Status::SPRITE_0_HIT);
             }
-            PRERENDER_SCANLINE if self.registers.mask.rendering() => {
+            scanline if scanline == prerender_scanline && self.registers.mask.rendering() => {
                 self.clock_sprite_render_line();
                 self.clock_bg_prerender_line();
             }
@@ -112,7 +114,16 @@ impl Ppu {
         }
 
         self.registers.next_dot();
-        report
+    }
+
+    /// When rendering starts with the OAM address not less than 8 the 8 bytes at the address
+    /// get copied over the first 8 bytes of OAM
+    /// https://www.nesdev.org/wiki/PPU_registers#OAMADDR
+    fn corrupt_oam(&mut self) {
+        let address = (self.registers.oam_address & 0xf8) as usize;
+        if self.registers.mask.rendering() && address != 0 {
+            self.registers.oam_data.copy_within(address..address + 8, 0);
+        }
     }
 
     fn render_pixel(&mut self) {
@@ -148,6 +159,12 @@ impl Ppu {
                 self.registers.v.scroll_fine_y();
                 self.registers.v.set_x(&self.registers.t);
             }
+            // Unused nametable fetches, MMC5 uses these to detect scanlines
+            337 | 339 => {
+                self.registers
+                    .bus
+                    .read(self.registers.v.nametable_address());
+            }
             _ => (),
         }
     }
@@ -155,8 +172,11 @@ impl Ppu {
     fn clock_bg_prerender_line(&mut self) {
         match self.registers.dot {
             280..=304 => self.registers.v.set_y(&self.registers.t),
-            // Skip last cycle on odd frames
-            339 if self.registers.frame_count % 2 == 1 => self.registers.dot += 1,
+            // Skip last cycle on odd frames, only the NTSC PPU does this
+            339 if self.registers.frame_count % 2 == 1 && self.registers.region == Region::Ntsc => {
+                self.clock_bg_render_line();
+                self.registers.dot += 1;
+            }
             // Prerender line does same stuff as render line but with extra stuff
             _ => self.clock_bg_render_line(),
         }
@@ -186,7 +206,9 @@ impl Ppu {
         match self.registers.dot {
             64 => self.eval_byte_offset = self.registers.oam_address as usize,
             // Technically supposed to happen for the entire scanline but do it once at the end for simplicity
-            256 if self.registers.scanline != PRERENDER_SCANLINE => self.eval_sprites(),
+            256 if self.registers.scanline != self.registers.region.prerender_scanline() => {
+                self.eval_sprites()
+            }
             261 => {
                 self.load_sprites();
                 self.registers.oam_address = 0;
@@ -196,35 +218,42 @@ impl Ppu {
     }
 
     /// Populates the sprite buffer for the next scanline and checks SPRITE_OVERFLOW
+    /// https://www.nesdev.org/wiki/PPU_sprite_evaluation
     fn eval_sprites(&mut self) {
         self.sprite_buffer.clear();
+        self.registers.secondary_oam.fill(0xff);
         let height = self.registers.control.sprite_height() as usize;
-
-        let mut i = 0;
-        while let Some(sprite) = self.registers.get_oam_sprite(i, self.eval_byte_offset) {
-            // Add to sprite buffer if sprite part of scanline
-            // Note that it's loading sprites for the next scanline so all sprite y is offset by one
-            let overflowed =
-                self.sprite_buffer.len() == MAX_SPRITES_PER_SCAN && !self.config.unlimited_sprites;
-            if self.registers.scanline >= sprite.y as usize
-                && self.registers.scanline < sprite.y as usize + height
-            {
-                // Check sprite overflow
-                if overflowed {
-                    self.registers.status.insert(Status::SPRITE_OVERFLOW);
-                    break;
+        let scanline = self.registers.scanline;
+        // Note that it's loading sprites for the next scanline so all sprite y is offset by one
+        let in_range = |y: u8| scanline >= y as usize && scanline < y as usize + height;
+
+        // Evaluation starts from the OAM address so a misaligned address reads other bytes as Y
+        let mut address = self.eval_byte_offset;
+        while address < self.registers.oam_data.len() {
+            let y = self.registers.oam_data[address];
+            let found = self.sprite_buffer.len();
+            if found < MAX_SPRITES_PER_SCAN || self.config.unlimited_sprites {
+                if in_range(y) {
+                    let end = (address + 4).min(self.registers.oam_data.len());
+                    let bytes = &self.registers.oam_data[address..end];
+                    if found < MAX_SPRITES_PER_SCAN {
+                        self.registers.secondary_oam[found * 4..found * 4 + bytes.len()]
+                            .copy_from_slice(bytes);
+                    }
+                    self.sprite_buffer
+                        .push(Sprite::new(bytes, (address / 4) as u8));
                 }
-                self.sprite_buffer.push(sprite);
-            } else if overflowed {
-                // After 8 sprites has been filled, the PPU will check for overflow by
-                // searching for another sprite that is in the scanline.
-                // But for some reason, when it doesn't find a sprite after filled,
-                // the next OAM y it checks is offseted by one extra byte which causes buggy
-                // behaviour when setting SPRITE_OVERFLOW flag.
-                self.eval_byte_offset += 1;
+                address += 4;
+            } else if in_range(y) {
+                self.registers.status.insert(Status::SPRITE_OVERFLOW);
+                break;
+            } else {
+                // After 8 sprites has been found, the PPU checks for overflow by searching for
+                // another sprite in the scanline. But the hardware increments both the sprite
+                // index and the byte index (without carry) when a sprite is not in range, so it
+                // checks the bytes diagonally which causes false positives and negatives
+                address = (address & !0b11) + 4 + ((address + 1) & 0b11);
             }
-
-            i += 1;
         }
     }
 
@@ -237,7 +266,7 @@ impl Ppu {
             sprite.load_shift_bits(self.registers.scanline as u16, &mut self.registers);
         }
         // Prender scanline still makes same tile fetches but nothing gets rendered
-        if self.registers.scanline == PRERENDER_SCANLINE {
+        if self.registers.scanline == self.registers.region.prerender_scanline() {
             self.sprite_buffer.clear();
         }
     }
@@ -331,3 +360,104 @@ pub fn get_pattern_tile_addresses(tile_number: u16, fine_y: u16) -> (u16, u16) {
     let address = ((tile_number) << 4) | (fine_y % 8);
     ((address), (address + 8))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Ppu with 8 sprites on scanline 10
+    fn setup_ppu() -> Ppu {
+        let mut ppu = Ppu::default();
+        ppu.registers.oam_data.fill(0xff);
+        for i in 0..8 {
+            ppu.registers.oam_data[i * 4] = 10;
+        }
+        ppu.registers.scanline = 10;
+        ppu
+    }
+
+    /// Dots until the next frame is completed
+    fn frame_dots(ppu: &mut Ppu) -> u32 {
+        let mut dots = 0;
+        while !ppu.frame_complete() {
+            ppu.clock();
+            dots += 1;
+        }
+        dots
+    }
+
+    #[test]
+    fn odd_frame_skip() {
+        let mut ppu = Ppu::default();
+        ppu.registers.mask = Mask::RENDER_BACKGROUND;
+        frame_dots(&mut ppu);
+        let frames = [frame_dots(&mut ppu), frame_dots(&mut ppu)];
+        assert_eq!(frames[0] + frames[1], 341 * 262 * 2 - 1);
+
+        ppu.registers.region = Region::Pal;
+        assert_eq!([frame_dots(&mut ppu), frame_dots(&mut ppu)], [341 * 312; 2]);
+    }
+
+    #[test]
+    fn sprite_overflow_bug() {
+        let mut ppu = setup_ppu();
+        ppu.eval_sprites();
+        assert_eq!(ppu.sprite_buffer.len(), 8);
+        assert!(!ppu.registers.status.contains(Status::SPRITE_OVERFLOW));
+        assert_eq!(ppu.registers.secondary_oam[28], 10);
+
+        // Sprite 8 is not in range so the tile number of sprite 9 is checked as Y
+        ppu.registers.oam_data[9 * 4 + 1] = 10;
+        ppu.eval_sprites();
+        assert!(ppu.registers.status.contains(Status::SPRITE_OVERFLOW));
+
+        // And the Y of sprite 9 gets skipped
+        ppu.registers.status.remove(Status::SPRITE_OVERFLOW);
+        ppu.registers.oam_data[9 * 4 + 1] = 0xff;
+        ppu.registers.oam_data[9 * 4] = 10;
+        ppu.eval_sprites();
+        assert!(!ppu.registers.status.contains(Status::SPRITE_OVERFLOW));
+    }
+
+    #[test]
+    fn oam_read_during_rendering() {
+        let mut ppu = setup_ppu();
+        ppu.eval_sprites();
+        ppu.registers.mask = Mask::RENDER_BACKGROUND;
+        ppu.registers.dot = 10;
+        assert_eq!(ppu.registers.read(0x2004), 0xff);
+        ppu.registers.dot = 257;
+        assert_eq!(ppu.registers.read(0x2004), 10);
+
+        // Writes only increment the address
+        ppu.registers.write(0x2004, 0x12);
+        assert_eq!(ppu.registers.oam_address, 4);
+        assert_eq!(ppu.registers.oam_data[0], 10);
+    }
+
+    #[test]
+    fn open_bus() {
+        let mut ppu = Ppu::default();
+        ppu.registers.write(0x2000, 0b1111_1111);
+        ppu.registers.control = Control::empty();
+        // Only the top 3 bits of status are driven
+        assert_eq!(ppu.registers.read(0x2002), 0b0001_1111);
+        assert_eq!(ppu.registers.read(0x2005), 0b0001_1111);
+    }
+
+    #[test]
+    fn oam_corruption() {
+        let mut ppu = Ppu::default();
+        for (i, byte) in ppu.registers.oam_data.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        ppu.registers.mask = Mask::RENDER_SPRITE;
+        ppu.registers.oam_address = 0x13;
+        ppu.corrupt_oam();
+        assert_eq!(
+            &ppu.registers.oam_data[..8],
+            &[16, 17, 18, 19, 20, 21, 22, 23]
+        );
+        assert_eq!(ppu.registers.oam_data[8], 8);
+    }
+}
@@ -1,13 +1,14 @@
 use crate::{
     cartridge::FixedArray,
     ppu::{
-        HEIGHT, PALETTE_START, PATTERN_TILE_COUNT, PRERENDER_SCANLINE, Sprite, VramRegister, WIDTH,
-        bus::PpuBus, sprite::Attributes,
+        HEIGHT, PALETTE_START, PATTERN_TILE_COUNT, Sprite, VramRegister, WIDTH, bus::PpuBus,
+        sprite::Attributes,
     },
+    region::Region,
 };
 
 bitflags::bitflags! {
-    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
     pub struct Control: u8 {
         /// XY bits of nametable or each unit is 0x400 offset
         const NAMETABLE = 0b11;
@@ -43,7 +44,7 @@ impl Control {
 }
 
 bitflags::bitflags! {
-    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
     pub struct Mask: u8 {
         const GRAYSCALE = 1;
         /// Show background in leftmost 8 pixels of screen
@@ -78,7 +79,7 @@ impl Mask {
 }
 
 bitflags::bitflags! {
-    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
     pub struct Status: u8 {
         const SPRITE_OVERFLOW = 1 << 5;
         const SPRITE_0_HIT = 1 << 6;
@@ -86,10 +87,10 @@ bitflags::bitflags! {
     }
 }
 
-/// AKA how many frames before open bus decays to 0
-const OPEN_BUS_DECAY_START: u32 = 30;
+/// AKA how many frames before a bit of the open bus decays to 0
+const OPEN_BUS_DECAY_START: u8 = 30;
 
-#[derive(Default)]
+#[derive(Default, serde::Serialize, serde::Deserialize)]
 pub struct Registers {
     pub bus: PpuBus,
     pub control: Control,
@@ -101,37 +102,43 @@ pub struct Registers {
     pub fine_x: u8,
     pub oam_address: u8,
     pub oam_data: FixedArray<u8, 256>,
+    /// Sprites found for the next scanline, filled by sprite evaluation
+    pub secondary_oam: FixedArray<u8, 32>,
     pub read_buffer: u8,
+    /// Latch of the I/O bus, read from registers that don't drive every bit
     pub open_bus: u8,
-    open_bus_decay_counter: u32,
+    /// Each bit of the open bus decays on its own
+    open_bus_decay_counters: [u8; 8],
 
     pub scanline: usize,
     pub dot: usize,
     pub frame_count: u32,
+    pub region: Region,
 }
 
 impl Registers {
     pub(crate) fn read(&mut self, address: u16) -> u8 {
-        let output = match address % 8 {
-            2 => self.read_status(),
-            4 => self.read_oam_data(),
+        // Bits the register drives, the rest come from the open bus
+        let (value, driven_bits) = match address % 8 {
+            2 => (self.read_status(), 0b1110_0000),
+            4 => (self.read_oam_data(), 0xff),
             7 => self.read_vram_data(),
-            _ => self.open_bus,
+            _ => (0, 0),
         };
-        self.open_bus = output;
-        self.open_bus_decay_counter = OPEN_BUS_DECAY_START;
-        output
+        self.refresh_open_bus(value, driven_bits);
+        self.open_bus
     }
 
     pub(crate) fn write(&mut self, address: u16, value: u8) {
         std::debug_assert_matches!(address, 0x2000..=0x3fff);
-        self.open_bus = value;
-        self.open_bus_decay_counter = OPEN_BUS_DECAY_START;
+        self.refresh_open_bus(value, 0xff);
         match address % 8 {
             0 => self.write_control(value),
             1 => self.mask = Mask::from_bits(value).unwrap(),
             2 => (),
             3 => self.oam_address = value,
+            // Writes during rendering don't go through but still bump the high bits of the address
+            4 if self.rendering_active() => self.oam_address = self.oam_address.wrapping_add(4),
             4 => self.write_oam_data(value),
             5 => self.write_scroll(value),
             6 => self.write_vram_address(value),
@@ -161,6 +168,21 @@ impl Registers {
         value
     }
 
+    fn refresh_open_bus(&mut self, value: u8, driven_bits: u8) {
+        self.open_bus = (self.open_bus & !driven_bits) | (value & driven_bits);
+        for (i, counter) in self.open_bus_decay_counters.iter_mut().enumerate() {
+            if driven_bits & (1 << i) != 0 {
+                *counter = OPEN_BUS_DECAY_START;
+            }
+        }
+    }
+
+    /// On a visible or prerender scanline with rendering on, when the PPU is using OAM
+    pub(crate) fn rendering_active(&self) -> bool {
+        self.mask.rendering()
+            && (self.scanline < HEIGHT || self.scanline == self.region.prerender_scanline())
+    }
+
     pub(crate) fn on_visble_dot(&self) -> bool {
         self.dot >= 1 && (self.dot - 1 < WIDTH)
     }
@@ -172,36 +194,54 @@ impl Registers {
             self.scanline += 1;
         }
 
-        if self.scanline == PRERENDER_SCANLINE + 1 {
+        if self.scanline > self.region.prerender_scanline() {
             self.frame_count = self.frame_count.wrapping_add(1);
             self.scanline = 0;
-            if self.open_bus_decay_counter == 0 {
-                self.open_bus = 0;
-            } else {
-                self.open_bus_decay_counter -= 1;
+            for (i, counter) in self.open_bus_decay_counters.iter_mut().enumerate() {
+                if *counter == 0 {
+                    self.open_bus &= !(1 << i);
+                } else {
+                    *counter -= 1;
+                }
             }
         }
     }
 
-    // TODO: weird oam behaviour while reading/writing during rendering
+    /// During rendering this returns whatever the PPU is reading from OAM at the time
+    /// Sprite evaluation is done all at once so the primary OAM is read at the OAM address
+    /// https://www.nesdev.org/wiki/PPU_sprite_evaluation
     fn read_oam_data(&self) -> u8 {
-        self.oam_data[self.oam_address as usize]
+        if !self.rendering_active() {
+            return self.oam_data[self.oam_address as usize];
+        }
+        match self.dot {
+            // Secondary OAM being cleared
+            1..=64 => 0xff,
+            65..=256 => self.oam_data[self.oam_address as usize],
+            // Sprite tile fetches read Y, tile, attributes then X for the rest of the 8 dots
+            257..=320 => {
+                let offset = (self.dot - 257) % 8;
+                self.secondary_oam[(self.dot - 257) / 8 * 4 + offset.min(3)]
+            }
+            _ => self.secondary_oam[0],
+        }
     }
 
     fn read_status(&mut self) -> u8 {
         let status = self.status.bits();
         self.status.remove(Status::VBLANK);
         self.latch = false;
-        // PPUSTATUS (with unused bits filled with open bus)
-        status | (self.open_bus & (!Status::all().bits()))
+        status
     }
 
-    fn read_vram_data(&mut self) -> u8 {
-        let mut output = self.read_buffer;
+    /// Returns the value and the bits that it drives on the open bus
+    fn read_vram_data(&mut self) -> (u8, u8) {
+        let mut output = (self.read_buffer, 0xff);
         // Palette address gets data returned immediately instead of being buffered
         // but read_buffer populated with nametable data
+        // Palette RAM is only 6 bits so the top 2 bits are open bus
         if self.v.0 >= PALETTE_START {
-            output = self.read_palette_ram(self.v.0);
+            output = (self.read_palette_ram(self.v.0), 0b0011_1111);
             self.read_buffer = self.bus.read(0x2f00 | (self.v.0 & 0xff));
         } else {
             self.read_buffer = self.bus.read(self.v.0);
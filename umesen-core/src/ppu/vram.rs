@@ -6,7 +6,7 @@
 ///  |||||++-+++--------- coarse Y scroll
 ///  |||++--------------- nametable select X and y
 ///  +++----------------- fine Y scroll
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct VramRegister(pub u16);
 
 #[rustfmt::skip]
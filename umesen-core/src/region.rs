@@ -0,0 +1,79 @@
+/// Console region, which decides the timing of the CPU, PPU and APU
+/// https://www.nesdev.org/wiki/Cycle_reference_chart
+#[derive(
+    Default, Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    /// Famiclone with PAL clocks but NTSC-like APU timings and a longer vblank
+    Dendy,
+}
+
+impl Region {
+    pub const ALL: [Region; 3] = [Region::Ntsc, Region::Pal, Region::Dendy];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Region::Ntsc => "NTSC",
+            Region::Pal => "PAL",
+            Region::Dendy => "Dendy",
+        }
+    }
+
+    pub fn cpu_clock_hz(self) -> f32 {
+        match self {
+            Region::Ntsc => 1789773.,
+            Region::Pal => 1662607.,
+            Region::Dendy => 1773448.,
+        }
+    }
+
+    /// Average CPU cycles in a frame
+    pub fn cycles_per_frame(self) -> f32 {
+        match self {
+            Region::Ntsc => 29780.5,
+            Region::Pal => 33247.5,
+            Region::Dendy => 35464.,
+        }
+    }
+
+    /// PPU dots to run for a CPU cycle, PAL runs 16 dots every 5 cycles
+    pub fn ppu_dots(self, cpu_cycle: u64) -> u8 {
+        match self {
+            Region::Pal if cpu_cycle.is_multiple_of(5) => 4,
+            _ => 3,
+        }
+    }
+
+    /// Scanline where vblank starts and the NMI happens
+    pub fn vblank_scanline(self) -> usize {
+        match self {
+            Region::Dendy => 291,
+            _ => 241,
+        }
+    }
+
+    /// Last scanline of the frame
+    pub fn prerender_scanline(self) -> usize {
+        match self {
+            Region::Ntsc => 261,
+            Region::Pal | Region::Dendy => 311,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ppu_dots() {
+        for region in Region::ALL {
+            let dots: u32 = (0..5).map(|i| region.ppu_dots(i) as u32).sum();
+            let expected = if region == Region::Pal { 16 } else { 15 };
+            assert_eq!(dots, expected, "{region:?}");
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::VecDeque;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct RewindConfig {
+    pub enabled: bool,
+    /// Maximum amount of memory used to store snapshots in megabytes
+    pub memory_budget_mb: u32,
+    /// Number of frames between each snapshot
+    pub frames_per_snapshot: u32,
+}
+
+impl Default for RewindConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            memory_budget_mb: 64,
+            frames_per_snapshot: 2,
+        }
+    }
+}
+
+/// Bounded history of save state snapshots for rewinding
+/// Only the newest snapshot is stored fully, every snapshot before it is stored as a compressed
+/// delta from the snapshot after it so the oldest snapshots can be dropped when over budget
+#[derive(Default)]
+pub struct Rewind {
+    pub config: RewindConfig,
+    newest: Vec<u8>,
+    /// Back of the deque contains the delta of the snapshot right before the newest
+    deltas: VecDeque<Vec<u8>>,
+    deltas_size: usize,
+    frames_since_snapshot: u32,
+}
+
+impl Rewind {
+    /// Called on every frame, returns true if a snapshot should be pushed this frame
+    pub fn should_snapshot(&mut self) -> bool {
+        if !self.config.enabled {
+            return false;
+        }
+
+        self.frames_since_snapshot += 1;
+        if self.frames_since_snapshot >= self.config.frames_per_snapshot {
+            self.frames_since_snapshot = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn push(&mut self, snapshot: Vec<u8>) {
+        if !self.newest.is_empty() {
+            let delta = encode_delta(&snapshot, &self.newest);
+            self.deltas_size += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.newest = snapshot;
+
+        let budget = self.config.memory_budget_mb as usize * 1024 * 1024;
+        while self.deltas_size + self.newest.len() > budget
+            && let Some(delta) = self.deltas.pop_front()
+        {
+            self.deltas_size -= delta.len();
+        }
+    }
+
+    /// Go back to the snapshot before the newest one and return it
+    /// Returns None if there are no more snapshots to go back to
+    pub fn pop(&mut self) -> Option<&[u8]> {
+        let delta = self.deltas.pop_back()?;
+        self.deltas_size -= delta.len();
+        self.newest = decode_delta(&self.newest, &delta);
+        Some(&self.newest)
+    }
+
+    pub fn clear(&mut self) {
+        self.newest.clear();
+        self.deltas.clear();
+        self.deltas_size = 0;
+        self.frames_since_snapshot = 0;
+    }
+
+    /// Number of snapshots that can be gone back to
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Total number of bytes used by the snapshots
+    pub fn memory_used(&self) -> usize {
+        self.deltas_size + self.newest.len()
+    }
+}
+
+/// Encodes the bytes in `to` relative to `from` by xoring them together and compressing the runs
+/// of zeros, which there are a lot of since most of the state stays the same between frames
+/// Format: u32 length of `to` then repeating (zero run length, literal run length, literals)
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let len = from.len().max(to.len());
+    let xor = |i: usize| from.get(i).unwrap_or(&0) ^ to.get(i).unwrap_or(&0);
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&(to.len() as u32).to_le_bytes());
+    let mut i = 0;
+    while i < len {
+        let zeros_start = i;
+        while i < len && xor(i) == 0 {
+            i += 1;
+        }
+        write_varint(&mut out, i - zeros_start);
+
+        let literals_start = i;
+        while i < len && xor(i) != 0 {
+            i += 1;
+        }
+        write_varint(&mut out, i - literals_start);
+        out.extend((literals_start..i).map(xor));
+    }
+    out
+}
+
+fn decode_delta(from: &[u8], delta: &[u8]) -> Vec<u8> {
+    let (len_bytes, mut delta) = delta.split_at(4);
+    let to_len = u32::from_le_bytes(len_bytes.try_into().unwrap()) as usize;
+    let mut out = Vec::with_capacity(to_len);
+    let from_byte = |i: usize| *from.get(i).unwrap_or(&0);
+
+    while !delta.is_empty() {
+        let zeros = read_varint(&mut delta);
+        out.extend((out.len()..out.len() + zeros).map(from_byte));
+
+        let literals = read_varint(&mut delta);
+        let (literal_bytes, rest) = delta.split_at(literals);
+        for byte in literal_bytes {
+            out.push(from_byte(out.len()) ^ byte);
+        }
+        delta = rest;
+    }
+
+    out.resize(to_len, 0);
+    out
+}
+
+/// Writes a LEB128 variable length integer
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(bytes: &mut &[u8]) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    while let Some((&byte, rest)) = bytes.split_first() {
+        *bytes = rest;
+        value |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn delta_roundtrip() {
+        let a = vec![1, 2, 3, 0, 0, 0, 0, 9, 9];
+        let b = vec![1, 2, 4, 0, 0, 0, 0, 9, 9, 5, 6];
+        assert_eq!(decode_delta(&a, &encode_delta(&a, &b)), b);
+        assert_eq!(decode_delta(&b, &encode_delta(&b, &a)), a);
+
+        let big = vec![7; 1000];
+        let delta = encode_delta(&big, &big);
+        assert!(delta.len() < 10);
+        assert_eq!(decode_delta(&big, &delta), big);
+    }
+
+    #[test]
+    fn push_pop() {
+        let mut rewind = Rewind::default();
+        for i in 0..5 {
+            rewind.push(vec![i; 100]);
+        }
+        assert_eq!(rewind.len(), 4);
+        assert_eq!(rewind.pop(), Some(&[3; 100][..]));
+        assert_eq!(rewind.pop(), Some(&[2; 100][..]));
+        rewind.push(vec![10; 50]);
+        assert_eq!(rewind.pop(), Some(&[2; 100][..]));
+        assert_eq!(rewind.pop(), Some(&[1; 100][..]));
+        assert_eq!(rewind.pop(), Some(&[0; 100][..]));
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn memory_budget() {
+        let mut rewind = Rewind::default();
+        rewind.config.memory_budget_mb = 1;
+        for i in 0..100 {
+            rewind.push((0..100_000).map(|j| (i + j) as u8).collect());
+        }
+        assert!(rewind.memory_used() <= 1024 * 1024);
+        assert!(!rewind.is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{Emulator, cpu::CpuError};
+
+/// Address of the status byte written by test ROMs
+const STATUS_ADDRESS: u16 = 0x6000;
+/// Written to $6001-$6003 once the status and text are valid
+const SIGNATURE: [u8; 3] = [0xde, 0xb0, 0x61];
+const TEXT_ADDRESS: u16 = 0x6004;
+/// Frames to wait before resetting when a ROM requests it, needs to be at least 100ms
+const RESET_DELAY_FRAMES: u32 = 10;
+/// Result code written by older test ROMs from before the $6000 protocol
+const LEGACY_RESULT_ADDRESS: u16 = 0x00f8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestRomStatus {
+    Passed,
+    /// Result code of the failed test
+    Failed(u8),
+    /// Didn't finish in the allowed number of frames
+    TimedOut,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestRomResult {
+    pub status: TestRomStatus,
+    /// Text output by the ROM
+    pub text: String,
+    pub frames: u32,
+}
+
+/// Runs a test ROM that reports its results using the $6000 status protocol used by blargg's
+/// test ROMs until it finishes or max_frames is reached
+/// https://github.com/christopherpow/nes-test-roms/blob/master/instr_test-v5/readme.txt
+pub fn run_test_rom(emu: &mut Emulator, max_frames: u32) -> Result<TestRomResult, CpuError> {
+    let mut reset_in_frames = None;
+    for frame in 0..max_frames {
+        emu.next_frame()?;
+
+        if let Some(frames) = reset_in_frames.as_mut() {
+            *frames -= 1;
+            if *frames == 0 {
+                reset_in_frames = None;
+                emu.soft_reset();
+            }
+            continue;
+        }
+
+        if !has_signature(emu) {
+            continue;
+        }
+        let status = match emu.cpu.bus.peek_read(STATUS_ADDRESS) {
+            // Still running
+            0x80 => continue,
+            0x81 => {
+                reset_in_frames = Some(RESET_DELAY_FRAMES);
+                continue;
+            }
+            0 => TestRomStatus::Passed,
+            code => TestRomStatus::Failed(code),
+        };
+        return Ok(TestRomResult {
+            status,
+            text: read_text(emu),
+            frames: frame + 1,
+        });
+    }
+
+    Ok(TestRomResult {
+        status: TestRomStatus::TimedOut,
+        text: read_text(emu),
+        frames: max_frames,
+    })
+}
+
+/// Runs an older blargg test ROM for a number of frames and reads the result code they leave
+/// at $F8, where 1 means passed and anything else is the number of the failed test
+pub fn run_legacy_test_rom(emu: &mut Emulator, frames: u32) -> Result<TestRomResult, CpuError> {
+    for _ in 0..frames {
+        emu.next_frame()?;
+    }
+    let status = match emu.cpu.bus.peek_read(LEGACY_RESULT_ADDRESS) {
+        1 => TestRomStatus::Passed,
+        0 => TestRomStatus::TimedOut,
+        code => TestRomStatus::Failed(code),
+    };
+    Ok(TestRomResult {
+        status,
+        text: String::new(),
+        frames,
+    })
+}
+
+fn has_signature(emu: &Emulator) -> bool {
+    (0..3).all(|i| emu.cpu.bus.peek_read(STATUS_ADDRESS + 1 + i) == SIGNATURE[i as usize])
+}
+
+/// Read the zero terminated text at $6004
+fn read_text(emu: &Emulator) -> String {
+    if !has_signature(emu) {
+        return String::new();
+    }
+    let bytes: Vec<u8> = (TEXT_ADDRESS..0x8000)
+        .map(|address| emu.cpu.bus.peek_read(address))
+        .take_while(|byte| *byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).trim().to_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Cartridge;
+
+    #[test]
+    fn reads_status() {
+        #[rustfmt::skip]
+        let program = [
+            0xa9, 0xde, 0x8d, 0x01, 0x60, // LDA #$de; STA $6001
+            0xa9, 0xb0, 0x8d, 0x02, 0x60, // LDA #$b0; STA $6002
+            0xa9, 0x61, 0x8d, 0x03, 0x60, // LDA #$61; STA $6003
+            0xa9, b'o', 0x8d, 0x04, 0x60, // LDA #'o'; STA $6004
+            0xa9, b'k', 0x8d, 0x05, 0x60, // LDA #'k'; STA $6005
+            0xa9, 0x00, 0x8d, 0x06, 0x60, // LDA #0; STA $6006
+            0xa9, 0x03, 0x8d, 0x00, 0x60, // LDA #3; STA $6000
+            0x4c, 0x23, 0x80, // JMP $8023
+        ];
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[..program.len()].copy_from_slice(&program);
+        // Reset vector to $8000
+        prg_rom[0x3ffd] = 0x80;
+
+        let mut emu = Emulator::default();
+        emu.cpu.bus.attach_catridge(
+            Cartridge::from_mapper(0, vec![0; 0x2000], prg_rom, vec![0; 0x2000]).unwrap(),
+        );
+        emu.cpu.reset();
+
+        let result = run_test_rom(&mut emu, 10).unwrap();
+        assert_eq!(result.status, TestRomStatus::Failed(3));
+        assert_eq!(result.text, "ok");
+        assert_eq!(result.frames, 1);
+    }
+
+    #[test]
+    fn reads_legacy_result() {
+        // LDA #1; STA $f8; JMP $8004
+        let program = [0xa9, 0x01, 0x85, 0xf8, 0x4c, 0x04, 0x80];
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[..program.len()].copy_from_slice(&program);
+        prg_rom[0x3ffd] = 0x80;
+
+        let mut emu = Emulator::default();
+        emu.cpu.bus.attach_catridge(
+            Cartridge::from_mapper(0, vec![0; 0x2000], prg_rom, vec![0; 0x2000]).unwrap(),
+        );
+        emu.cpu.reset();
+
+        let result = run_legacy_test_rom(&mut emu, 2).unwrap();
+        assert_eq!(result.status, TestRomStatus::Passed);
+    }
+}
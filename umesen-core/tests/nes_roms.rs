@@ -1,4 +1,10 @@
-use umesen_core::{Emulator, controller::Button};
+use umesen_core::{
+    Emulator, Region,
+    breakpoint::{Breakpoint, BreakpointKind, Condition},
+    controller::Button,
+    movie::Movie,
+    power_on::{PowerOnConfig, RamState},
+};
 
 // Test rom by kevtris https://www.qmtpro.com/~nes/misc/nestest.txt
 #[test]
@@ -37,3 +43,217 @@ fn nestest() {
         emu.cpu.execute_next().unwrap();
     }
 }
+
+#[test]
+fn save_state_roundtrip() {
+    let mut emu = Emulator::default();
+    emu.load_nes_rom(&include_bytes!("nestest.nes")[..])
+        .unwrap();
+    emu.cpu.pc = 0xc000;
+    for _ in 0..1000 {
+        emu.cpu.execute_next().unwrap();
+    }
+
+    let state = emu.save_state();
+    let run = |emu: &mut Emulator| {
+        (0..1000)
+            .map(|_| {
+                emu.cpu.execute_next().unwrap();
+                (
+                    emu.cpu.pc,
+                    emu.cpu.a,
+                    emu.cpu.x,
+                    emu.cpu.bus.cpu_cycles_total,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let expected = run(&mut emu);
+    emu.load_state(&state).unwrap();
+    assert_eq!(run(&mut emu), expected);
+}
+
+#[test]
+fn movie_playback() {
+    let mut emu = Emulator::default();
+    emu.load_nes_rom(&include_bytes!("nestest.nes")[..])
+        .unwrap();
+    emu.next_frame().unwrap();
+
+    // Move through the nestest menu and start the tests
+    emu.record_movie(true);
+    let inputs = [
+        Button::DOWN,
+        Button::empty(),
+        Button::START,
+        Button::empty(),
+    ];
+    let mut expected = Vec::new();
+    for i in 0..60 {
+        emu.controller(0).set_state(inputs[i / 15]);
+        emu.next_frame().unwrap();
+        expected.push((emu.cpu.pc, emu.cpu.bus.ram.to_vec()));
+    }
+    let movie = emu.stop_movie().unwrap();
+    assert_eq!(movie.frames.len(), 60);
+
+    let movie = Movie::from_fm2(&movie.to_fm2()).unwrap();
+    emu.controller(0).set_state(Button::all());
+    emu.play_movie(movie).unwrap();
+    for state in expected {
+        emu.next_frame().unwrap();
+        assert!(
+            (emu.cpu.pc, emu.cpu.bus.ram.to_vec()) == state,
+            "Movie playback desynced"
+        );
+    }
+    assert!(emu.movie().is_none());
+}
+
+#[test]
+fn movie_rerecord() {
+    let mut emu = Emulator::default();
+    emu.load_nes_rom(&include_bytes!("nestest.nes")[..])
+        .unwrap();
+    let outside_movie = emu.save_state();
+    emu.record_movie(true);
+    for _ in 0..10 {
+        emu.next_frame().unwrap();
+    }
+    let state = emu.save_state();
+    for _ in 0..20 {
+        emu.next_frame().unwrap();
+    }
+
+    // Loading goes back to the frame of the state and drops the frames after it
+    emu.load_state(&state).unwrap();
+    let active = emu.movie().unwrap();
+    assert_eq!((active.frame, active.movie.frames.len()), (10, 10));
+    for _ in 0..5 {
+        emu.next_frame().unwrap();
+    }
+    let movie = emu.stop_movie().unwrap();
+    assert_eq!(movie.frames.len(), 15);
+
+    emu.play_movie(movie).unwrap();
+    for _ in 0..12 {
+        emu.next_frame().unwrap();
+    }
+    emu.load_state(&state).unwrap();
+    assert_eq!(emu.movie().unwrap().frame, 10);
+
+    // A state from before the movie started can't be placed in it
+    emu.load_state(&outside_movie).unwrap();
+    assert!(emu.movie().is_none());
+}
+
+#[test]
+fn region_frame_length() {
+    let mut emu = Emulator::default();
+    emu.load_nes_rom(&include_bytes!("nestest.nes")[..])
+        .unwrap();
+    for region in Region::ALL {
+        emu.set_region_override(Some(region));
+        assert_eq!(emu.region(), region);
+        emu.next_frame().unwrap();
+        let start = emu.cpu.bus.cpu_cycles_total;
+        emu.next_frame().unwrap();
+        let cycles = (emu.cpu.bus.cpu_cycles_total - start) as f32;
+        // Frames end on an instruction boundary
+        assert!(
+            (cycles - region.cycles_per_frame()).abs() < 8.,
+            "{region:?} frame took {cycles} cycles"
+        );
+    }
+    emu.set_region_override(None);
+    assert_eq!(emu.region(), Region::Ntsc);
+}
+
+#[test]
+fn power_on_state() {
+    let mut emu = Emulator::default();
+    emu.power_on = PowerOnConfig {
+        ram_state: RamState::Random,
+        seed: 42,
+    };
+    emu.load_nes_rom(&include_bytes!("nestest.nes")[..])
+        .unwrap();
+    let ram = emu.cpu.bus.ram.to_vec();
+    assert!(ram.iter().any(|byte| *byte != 0));
+
+    for _ in 0..1000 {
+        emu.cpu.execute_next().unwrap();
+    }
+    emu.hard_reset();
+    assert_eq!(emu.cpu.bus.ram.to_vec(), ram);
+    assert_eq!(emu.cpu.pc, 0xc004);
+
+    // Soft resets keep RAM
+    emu.cpu.bus.ram[0] = !ram[0];
+    emu.soft_reset();
+    assert_eq!(emu.cpu.bus.ram[0], !ram[0]);
+}
+
+#[test]
+fn breakpoints() {
+    let mut emu = Emulator::default();
+    emu.load_nes_rom(&include_bytes!("nestest.nes")[..])
+        .unwrap();
+    emu.cpu.pc = 0xc000;
+    let list = &mut emu.breakpoints_mut().list;
+    list.push(Breakpoint::new(BreakpointKind::Execute, 0xc72d, 0xc72d));
+    list.push(Breakpoint {
+        condition: Some(Condition::parse("value == $ff && A == $ff").unwrap()),
+        ..Breakpoint::new(BreakpointKind::Write, 0x0001, 0x0001)
+    });
+
+    // Stops before running the instruction
+    let reason = emu.next_frame().unwrap().unwrap();
+    assert_eq!((reason.index, reason.address), (0, 0xc72d));
+    assert_eq!(emu.cpu.pc, 0xc72d);
+    assert_eq!(emu.cpu.bus.cpu_cycles_total, 27);
+
+    // Stops after the instruction that wrote
+    let reason = emu.next_frame().unwrap().unwrap();
+    assert_eq!((reason.index, reason.value), (1, 0xff));
+    assert_eq!(emu.cpu.pc, 0xc782);
+    assert_eq!(emu.breakpoints().list[1].hit_count, 1);
+
+    emu.breakpoints_mut().list.clear();
+    emu.breakpoints_mut()
+        .list
+        .push(Breakpoint::new(BreakpointKind::PpuWrite, 0x2000, 0x23ff));
+    emu.hard_reset();
+    let reason = (0..10).find_map(|_| emu.next_frame().unwrap()).unwrap();
+    assert_eq!(reason.kind, BreakpointKind::PpuWrite);
+    assert!(emu.breakpoints().list[0].hit_count > 0);
+}
+
+fn screen(emu: &mut Emulator) -> Vec<[u8; 3]> {
+    emu.ppu()
+        .screen_pixels
+        .iter()
+        .map(|pixel| **pixel)
+        .collect()
+}
+
+#[test]
+fn rewind_screen() {
+    let mut emu = Emulator::default();
+    emu.rewind.config.frames_per_snapshot = 1;
+    emu.load_nes_rom(&include_bytes!("nestest.nes")[..])
+        .unwrap();
+    let mut screens = Vec::new();
+    for _ in 0..10 {
+        emu.next_frame().unwrap();
+        screens.push(screen(&mut emu));
+    }
+
+    // Each step goes back to the frame the snapshot was made on
+    for expected in screens.iter().rev().skip(1) {
+        assert!(emu.rewind_step());
+        assert!(screen(&mut emu) == *expected);
+    }
+    assert!(!emu.rewind_step());
+    assert!(screen(&mut emu) != *screens.last().unwrap());
+}
@@ -0,0 +1,190 @@
+//! Runs the standard test ROMs that report results at $6000 and prints a scoreboard
+//! The ROMs are not included in the repo so the test is ignored by default, point
+//! UMESEN_TEST_ROMS at a checkout of https://github.com/christopherpow/nes-test-roms and run
+//! `cargo test -p umesen-core --test test_roms -- --ignored` to run them
+
+use umesen_core::{
+    Emulator,
+    cpu::CpuError,
+    test_rom::{TestRomResult, TestRomStatus, run_legacy_test_rom, run_test_rom},
+};
+
+const MAX_FRAMES: u32 = 60 * 60;
+/// Legacy ROMs don't say when they are done so they always run this long
+const LEGACY_FRAMES: u32 = 60 * 10;
+
+const TEST_ROMS: &[(&str, &[&str])] = &[
+    (
+        "instr_test-v5/rom_singles",
+        &[
+            "01-basics",
+            "02-implied",
+            "03-immediate",
+            "04-zero_page",
+            "05-zp_xy",
+            "06-absolute",
+            "07-abs_xy",
+            "08-ind_x",
+            "09-ind_y",
+            "10-branches",
+            "11-stack",
+            "12-jmp_jsr",
+            "13-rts",
+            "14-rti",
+            "15-brk",
+            "16-special",
+        ],
+    ),
+    (
+        "cpu_interrupts_v2/rom_singles",
+        &[
+            "1-cli_latency",
+            "2-nmi_and_brk",
+            "3-nmi_and_irq",
+            "4-irq_and_dma",
+            "5-branch_delays_irq",
+        ],
+    ),
+    (
+        "ppu_vbl_nmi/rom_singles",
+        &[
+            "01-vbl_basics",
+            "02-vbl_set_time",
+            "03-vbl_clear_time",
+            "04-nmi_control",
+            "05-nmi_timing",
+            "06-suppression",
+            "07-nmi_on_timing",
+            "08-nmi_off_timing",
+            "09-even_odd_frames",
+            "10-even_odd_timing",
+        ],
+    ),
+    ("oam_read", &["oam_read"]),
+    ("oam_stress", &["oam_stress"]),
+    (
+        "apu_test/rom_singles",
+        &[
+            "1-len_ctr",
+            "2-len_table",
+            "3-irq_flag",
+            "4-jitter",
+            "5-len_timing",
+            "6-irq_flag_timing",
+            "7-dmc_basics",
+            "8-dmc_rates",
+        ],
+    ),
+    (
+        "dmc_dma_during_read4",
+        &[
+            "dma_2007_read",
+            "dma_2007_write",
+            "dma_4016_read",
+            "double_2007_read",
+            "read_write_2007",
+        ],
+    ),
+    (
+        "mmc3_test_2/rom_singles",
+        &[
+            "1-clocking",
+            "2-details",
+            "3-A12_clocking",
+            "4-scanline_timing",
+            "5-MMC3",
+            "6-MMC3_alt",
+        ],
+    ),
+];
+
+/// Older ROMs that report a result code at $F8 instead
+const LEGACY_TEST_ROMS: &[(&str, &[&str])] = &[(
+    "sprite_overflow_tests",
+    &[
+        "1.Basics",
+        "2.Details",
+        "3.Timing",
+        "4.Obscure",
+        "5.Emulator",
+    ],
+)];
+
+/// ROMs that are expected to fail, printed in the scoreboard without failing the test
+const KNOWN_FAILURES: &[(&str, &str)] = &[(
+    "mmc3_test_2/rom_singles/6-MMC3_alt",
+    "the MMC3 revision A IRQ needs submapper 4, which its iNES 1.0 header can't specify",
+)];
+
+type Runner = fn(&mut Emulator) -> Result<TestRomResult, CpuError>;
+
+#[test]
+#[ignore = "needs the test ROMs from UMESEN_TEST_ROMS"]
+fn test_roms() {
+    let dir = std::path::PathBuf::from(
+        std::env::var("UMESEN_TEST_ROMS")
+            .expect("UMESEN_TEST_ROMS should point at a checkout of nes-test-roms"),
+    );
+
+    let mut failed = Vec::new();
+    let mut known_failed = 0;
+    let mut missing = Vec::new();
+    let mut passed = 0;
+    let runners = [
+        (TEST_ROMS, (|emu| run_test_rom(emu, MAX_FRAMES)) as Runner),
+        (LEGACY_TEST_ROMS, |emu| {
+            run_legacy_test_rom(emu, LEGACY_FRAMES)
+        }),
+    ];
+    let suites = runners
+        .iter()
+        .flat_map(|(suites, runner)| suites.iter().map(move |suite| (suite, runner)));
+    for ((suite, roms), runner) in suites {
+        for rom in roms.iter() {
+            let path = dir.join(suite).join(format!("{rom}.nes"));
+            let name = format!("{suite}/{rom}");
+            if !path.exists() {
+                println!("MISSING {name}");
+                missing.push(name);
+                continue;
+            }
+
+            let mut emu = Emulator::default();
+            emu.rewind.config.enabled = false;
+            emu.load_nes_file(&path).unwrap();
+            let known_failure = KNOWN_FAILURES
+                .iter()
+                .find(|(known, _)| *known == name)
+                .map(|(_, reason)| reason);
+            let failure = match runner(&mut emu) {
+                Ok(result) if result.status == TestRomStatus::Passed => {
+                    println!("PASS {name}");
+                    if known_failure.is_some() {
+                        println!("  it's in KNOWN_FAILURES but passes now");
+                    }
+                    passed += 1;
+                    continue;
+                }
+                Ok(result) => format!("{:?}\n{}", result.status, result.text),
+                Err(err) => format!("CPU halted: {err}"),
+            };
+            match known_failure {
+                Some(reason) => {
+                    println!("KNOWN FAIL {name} ({reason}) {failure}");
+                    known_failed += 1;
+                }
+                None => {
+                    println!("FAIL {name} {failure}");
+                    failed.push(name);
+                }
+            }
+        }
+    }
+
+    println!(
+        "{passed}/{} test ROMs passed, {known_failed} known failures",
+        passed + failed.len() + known_failed
+    );
+    assert!(missing.is_empty(), "Missing test ROMs: {missing:?}");
+    assert!(failed.is_empty(), "Failed test ROMs: {failed:?}");
+}
@@ -7,10 +7,13 @@ pub enum ActionKind {
     ControllerInput(u8, Button),
     PauseResume,
     SoftReset,
+    HardReset,
     Step,
     NextFrame,
     QuickSave,
     QuickLoad,
+    Rewind,
+    SwitchDiskSide,
 }
 
 impl ActionKind {
@@ -22,9 +25,12 @@ impl ActionKind {
             Self::NextFrame => "Step next frame".to_owned(),
             Self::PauseResume => "Pause/Resume".to_owned(),
             Self::SoftReset => "Soft reset".to_owned(),
+            Self::HardReset => "Hard reset".to_owned(),
             Self::Step => "Step Instruction".to_owned(),
             Self::QuickSave => "Quick Save".to_owned(),
             Self::QuickLoad => "Quick Load".to_owned(),
+            Self::Rewind => "Rewind (hold)".to_owned(),
+            Self::SwitchDiskSide => "Switch disk side".to_owned(),
         }
     }
 }
@@ -45,9 +51,12 @@ pub static DEFAULT_ACTION_MAP: LazyLock<ActionMapType> = LazyLock::new(|| {
     let mapping = [
         (PauseResume, F4),
         (SoftReset, F5),
+        (HardReset, F7),
         (Step, OpenBracket),
         (QuickSave, W),
         (QuickLoad, O),
+        (Rewind, Backspace),
+        (SwitchDiskSide, F6),
         (NextFrame, CloseBracket),
         (ControllerInput(0, Button::UP), I),
         (ControllerInput(0, Button::DOWN), K),
@@ -1,7 +1,14 @@
 use std::collections::HashSet;
 
+use umesen_core::movie::{Movie, MovieMode};
+
 use crate::{
-    ActionKind, DEFAULT_ACTION_MAP, Preferences, audio::setup_audio_stream, ui_window::UiWindowKind,
+    ActionKind, DEFAULT_ACTION_MAP, Preferences,
+    audio::setup_audio_stream,
+    battery_save::BatterySave,
+    save_slots::{SLOT_COUNT, THUMBNAIL_SIZE},
+    slot_texture_name,
+    ui_window::UiWindowKind,
 };
 
 #[derive(serde::Deserialize, serde::Serialize, Default)]
@@ -24,6 +31,8 @@ impl App {
             .storage
             .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
             .unwrap_or_default();
+        app.state.emu.set_region_override(app.preferences.region);
+        app.state.emu.power_on = app.preferences.power_on.clone();
 
         if !app.recent_file_paths.is_empty() {
             let path = app.recent_file_paths.remove(0);
@@ -45,7 +54,26 @@ impl App {
 
     fn load_nes_rom(&mut self, path: std::path::PathBuf) {
         log::trace!("Loading {path:?}");
-        if let Err(err) = self.state.emu.load_nes_file(&path) {
+        self.state.battery_save.flush(&self.state.emu);
+        self.state.disk_save.flush(&self.state.emu);
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        let extension = extension.map(str::to_ascii_lowercase).unwrap_or_default();
+        let is_disk = extension == "fds";
+        let is_nsf = extension == "nsf" || extension == "nsfe";
+        let result = match (is_disk, &self.preferences.fds_bios_path) {
+            _ if is_nsf => self.state.emu.load_nsf_file(&path),
+            (false, _) => self.state.emu.load_nes_file(&path),
+            (true, Some(bios_path)) => self.state.emu.load_fds_file(&path, bios_path),
+            (true, None) => {
+                self.ui_windows.insert(UiWindowKind::Popup {
+                    heading: "FDS BIOS not set!".to_string(),
+                    message: "Select the FDS BIOS ROM in Preferences > Misc to play disk images"
+                        .to_string(),
+                });
+                return;
+            }
+        };
+        if let Err(err) = result {
             self.ui_windows.insert(UiWindowKind::Popup {
                 heading: "Failed to load NES ROM!".to_string(),
                 message: format!("{err}"),
@@ -56,6 +84,14 @@ impl App {
                 "Loaded cartridge with header: {:?}",
                 self.state.emu.cartridge().unwrap().header()
             );
+            let sav_path = BatterySave::sav_path(&path, self.preferences.saves_dir.as_deref());
+            self.state.battery_save.load(&mut self.state.emu, sav_path);
+            self.state.disk_save.load(&mut self.state.emu, &path);
+            self.state.load_save_slots();
+            if is_nsf {
+                self.ui_windows.insert(UiWindowKind::NsfPlayer);
+            }
+
             // Make sure added path is on top
             self.recent_file_paths.retain(|x| *x != path);
             self.recent_file_paths.insert(0, path);
@@ -68,7 +104,7 @@ impl App {
         ui.menu_button("File", |ui| {
             if ui.button("Open ROM...").clicked() {
                 if let Some(path) = rfd::FileDialog::new()
-                    .add_filter("NES ROM", &["nes"])
+                    .add_filter("NES ROM", &["nes", "fds", "nsf", "nsfe"])
                     .pick_file()
                 {
                     self.load_nes_rom(path);
@@ -101,6 +137,7 @@ impl App {
                 PpuState,
                 Stats,
                 CatridgeInfo,
+                NsfPlayer,
             ] {
                 let mut open = self.ui_windows.contains(&kind);
                 let text = format!("{}...", kind.title());
@@ -116,22 +153,148 @@ impl App {
 
         ui.menu_button("Emulation", |ui| {
             use ActionKind::*;
-            self.show_action_list(ui, &[PauseResume, SoftReset, QuickSave, QuickLoad]);
+            self.show_action_list(
+                ui,
+                &[
+                    PauseResume,
+                    SoftReset,
+                    HardReset,
+                    QuickSave,
+                    QuickLoad,
+                    Rewind,
+                ],
+            );
+            if self
+                .state
+                .emu
+                .cartridge()
+                .is_some_and(|c| c.disk_side_count() > 0)
+            {
+                self.show_action_list(ui, &[SwitchDiskSide]);
+            }
 
             ui.menu_button("Quick Save Slot", |ui| {
-                for i in 0..9 {
-                    let mut text = egui::RichText::new(format!("Slot {i}"));
+                for i in 0..SLOT_COUNT {
+                    let slot = self.state.save_slots.get(i);
+                    let age = slot.map(|s| s.age_text()).unwrap_or("empty".to_owned());
+                    let mut text = egui::RichText::new(format!("Slot {i} ({age})"));
                     if i == self.state.selected_quick_save {
                         text = text.underline();
                     }
-                    if ui.button(text).clicked() {
+
+                    let button = if slot.is_some() {
+                        let texture = self
+                            .state
+                            .texture_map
+                            .get(slot_texture_name(i), THUMBNAIL_SIZE);
+                        egui::Button::image_and_text(texture.image(ui), text)
+                    } else {
+                        egui::Button::new(text)
+                    };
+                    if ui.add(button).clicked() {
                         self.state.selected_quick_save = i;
                     }
                 }
             });
+
+            ui.menu_button("Movie", |ui| self.show_movie_menu(ui));
         });
     }
 
+    fn show_movie_menu(&mut self, ui: &mut egui::Ui) {
+        if let Some(active) = self.state.emu.movie() {
+            let mode = match active.mode {
+                MovieMode::Recording => "Recording",
+                MovieMode::Playing => "Playing",
+            };
+            ui.label(format!("{mode} frame {}", active.frame));
+            if ui.button("Stop").clicked() {
+                self.stop_movie();
+                ui.close();
+            }
+            return;
+        }
+
+        if ui.button("Record from power on").clicked() {
+            if let Some(path) = self.recent_file_paths.first().cloned() {
+                self.load_nes_rom(path);
+            }
+            self.state.emu.record_movie(false);
+            ui.close();
+        }
+        if ui.button("Record from current state").clicked() {
+            self.state.emu.record_movie(true);
+            ui.close();
+        }
+        if ui.button("Play...").clicked() {
+            if let Some(path) = rfd::FileDialog::new()
+                .add_filter("FCEUX movie", &["fm2"])
+                .pick_file()
+            {
+                self.play_movie(path);
+            }
+            ui.close();
+        }
+    }
+
+    fn play_movie(&mut self, path: std::path::PathBuf) {
+        let movie = match Movie::load_fm2_file(&path) {
+            Ok(movie) => movie,
+            Err(err) => {
+                self.ui_windows.insert(UiWindowKind::Popup {
+                    heading: "Failed to load movie!".to_string(),
+                    message: format!("{err}"),
+                });
+                return;
+            }
+        };
+
+        // Movies without a save state start from power on
+        if movie.start_state.is_none()
+            && let Some(rom_path) = self.recent_file_paths.first().cloned()
+        {
+            self.load_nes_rom(rom_path);
+        }
+        if let Err(err) = self.state.emu.play_movie(movie) {
+            self.ui_windows.insert(UiWindowKind::Popup {
+                heading: "Failed to play movie!".to_string(),
+                message: format!("{err}"),
+            });
+        }
+        self.state.emu.running = true;
+    }
+
+    /// Stop the current movie, asking where to save it if it was being recorded
+    fn stop_movie(&mut self) {
+        let recording = self
+            .state
+            .emu
+            .movie()
+            .is_some_and(|active| active.mode == MovieMode::Recording);
+        let Some(mut movie) = self.state.emu.stop_movie() else {
+            return;
+        };
+        if !recording {
+            return;
+        }
+
+        let rom_name = self.recent_file_paths.first().and_then(|p| p.file_stem());
+        movie.rom_filename = rom_name
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("FCEUX movie", &["fm2"])
+            .set_file_name(format!("{}.fm2", movie.rom_filename))
+            .save_file()
+            && let Err(err) = movie.save_fm2_file(&path)
+        {
+            self.ui_windows.insert(UiWindowKind::Popup {
+                heading: "Failed to save movie!".to_string(),
+                message: format!("{err}"),
+            });
+        }
+    }
+
     fn show_action_list(&mut self, ui: &mut egui::Ui, list: &[ActionKind]) {
         for action in list.iter() {
             let binding = self.preferences.key_action_map.bindings_map.get(action);
@@ -152,6 +315,8 @@ impl App {
                 let controller = self.state.emu.controller(number);
                 let key_down = i.key_down(shortcut.logical_key);
                 controller.set_button(button, key_down, self.preferences.allow_illegal_press);
+            } else if action == ActionKind::Rewind {
+                self.state.rewinding = i.key_down(shortcut.logical_key);
             } else if i.consume_shortcut(&shortcut) {
                 self.state.do_action(action);
             }
@@ -169,6 +334,8 @@ impl App {
 impl eframe::App for App {
     /// Called by the frame work to save state before shutdown.
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.state.battery_save.flush(&self.state.emu);
+        self.state.disk_save.flush(&self.state.emu);
         // Remove all popups from being saved
         self.ui_windows
             .retain(|kind| !matches!(kind, UiWindowKind::Popup { .. }));
@@ -179,7 +346,19 @@ impl eframe::App for App {
         ctx.input_mut(|i| self.check_input(i));
 
         self.state.emu.ppu().config = self.preferences.ppu.clone();
-        self.state.emu.apu().config = self.preferences.apu.clone();
+        let mut apu_config = self.preferences.apu.clone();
+        if self
+            .state
+            .emu
+            .cartridge()
+            .is_some_and(|c| c.nsf().is_some())
+        {
+            self.state.nsf_mutes.apply(&mut apu_config);
+        }
+        self.state.emu.apu().config = apu_config;
+        self.state.emu.rewind.config = self.preferences.rewind.clone();
+        self.state.emu.set_region_override(self.preferences.region);
+        self.state.emu.power_on = self.preferences.power_on.clone();
 
         self.state.update_emulation(ctx);
     }
@@ -0,0 +1,106 @@
+use std::path::{Path, PathBuf};
+
+pub const FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Keeps the battery backed ram of the cartridge in sync with a .sav file
+pub struct BatterySave {
+    path: Option<PathBuf>,
+    /// Data last read from or written to the file so it only gets written when changed
+    last_data: Vec<u8>,
+    last_flush_time: std::time::Instant,
+}
+
+impl Default for BatterySave {
+    fn default() -> Self {
+        Self {
+            path: None,
+            last_data: Vec::new(),
+            last_flush_time: std::time::Instant::now(),
+        }
+    }
+}
+
+impl BatterySave {
+    /// Get the path of the .sav file for a ROM, placed next to the ROM if saves_dir is None
+    pub fn sav_path(rom_path: &Path, saves_dir: Option<&Path>) -> PathBuf {
+        match (saves_dir, rom_path.file_stem()) {
+            // Not with_extension since it would replace anything after a dot in the name
+            (Some(dir), Some(name)) => dir.join(format!("{}.sav", name.to_string_lossy())),
+            _ => rom_path.with_extension("sav"),
+        }
+    }
+
+    /// Load the .sav file into the cartridge if the cartridge has a battery
+    pub fn load(&mut self, emu: &mut umesen_core::Emulator, path: PathBuf) {
+        self.path = None;
+        let Some(cartridge) = emu.cartridge_mut() else {
+            return;
+        };
+        let Some(data) = cartridge.battery_data() else {
+            return;
+        };
+
+        self.last_data = data;
+        if path.exists() {
+            match std::fs::read(&path) {
+                Ok(data) => {
+                    log::info!("Loaded battery save {path:?}");
+                    cartridge.load_battery_data(&data);
+                    self.last_data = data;
+                }
+                Err(err) => log::error!("Failed to read battery save {path:?}: {err}"),
+            }
+        }
+        self.path = Some(path);
+        self.last_flush_time = std::time::Instant::now();
+    }
+
+    /// Write the battery ram to the .sav file if it changed
+    pub fn flush(&mut self, emu: &umesen_core::Emulator) {
+        self.last_flush_time = std::time::Instant::now();
+        let Some(path) = self.path.as_ref() else {
+            return;
+        };
+        let Some(data) = emu.cartridge().and_then(|c| c.battery_data()) else {
+            return;
+        };
+
+        if data != self.last_data {
+            let result = path
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| std::fs::write(path, &data));
+            match result {
+                Ok(_) => {
+                    log::trace!("Wrote battery save {path:?}");
+                    self.last_data = data;
+                }
+                Err(err) => log::error!("Failed to write battery save {path:?}: {err}"),
+            }
+        }
+    }
+
+    pub fn flush_periodically(&mut self, emu: &umesen_core::Emulator) {
+        if self.last_flush_time.elapsed() >= FLUSH_INTERVAL {
+            self.flush(emu);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sav_path() {
+        let saves_dir = Path::new("saves");
+        assert_eq!(
+            BatterySave::sav_path(Path::new("roms/Super Mario Bros. 3.nes"), Some(saves_dir)),
+            Path::new("saves/Super Mario Bros. 3.sav")
+        );
+        assert_eq!(
+            BatterySave::sav_path(Path::new("roms/Super Mario Bros. 2.nes"), None),
+            Path::new("roms/Super Mario Bros. 2.sav")
+        );
+    }
+}
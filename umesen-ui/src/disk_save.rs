@@ -0,0 +1,102 @@
+use std::path::{Path, PathBuf};
+
+use umesen_core::ips;
+
+use crate::battery_save::FLUSH_INTERVAL;
+
+/// Keeps the changes made to a Famicom Disk System disk in an IPS patch next to the image,
+/// so the image itself is never modified
+pub struct DiskSave {
+    path: Option<PathBuf>,
+    /// Image file as it was read, the patch is made against this
+    original: Vec<u8>,
+    /// Disk image last read from or written to the patch so it only gets written when changed
+    last_image: Vec<u8>,
+    last_flush_time: std::time::Instant,
+}
+
+impl Default for DiskSave {
+    fn default() -> Self {
+        Self {
+            path: None,
+            original: Vec::new(),
+            last_image: Vec::new(),
+            last_flush_time: std::time::Instant::now(),
+        }
+    }
+}
+
+impl DiskSave {
+    /// Apply the patch next to the image to the disk if the cartridge is a disk
+    pub fn load(&mut self, emu: &mut umesen_core::Emulator, image_path: &Path) {
+        self.path = None;
+        let Some(cartridge) = emu.cartridge_mut() else {
+            return;
+        };
+        let Some(image) = cartridge.disk_image() else {
+            return;
+        };
+
+        self.original = match std::fs::read(image_path) {
+            Ok(original) => original,
+            Err(err) => {
+                log::error!("Failed to read disk image {image_path:?}: {err}");
+                return;
+            }
+        };
+        self.last_image = image;
+
+        let path = image_path.with_extension("ips");
+        if path.exists() {
+            let result = std::fs::read(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|patch| {
+                    ips::apply_patch(&self.original, &patch).map_err(|err| err.to_string())
+                })
+                .and_then(|image| {
+                    cartridge
+                        .load_disk_image(&image)
+                        .map_err(|err| err.to_string())
+                });
+            match result {
+                Ok(_) => {
+                    log::info!("Loaded disk changes {path:?}");
+                    self.last_image = cartridge.disk_image().unwrap_or_default();
+                }
+                Err(err) => log::error!("Failed to load disk changes {path:?}: {err}"),
+            }
+        }
+        self.path = Some(path);
+        self.last_flush_time = std::time::Instant::now();
+    }
+
+    /// Write the patch if the disk changed
+    pub fn flush(&mut self, emu: &umesen_core::Emulator) {
+        self.last_flush_time = std::time::Instant::now();
+        let Some(path) = self.path.as_ref() else {
+            return;
+        };
+        let Some(image) = emu.cartridge().and_then(|c| c.disk_image()) else {
+            return;
+        };
+
+        if image != self.last_image {
+            let result = ips::create_patch(&self.original, &image)
+                .map_err(|err| err.to_string())
+                .and_then(|patch| std::fs::write(path, patch).map_err(|err| err.to_string()));
+            match result {
+                Ok(_) => {
+                    log::trace!("Wrote disk changes {path:?}");
+                    self.last_image = image;
+                }
+                Err(err) => log::error!("Failed to write disk changes {path:?}: {err}"),
+            }
+        }
+    }
+
+    pub fn flush_periodically(&mut self, emu: &umesen_core::Emulator) {
+        if self.last_flush_time.elapsed() >= FLUSH_INTERVAL {
+            self.flush(emu);
+        }
+    }
+}
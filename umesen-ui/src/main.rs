@@ -3,7 +3,10 @@
 mod action;
 mod app;
 mod audio;
+mod battery_save;
+mod disk_save;
 mod egui_util;
+mod save_slots;
 mod state;
 mod texture;
 mod ui_window;
@@ -13,6 +16,8 @@ pub use app::App;
 pub use state::*;
 pub use texture::Texture;
 
+pub const APP_NAME: &str = "Umesen";
+
 #[cfg(not(target_arch = "wasm32"))]
 fn main() -> eframe::Result {
     env_logger::builder()
@@ -22,7 +27,7 @@ fn main() -> eframe::Result {
         .init();
 
     eframe::run_native(
-        "Umesen",
+        APP_NAME,
         eframe::NativeOptions::default(),
         Box::new(|cc| Ok(Box::new(App::new(cc)))),
     )
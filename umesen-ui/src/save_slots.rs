@@ -0,0 +1,105 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use umesen_core::ppu::{HEIGHT, WIDTH};
+
+pub const SLOT_COUNT: u8 = 9;
+const THUMBNAIL_SCALE: usize = 4;
+pub const THUMBNAIL_SIZE: [usize; 2] = [WIDTH / THUMBNAIL_SCALE, HEIGHT / THUMBNAIL_SCALE];
+
+/// A quick save slot that gets stored on disk
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct SaveSlot {
+    /// Seconds since the unix epoch of when the slot was saved
+    pub timestamp: u64,
+    /// Downscaled RGB screen pixels at the time of saving
+    pub thumbnail: Vec<[u8; 3]>,
+    pub state: Vec<u8>,
+}
+
+impl SaveSlot {
+    pub fn new(emu: &mut umesen_core::Emulator) -> Self {
+        let pixels = &emu.ppu().screen_pixels;
+        let thumbnail = (0..THUMBNAIL_SIZE[1])
+            .flat_map(|y| (0..THUMBNAIL_SIZE[0]).map(move |x| (x, y)))
+            .map(|(x, y)| *pixels[(y * WIDTH + x) * THUMBNAIL_SCALE])
+            .collect();
+
+        Self {
+            timestamp: unix_time_now(),
+            thumbnail,
+            state: emu.save_state(),
+        }
+    }
+
+    /// Text describing how long ago the slot was saved
+    pub fn age_text(&self) -> String {
+        let seconds = unix_time_now().saturating_sub(self.timestamp);
+        match seconds {
+            0..60 => "just now".to_owned(),
+            60..3600 => format!("{} min ago", seconds / 60),
+            3600..86400 => format!("{} hours ago", seconds / 3600),
+            _ => format!("{} days ago", seconds / 86400),
+        }
+    }
+}
+
+/// Quick save slots for the currently loaded ROM
+/// These are stored in the app storage directory inside a folder named by the ROM CRC32
+#[derive(Default)]
+pub struct SaveSlots {
+    dir: Option<PathBuf>,
+    slots: HashMap<u8, SaveSlot>,
+}
+
+impl SaveSlots {
+    /// Replace the slots with the ones saved on disk for a ROM
+    pub fn load_for_rom(&mut self, rom_crc32: u32) {
+        self.slots.clear();
+        self.dir = eframe::storage_dir(crate::APP_NAME)
+            .map(|dir| dir.join("save_states").join(format!("{rom_crc32:08x}")));
+
+        for number in 0..SLOT_COUNT {
+            let Some(path) = self.slot_path(number).filter(|p| p.exists()) else {
+                continue;
+            };
+
+            let slot = std::fs::read(&path)
+                .map_err(bincode::Error::from)
+                .and_then(|bytes| bincode::deserialize(&bytes));
+            match slot {
+                Ok(slot) => drop(self.slots.insert(number, slot)),
+                Err(err) => log::error!("Failed to read save slot {path:?}: {err}"),
+            }
+        }
+    }
+
+    pub fn get(&self, number: u8) -> Option<&SaveSlot> {
+        self.slots.get(&number)
+    }
+
+    /// Set the slot and write it to disk
+    pub fn insert(&mut self, number: u8, slot: SaveSlot) -> std::io::Result<()> {
+        if let Some(path) = self.slot_path(number) {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let bytes = bincode::serialize(&slot).map_err(std::io::Error::other)?;
+            std::fs::write(path, bytes)?;
+        }
+        self.slots.insert(number, slot);
+        Ok(())
+    }
+
+    fn slot_path(&self, number: u8) -> Option<PathBuf> {
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(format!("slot{number}.state")))
+    }
+}
+
+fn unix_time_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
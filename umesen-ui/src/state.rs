@@ -1,12 +1,26 @@
-use crate::{ActionKind, KeyActionMap, texture::TextureMap};
+use crate::{
+    ActionKind, KeyActionMap,
+    battery_save::BatterySave,
+    disk_save::DiskSave,
+    save_slots::{SLOT_COUNT, SaveSlot, SaveSlots, THUMBNAIL_SIZE},
+    texture::TextureMap,
+};
 
 #[derive(serde::Deserialize, serde::Serialize, Default)]
 #[serde(default)]
 pub struct Preferences {
     pub key_action_map: KeyActionMap,
     pub allow_illegal_press: bool,
+    /// Directory to put battery .sav files in, otherwise they are put next to the ROM
+    pub saves_dir: Option<std::path::PathBuf>,
+    /// BIOS ROM needed to run Famicom Disk System games
+    pub fds_bios_path: Option<std::path::PathBuf>,
+    /// Region forced on every ROM, otherwise it comes from the header
+    pub region: Option<umesen_core::Region>,
+    pub power_on: umesen_core::power_on::PowerOnConfig,
     pub ppu: umesen_core::ppu::PpuConfig,
     pub apu: umesen_core::apu::ApuConfig,
+    pub rewind: umesen_core::rewind::RewindConfig,
 }
 
 #[derive(Default)]
@@ -14,18 +28,46 @@ pub struct State {
     pub emu: umesen_core::Emulator,
     pub texture_map: TextureMap,
     pub ui_render_time: f32,
-    pub save_states: std::collections::HashMap<u8, umesen_core::Cpu>,
+    pub save_slots: SaveSlots,
+    pub battery_save: BatterySave,
+    pub disk_save: DiskSave,
     pub selected_quick_save: u8,
+    /// Rewind key is held down
+    pub rewinding: bool,
+    pub nsf_mutes: crate::ui_window::nsf_player::NsfMutes,
+    /// Breakpoint that paused emulation, cleared when resuming
+    pub break_reason: Option<umesen_core::breakpoint::BreakReason>,
 }
 
 impl State {
     pub fn update_emulation(&mut self, ctx: &egui::Context) {
-        if let Err(err) = self
+        // Emulation is not run while rewinding so no audio is generated
+        if self.rewinding {
+            if self.emu.rewind_step() {
+                self.texture_map
+                    .update_ppu_texture(&self.emu.ppu().screen_pixels);
+            }
+            ctx.request_repaint();
+            return;
+        }
+
+        match self
             .emu
             .update(|pixels| self.texture_map.update_ppu_texture(pixels))
         {
-            log::warn!("CPU halted: {err}");
-            self.emu.running = false;
+            Ok(Some(reason)) => {
+                self.break_reason = Some(reason);
+                self.texture_map
+                    .update_ppu_texture(&self.emu.ppu().screen_pixels);
+            }
+            Ok(None) => (),
+            Err(err) => {
+                log::warn!("CPU halted: {err}");
+                self.emu.running = false;
+            }
+        }
+        if self.emu.running {
+            self.break_reason = None;
         }
 
         if self.emu.speed < 1. {
@@ -35,31 +77,75 @@ impl State {
         if self.emu.running {
             ctx.request_repaint();
         }
+        self.battery_save.flush_periodically(&self.emu);
+        self.disk_save.flush_periodically(&self.emu);
+    }
+
+    /// Load the save slots for the currently loaded ROM
+    pub fn load_save_slots(&mut self) {
+        if let Some(cartridge) = self.emu.cartridge() {
+            self.save_slots.load_for_rom(cartridge.rom_crc32());
+            for number in 0..SLOT_COUNT {
+                self.update_slot_thumbnail(number);
+            }
+        }
+    }
+
+    fn update_slot_thumbnail(&mut self, number: u8) {
+        if let Some(slot) = self.save_slots.get(number) {
+            let texture = self
+                .texture_map
+                .get(slot_texture_name(number), THUMBNAIL_SIZE);
+            texture.update_pixels(
+                slot.thumbnail
+                    .iter()
+                    .map(|c| egui::Color32::from_rgb(c[0], c[1], c[2]))
+                    .collect(),
+            );
+        }
     }
 
     pub fn do_action(&mut self, action: ActionKind) {
         match action {
             ActionKind::SoftReset => {
-                self.emu.cpu.reset();
+                self.emu.soft_reset();
+                self.emu.running = true;
+            }
+            ActionKind::HardReset => {
+                self.emu.hard_reset();
                 self.emu.running = true;
             }
             ActionKind::PauseResume => self.emu.running = !self.emu.running,
             ActionKind::Step => {
                 self.emu.running = false;
-                self.emu.cpu.execute_next().ok();
+                self.break_reason = None;
+                self.emu.step().ok();
             }
             ActionKind::QuickSave => {
-                // self.save_states
-                //     .insert(self.selected_quick_save, self.emu.cpu.clone());
+                let slot = SaveSlot::new(&mut self.emu);
+                if let Err(err) = self.save_slots.insert(self.selected_quick_save, slot) {
+                    log::error!("Failed to write save slot: {err}");
+                }
+                self.update_slot_thumbnail(self.selected_quick_save);
             }
             ActionKind::QuickLoad => {
-                if let Some(cpu) = self.save_states.get(&self.selected_quick_save) {
-                    // self.emu.cpu = cpu.clone();
+                if let Some(slot) = self.save_slots.get(self.selected_quick_save)
+                    && let Err(err) = self.emu.load_state(&slot.state)
+                {
+                    log::error!("Failed to load save state: {err}");
                 }
             }
             ActionKind::NextFrame => {
                 self.emu.running = false;
-                self.emu.next_frame().ok();
+                self.break_reason = self.emu.next_frame().ok().flatten();
+            }
+            ActionKind::Rewind => {
+                self.emu.rewind_step();
+            }
+            ActionKind::SwitchDiskSide => {
+                if let Some(cartridge) = self.emu.cartridge_mut() {
+                    cartridge.switch_disk_side();
+                }
             }
             ActionKind::ControllerInput(..) => unreachable!(),
         }
@@ -67,3 +153,7 @@ impl State {
             .update_ppu_texture(&self.emu.ppu().screen_pixels);
     }
 }
+
+pub fn slot_texture_name(number: u8) -> String {
+    format!("save_slot{number}")
+}
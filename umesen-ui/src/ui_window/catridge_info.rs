@@ -4,7 +4,18 @@ pub fn show(ui: &mut egui::Ui, state: &mut crate::State) {
         return;
     };
 
+    if let Some(original) = catridge.original_header() {
+        ui.colored_label(ui.visuals().warn_fg_color, "Header corrected from database")
+            .on_hover_text(format!("Header in ROM file: {original:#?}"));
+    }
+    ui.label(format!("ROM CRC32: {:08X}", catridge.rom_crc32()));
     ui.label(format!("Mapper ID: {:?}", catridge.header().mapper_id));
+    ui.label(format!(
+        "Submapper ID: {:?}",
+        catridge.header().submapper_id
+    ));
+    ui.label(format!("Battery: {}", catridge.header().has_battery));
+    ui.label(format!("Region: {}", catridge.header().region.name()));
     ui.label(format!(
         "PRG ROM size: {:?}",
         catridge.header().prg_rom_size
@@ -23,6 +34,12 @@ pub fn show(ui: &mut egui::Ui, state: &mut crate::State) {
         catridge.header().chr_mem_size
     ));
     ui.label(format!("Mirroring: {:?}", catridge.mirroring()));
+    if catridge.disk_side_count() > 0 {
+        let side = catridge.disk_side().map_or("Ejected".to_owned(), |side| {
+            format!("{} of {}", side + 1, catridge.disk_side_count())
+        });
+        ui.label(format!("Disk side: {side}"));
+    }
 
     ui.label("Mapper state: ");
     egui::Frame::canvas(ui.style())
@@ -1,3 +1,5 @@
+use umesen_core::breakpoint::{Breakpoint, BreakpointKind, Condition};
+
 use crate::ActionKind;
 
 pub fn show(ui: &mut egui::Ui, state: &mut crate::State) {
@@ -41,8 +43,25 @@ pub fn show(ui: &mut egui::Ui, state: &mut crate::State) {
         }
     });
 
+    if let Some(reason) = state.break_reason {
+        ui.label(
+            egui::RichText::new(format!(
+                "Hit breakpoint #{}: {} ${:04x} = ${:02x}",
+                reason.index + 1,
+                reason.kind.name(),
+                reason.address,
+                reason.value
+            ))
+            .color(egui::Color32::LIGHT_RED),
+        );
+    }
+
     ui.separator();
 
+    egui::CollapsingHeader::new("Breakpoints")
+        .default_open(true)
+        .show_unindented(ui, |ui| show_breakpoints(ui, state));
+
     let mut disassembler = umesen_core::cpu::Disassembler::new(&state.emu.cpu);
 
     let frame = egui::Frame::canvas(ui.style()).inner_margin(6.0);
@@ -58,3 +77,79 @@ pub fn show(ui: &mut egui::Ui, state: &mut crate::State) {
             });
         });
 }
+
+fn show_breakpoints(ui: &mut egui::Ui, state: &mut crate::State) {
+    let pc = state.emu.cpu.pc;
+    let list = &mut state.emu.breakpoints_mut().list;
+    let condition_id = |i: usize| egui::Id::new(("breakpoint condition", i));
+    let mut removed = None;
+    for (i, breakpoint) in list.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut breakpoint.enabled, "");
+            egui::ComboBox::from_id_salt(("breakpoint kind", i))
+                .width(90.)
+                .selected_text(breakpoint.kind.name())
+                .show_ui(ui, |ui| {
+                    for kind in BreakpointKind::ALL {
+                        ui.selectable_value(&mut breakpoint.kind, kind, kind.name());
+                    }
+                });
+            ui.add(egui::DragValue::new(&mut breakpoint.start).hexadecimal(4, false, false));
+            ui.label("-");
+            if ui
+                .add(egui::DragValue::new(&mut breakpoint.end).hexadecimal(4, false, false))
+                .changed()
+                || breakpoint.end < breakpoint.start
+            {
+                breakpoint.end = breakpoint.end.max(breakpoint.start);
+            }
+
+            // Text is kept while it doesn't parse so it can still be edited
+            let mut text = ui
+                .data(|d| d.get_temp::<String>(condition_id(i)))
+                .unwrap_or_else(|| {
+                    breakpoint
+                        .condition
+                        .as_ref()
+                        .map_or(String::new(), |c| c.source().to_string())
+                });
+            let result = (!text.trim().is_empty()).then(|| Condition::parse(&text));
+            let mut edit = egui::TextEdit::singleline(&mut text)
+                .hint_text("Condition")
+                .desired_width(140.);
+            if let Some(Err(_)) = &result {
+                edit = edit.text_color(egui::Color32::LIGHT_RED);
+            }
+            let mut response = ui.add(edit);
+            if let Some(Err(err)) = &result {
+                response = response.on_hover_text(err.to_string());
+            }
+            if response.changed() {
+                match (!text.trim().is_empty()).then(|| Condition::parse(&text)) {
+                    None => breakpoint.condition = None,
+                    Some(Ok(condition)) => breakpoint.condition = Some(condition),
+                    Some(Err(_)) => (),
+                }
+                ui.data_mut(|d| d.insert_temp(condition_id(i), text));
+            }
+
+            ui.label(format!("{} hits", breakpoint.hit_count));
+            if ui.button("🗑").clicked() {
+                removed = Some(i);
+            }
+        });
+    }
+
+    if let Some(i) = removed {
+        // Condition text is stored by index so the ones after it would be wrong
+        ui.data_mut(|d| {
+            for j in i..list.len() {
+                d.remove::<String>(condition_id(j));
+            }
+        });
+        list.remove(i);
+    }
+    if ui.button("Add breakpoint").clicked() {
+        list.push(Breakpoint::new(BreakpointKind::Execute, pc, pc));
+    }
+}
@@ -1,6 +1,7 @@
 mod catridge_info;
 mod debugger;
 pub mod hex_viewer;
+pub mod nsf_player;
 pub mod ppu_memory;
 mod ppu_state;
 mod preferences;
@@ -15,6 +16,7 @@ pub enum UiWindowKind {
     Stats,
     Preferences,
     CatridgeInfo,
+    NsfPlayer,
     Popup { heading: String, message: String },
 }
 
@@ -26,6 +28,7 @@ impl UiWindowKind {
             Self::Popup { .. } => "Error",
             Self::Stats => "Stats",
             Self::CatridgeInfo => "Catridge Info",
+            Self::NsfPlayer => "NSF Player",
             Self::PpuMemory => "Ppu Memory",
             Self::PpuState => "Ppu State",
             Self::Preferences => "Preferences",
@@ -64,6 +67,7 @@ impl UiWindowKind {
                 Self::PpuState => ppu_state::show(ui, state),
                 Self::Preferences => preferences::show(ui, preferences),
                 Self::CatridgeInfo => catridge_info::show(ui, state),
+                Self::NsfPlayer => nsf_player::show(ui, state),
                 Self::Popup { .. } => unreachable!(),
             });
 
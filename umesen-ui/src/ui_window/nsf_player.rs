@@ -0,0 +1,154 @@
+use umesen_core::{apu::ApuConfig, cartridge::NsfChips};
+
+/// Length of the seek bar for tracks without a length in the file
+const DEFAULT_TRACK_SECONDS: f32 = 180.;
+
+type ChannelVolume = fn(&mut ApuConfig) -> &mut f32;
+
+/// Channels that can be muted, with the expansion chip they need
+const CHANNELS: [(Option<NsfChips>, &str, ChannelVolume); 11] = [
+    (None, "Pulse 0", |c| &mut c.pulse_0_volume),
+    (None, "Pulse 1", |c| &mut c.pulse_1_volume),
+    (None, "Triangle", |c| &mut c.triangle_volume),
+    (None, "Noise", |c| &mut c.noise_volume),
+    (None, "DMC", |c| &mut c.dmc_volume),
+    (Some(NsfChips::VRC6), "VRC6", |c| &mut c.vrc6_volume),
+    (Some(NsfChips::VRC7), "VRC7", |c| &mut c.vrc7_volume),
+    (Some(NsfChips::FDS), "FDS", |c| &mut c.fds_volume),
+    (Some(NsfChips::MMC5), "MMC5", |c| &mut c.mmc5_volume),
+    (Some(NsfChips::NAMCO163), "Namco 163", |c| {
+        &mut c.namco163_volume
+    }),
+    (Some(NsfChips::SUNSOFT5B), "Sunsoft 5B", |c| {
+        &mut c.sunsoft5b_volume
+    }),
+];
+
+/// Channels muted in the player, kept apart from the volume preferences so they don't
+/// change the volumes or carry over to games
+#[derive(Default)]
+pub struct NsfMutes([bool; CHANNELS.len()]);
+
+impl NsfMutes {
+    /// Silence the muted channels in the config given to the APU
+    pub fn apply(&self, config: &mut ApuConfig) {
+        for (muted, (_, _, volume)) in self.0.iter().zip(CHANNELS) {
+            if *muted {
+                *volume(config) = 0.;
+            }
+        }
+    }
+}
+
+pub fn show(ui: &mut egui::Ui, state: &mut crate::State) {
+    let emu = &mut state.emu;
+    let Some(cartridge) = emu.cartridge() else {
+        ui.label("No NSF loaded");
+        return;
+    };
+    let (Some(nsf), Some(current_track)) = (cartridge.nsf(), cartridge.nsf_track()) else {
+        ui.label("No NSF loaded");
+        return;
+    };
+
+    ui.heading(&nsf.title);
+    ui.label(&nsf.artist);
+    ui.label(&nsf.copyright);
+    let chips = nsf.chips;
+    let chip_names = chips.iter_names().map(|(name, _)| name).collect::<Vec<_>>();
+    ui.label(format!(
+        "Expansion chips: {}",
+        if chip_names.is_empty() {
+            "None".to_owned()
+        } else {
+            chip_names.join(", ")
+        }
+    ));
+    if chips.contains(NsfChips::VRC7) {
+        ui.colored_label(ui.visuals().warn_fg_color, "VRC7 audio is not supported");
+    }
+    ui.separator();
+
+    let mut selected_track = None;
+    egui::ScrollArea::vertical()
+        .max_height(200.)
+        .show(ui, |ui| {
+            for track in 0..nsf.track_count {
+                let mut text = format!("{}. {}", track + 1, nsf.track_label(track).unwrap_or(""));
+                if let Some(length) = nsf.track_length(track) {
+                    text += &format!(" ({})", format_time(length as f32 / 1000.));
+                }
+                if ui.selectable_label(track == current_track, text).clicked() {
+                    selected_track = Some(track);
+                }
+            }
+        });
+    ui.separator();
+
+    let track_count = nsf.track_count;
+    let length = nsf
+        .track_length(current_track)
+        .map_or(DEFAULT_TRACK_SECONDS, |length| length as f32 / 1000.);
+    let mut seek = None;
+    ui.horizontal(|ui| {
+        if ui.button("Previous").clicked() {
+            selected_track = Some(current_track.saturating_sub(1));
+        }
+        let text = if emu.running { "Pause" } else { "Play" };
+        if ui.button(text).clicked() {
+            emu.running = !emu.running;
+        }
+        if ui.button("Next").clicked() && current_track + 1 < track_count {
+            selected_track = Some(current_track + 1);
+        }
+
+        // Seeking is slow so only do it once the slider is let go
+        let id = ui.id().with("nsf seek");
+        let mut position = ui
+            .data(|d| d.get_temp::<f32>(id))
+            .unwrap_or(emu.nsf_position());
+        let response = ui.add(
+            egui::Slider::new(&mut position, 0.0..=length.max(emu.nsf_position()))
+                .show_value(false),
+        );
+        if response.dragged() {
+            ui.data_mut(|d| d.insert_temp(id, position));
+        } else if response.drag_stopped() || response.changed() {
+            ui.data_mut(|d| d.remove::<f32>(id));
+            seek = Some(position);
+        }
+        ui.label(format!(
+            "{} / {}",
+            format_time(position),
+            format_time(length)
+        ));
+    });
+
+    ui.separator();
+    ui.label("Channels");
+    ui.horizontal_wrapped(|ui| {
+        for (muted, (chip, name, _)) in state.nsf_mutes.0.iter_mut().zip(CHANNELS) {
+            if chip.is_none_or(|chip| chips.contains(chip)) {
+                let mut enabled = !*muted;
+                if ui.checkbox(&mut enabled, name).changed() {
+                    *muted = !enabled;
+                }
+            }
+        }
+    });
+
+    if let Some(track) = selected_track {
+        emu.play_nsf_track(track);
+        emu.running = true;
+    } else if let Some(seconds) = seek
+        && let Err(err) = emu.seek_nsf(seconds)
+    {
+        log::warn!("CPU halted: {err}");
+        emu.running = false;
+    }
+}
+
+fn format_time(seconds: f32) -> String {
+    let seconds = seconds as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
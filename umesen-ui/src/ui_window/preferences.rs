@@ -1,3 +1,5 @@
+use umesen_core::power_on::RamState;
+
 use crate::{ActionKind, Preferences};
 
 #[derive(Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize, Debug)]
@@ -32,6 +34,66 @@ pub fn show(ui: &mut egui::Ui, prefs: &mut Preferences) {
                 ui.label("Allow unlimited sprites").on_hover_text("Allow unlimited sprites to be rendered on the same scanline at a time instead of the usual 8");
                 ui.checkbox(&mut prefs.ppu.unlimited_sprites, "");
                 ui.end_row();
+                ui.label("Region").on_hover_text("Console timing to emulate, Auto uses the region from the NES 2.0 header");
+                egui::ComboBox::from_id_salt("region pref")
+                    .selected_text(prefs.region.map_or("Auto", |region| region.name()))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut prefs.region, None, "Auto");
+                        for region in umesen_core::Region::ALL {
+                            ui.selectable_value(&mut prefs.region, Some(region), region.name());
+                        }
+                    });
+                ui.end_row();
+                ui.label("Power on RAM").on_hover_text("What RAM and OAM contain when loading a ROM or doing a hard reset, useful for finding uninitialized memory bugs");
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("power on ram pref")
+                        .selected_text(prefs.power_on.ram_state.name())
+                        .show_ui(ui, |ui| {
+                            for state in RamState::ALL {
+                                ui.selectable_value(&mut prefs.power_on.ram_state, state, state.name());
+                            }
+                        });
+                    if prefs.power_on.ram_state == RamState::Random {
+                        ui.label("Seed");
+                        ui.add(egui::DragValue::new(&mut prefs.power_on.seed));
+                    }
+                });
+                ui.end_row();
+                ui.label("Battery saves folder").on_hover_text("Folder to store battery backed .sav files, otherwise they are stored next to the ROM");
+                ui.horizontal(|ui| {
+                    let text = prefs.saves_dir.as_ref().map(|dir| dir.to_string_lossy()).unwrap_or("Next to ROM".into());
+                    if ui.button(text).clicked()
+                        && let Some(dir) = rfd::FileDialog::new().pick_folder()
+                    {
+                        prefs.saves_dir = Some(dir);
+                    }
+                    if prefs.saves_dir.is_some() && ui.button("Clear").clicked() {
+                        prefs.saves_dir = None;
+                    }
+                });
+                ui.end_row();
+                ui.label("FDS BIOS").on_hover_text("BIOS ROM of the Famicom Disk System, needed to run .fds disk images");
+                ui.horizontal(|ui| {
+                    let text = prefs.fds_bios_path.as_ref().map(|path| path.to_string_lossy()).unwrap_or("Not set".into());
+                    if ui.button(text).clicked()
+                        && let Some(path) = rfd::FileDialog::new().add_filter("FDS BIOS", &["rom", "bin"]).pick_file()
+                    {
+                        prefs.fds_bios_path = Some(path);
+                    }
+                    if prefs.fds_bios_path.is_some() && ui.button("Clear").clicked() {
+                        prefs.fds_bios_path = None;
+                    }
+                });
+                ui.end_row();
+                ui.label("Rewind");
+                ui.checkbox(&mut prefs.rewind.enabled, "");
+                ui.end_row();
+                ui.label("Rewind memory (MB)").on_hover_text("Maximum memory used to store rewind snapshots, more memory allows rewinding further back");
+                ui.add(egui::Slider::new(&mut prefs.rewind.memory_budget_mb, 8..=1024).logarithmic(true));
+                ui.end_row();
+                ui.label("Frames per rewind snapshot").on_hover_text("Lower values rewind more smoothly but use more memory");
+                ui.add(egui::Slider::new(&mut prefs.rewind.frames_per_snapshot, 1..=30));
+                ui.end_row();
             });
         }
         Tab::Audio => {
@@ -54,6 +116,27 @@ pub fn show(ui: &mut egui::Ui, prefs: &mut Preferences) {
                 ui.label("DMC volume");
                 ui.add(egui::Slider::new(&mut prefs.apu.dmc_volume, (0.)..=1.));
                 ui.end_row();
+                ui.label("VRC6 volume");
+                ui.add(egui::Slider::new(&mut prefs.apu.vrc6_volume, (0.)..=1.));
+                ui.end_row();
+                ui.label("VRC7 volume");
+                ui.add(egui::Slider::new(&mut prefs.apu.vrc7_volume, (0.)..=1.));
+                ui.end_row();
+                ui.label("Namco 163 volume");
+                ui.add(egui::Slider::new(&mut prefs.apu.namco163_volume, (0.)..=1.));
+                ui.end_row();
+                ui.label("Sunsoft 5B volume");
+                ui.add(egui::Slider::new(
+                    &mut prefs.apu.sunsoft5b_volume,
+                    (0.)..=1.,
+                ));
+                ui.end_row();
+                ui.label("FDS volume");
+                ui.add(egui::Slider::new(&mut prefs.apu.fds_volume, (0.)..=1.));
+                ui.end_row();
+                ui.label("MMC5 volume");
+                ui.add(egui::Slider::new(&mut prefs.apu.mmc5_volume, (0.)..=1.));
+                ui.end_row();
             });
         }
         Tab::KeyBinds => {